@@ -0,0 +1,189 @@
+//! Arbitrary-precision floats for the `--bits` mode, on top of astro-float.
+//! Every value keeps the precision it was entered with, and a result takes
+//! the finer precision of its operands.
+
+use astro_float::{Consts, Radix, RoundingMode};
+use std::cell::RefCell;
+
+pub use astro_float::BigFloat;
+
+const ROUNDING: RoundingMode = RoundingMode::ToEven;
+
+thread_local! {
+    /// Digits of pi, e and the logarithms computed so far, reused by later
+    /// operations at the same or a lower precision.
+    static CONSTS: RefCell<Consts> = RefCell::new(Consts::new().expect("cannot allocate the constants cache"));
+}
+
+fn with_consts<T>(fun: impl FnOnce(&mut Consts) -> T) -> T {
+    CONSTS.with(|consts| fun(&mut consts.borrow_mut()))
+}
+
+/// The precision of `x` in bits. Infinities and NaN have none of their own
+/// and count as a single 64-bit word.
+pub fn precision(x: &BigFloat) -> usize {
+    x.mantissa_max_bit_len().unwrap_or(64)
+}
+
+/// Parses the decimal `text` of a number literal straight into `bits` bits,
+/// so `0.1` is as close as that precision allows rather than the nearest
+/// `f64`. Spellings only `f64` knows, like `inf`, go through `fallback`.
+pub fn parse(text: &str, bits: usize, fallback: f64) -> BigFloat {
+    let parsed = with_consts(|cc| BigFloat::parse(text, Radix::Dec, bits, ROUNDING, cc));
+    if parsed.is_nan() { BigFloat::from_f64(fallback, bits) } else { parsed }
+}
+
+pub fn from_f64(value: f64, bits: usize) -> BigFloat {
+    BigFloat::from_f64(value, bits)
+}
+
+/// The nearest `f64`, infinite when `x` is out of its range.
+pub fn to_f64(x: &BigFloat) -> f64 {
+    match x.is_nan() {
+        true => f64::NAN,
+        false => with_consts(|cc| x.format(Radix::Dec, ROUNDING, cc))
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .unwrap_or(f64::NAN),
+    }
+}
+
+pub fn is_finite(x: &BigFloat) -> bool {
+    !x.is_nan() && !x.is_inf()
+}
+
+/// The named constants that can be computed to any precision.
+pub fn constant(name: &str, bits: usize) -> Option<BigFloat> {
+    match name {
+        "pi" => Some(with_consts(|cc| cc.pi(bits, ROUNDING))),
+        "e" => Some(with_consts(|cc| cc.e(bits, ROUNDING))),
+        "tau" => Some(mul(&constant("pi", bits)?, &from_f64(2.0, bits), bits)),
+        "phi" => {
+            let root = from_f64(5.0, bits).sqrt(bits, ROUNDING);
+            Some(div(&add(&root, &from_f64(1.0, bits), bits), &from_f64(2.0, bits), bits))
+        },
+        _ => None,
+    }
+}
+
+pub fn add(a: &BigFloat, b: &BigFloat, bits: usize) -> BigFloat {
+    a.add(b, bits, ROUNDING)
+}
+
+pub fn sub(a: &BigFloat, b: &BigFloat, bits: usize) -> BigFloat {
+    a.sub(b, bits, ROUNDING)
+}
+
+pub fn mul(a: &BigFloat, b: &BigFloat, bits: usize) -> BigFloat {
+    a.mul(b, bits, ROUNDING)
+}
+
+pub fn div(a: &BigFloat, b: &BigFloat, bits: usize) -> BigFloat {
+    a.div(b, bits, ROUNDING)
+}
+
+pub fn pow(a: &BigFloat, b: &BigFloat, bits: usize) -> BigFloat {
+    with_consts(|cc| a.pow(b, bits, ROUNDING, cc))
+}
+
+pub fn sqrt(x: &BigFloat, bits: usize) -> BigFloat {
+    x.sqrt(bits, ROUNDING)
+}
+
+pub fn exp(x: &BigFloat, bits: usize) -> BigFloat {
+    with_consts(|cc| x.exp(bits, ROUNDING, cc))
+}
+
+pub fn ln(x: &BigFloat, bits: usize) -> BigFloat {
+    with_consts(|cc| x.ln(bits, ROUNDING, cc))
+}
+
+pub fn sin(x: &BigFloat, bits: usize) -> BigFloat {
+    with_consts(|cc| x.sin(bits, ROUNDING, cc))
+}
+
+pub fn cos(x: &BigFloat, bits: usize) -> BigFloat {
+    with_consts(|cc| x.cos(bits, ROUNDING, cc))
+}
+
+pub fn tan(x: &BigFloat, bits: usize) -> BigFloat {
+    with_consts(|cc| x.tan(bits, ROUNDING, cc))
+}
+
+/// Rounds the decimal `digits` to `keep` of them, half up. Returns whether
+/// the carry ran off the front, which adds a digit and so one to the exponent.
+fn round_digits(digits: &mut Vec<u8>, keep: usize) -> bool {
+    if digits.len() <= keep {
+        return false;
+    }
+    let round_up = digits[keep] >= b'5';
+    digits.truncate(keep);
+    if !round_up {
+        return false;
+    }
+    for digit in digits.iter_mut().rev() {
+        if *digit == b'9' {
+            *digit = b'0';
+        } else {
+            *digit += 1;
+            return false;
+        }
+    }
+    digits.insert(0, b'1');
+    digits.pop();
+    true
+}
+
+/// Every digit of `x` in scientific notation, which parses back to the same
+/// value at the same precision.
+pub fn format_exact(x: &BigFloat) -> String {
+    with_consts(|cc| x.format(Radix::Dec, ROUNDING, cc)).unwrap_or_else(|_| "NaN".to_string())
+}
+
+/// Formats `x` in decimal like `format_number` does an `f64`: with `digits`
+/// significant digits, or with all but the last of the digits its precision
+/// carries, which is mostly rounding noise. Numbers too large or small to
+/// write out in full get an exponent.
+pub fn format(x: &BigFloat, digits: Option<usize>) -> String {
+    if x.is_nan() {
+        return "NaN".to_string();
+    }
+    if x.is_inf() {
+        return if x.is_negative() { "-inf" } else { "inf" }.to_string();
+    }
+    if x.is_zero() {
+        return "0".to_string();
+    }
+    let text = format_exact(x);
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", mantissa),
+    };
+    let mut exponent = exponent.parse::<i64>().unwrap_or(0);
+    let mut figures = mantissa.bytes().filter(u8::is_ascii_digit).collect::<Vec<_>>();
+    let carried = (precision(x) as f64 * std::f64::consts::LOG10_2) as usize;
+    if round_digits(&mut figures, digits.unwrap_or(carried - 1).max(1)) {
+        exponent += 1;
+    }
+    while figures.len() > 1 && figures.last() == Some(&b'0') {
+        figures.pop();
+    }
+    let figures = String::from_utf8(figures).unwrap();
+
+    let shown = figures.len() as i64;
+    if !(-5..shown.max(21)).contains(&exponent) {
+        let (lead, rest) = figures.split_at(1);
+        let rest = if rest.is_empty() { String::new() } else { format!(".{rest}") };
+        return format!("{sign}{lead}{rest}e{exponent}");
+    }
+    if exponent < 0 {
+        return format!("{sign}0.{}{figures}", "0".repeat((-exponent - 1) as usize));
+    }
+    let whole = exponent as usize + 1;
+    if figures.len() <= whole {
+        format!("{sign}{figures}{}", "0".repeat(whole - figures.len()))
+    } else {
+        format!("{sign}{}.{}", &figures[..whole], &figures[whole..])
+    }
+}
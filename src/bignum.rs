@@ -0,0 +1,72 @@
+//! Exact combinatorics on big integers, for the operators that would lose
+//! precision past 2^53 in `f64`.
+
+use crate::numtheory;
+use num_bigint::BigInt;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+/// The product of `lo..hi`, split in halves so the factors stay balanced.
+fn range_product(lo: u64, hi: u64) -> BigInt {
+    match hi - lo {
+        0 => BigInt::one(),
+        1 => BigInt::from(lo),
+        len => {
+            let mid = lo + len / 2;
+            range_product(lo, mid) * range_product(mid, hi)
+        },
+    }
+}
+
+pub fn factorial(n: u64) -> BigInt {
+    range_product(1, n + 1)
+}
+
+/// The falling factorial `n (n-1) ... (n-k+1)`.
+pub fn permutations(n: &BigInt, k: u64) -> BigInt {
+    (0..k).fold(BigInt::one(), |product, i| product * (n - i))
+}
+
+/// `n` choose `k` for `k <= n`. Each partial product is itself a binomial
+/// coefficient, so the division is always exact.
+pub fn binomial(n: &BigInt, k: u64) -> BigInt {
+    (0..k).fold(BigInt::one(), |product, i| product * (n - i) / (i + 1))
+}
+
+/// Roughly how many bits `n!` takes, from Stirling's approximation.
+pub fn factorial_bits(n: u64) -> u64 {
+    let n = n as f64;
+    (n * (n.log2() - std::f64::consts::E.log2()) + 2.0).max(1.0) as u64
+}
+
+/// Miller–Rabin with the primes up to 41 as witnesses, which is exact below
+/// 3.3 * 10^24; a larger composite that passes all thirteen is vanishingly
+/// unlikely. Integers that fit in a `u64` go to `numtheory::is_prime`.
+pub fn is_probable_prime(n: &BigInt) -> bool {
+    const WITNESSES: [u32; 13] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41];
+    if n.is_negative() {
+        return false;
+    }
+    if let Some(n) = n.to_u64() {
+        return numtheory::is_prime(n);
+    }
+    if WITNESSES.iter().any(|p| (n % p).is_zero()) {
+        return false;
+    }
+    let n_minus_one = n - 1u32;
+    let s = n_minus_one.trailing_zeros().unwrap();
+    let d = &n_minus_one >> s;
+    'witness: for a in WITNESSES {
+        let mut x = BigInt::from(a).modpow(&d, n);
+        if x.is_one() || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..s {
+            x = &x * &x % n;
+            if x == n_minus_one {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
@@ -0,0 +1,206 @@
+//! Dates and times of day in UTC, as seconds since 1970-01-01T00:00:00 on
+//! the proleptic Gregorian calendar, without leap seconds.
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DateTime {
+    pub seconds: f64,
+}
+
+/// Days from 1970-01-01 to the given date, after Howard Hinnant's
+/// `days_from_civil`: the year is counted from March, so the leap day falls
+/// at its end, and 400 years make an era of exactly 146,097 days.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A field of so many decimal digits, without a sign.
+fn field(text: &str, digits: std::ops::RangeInclusive<usize>) -> Option<u32> {
+    if !digits.contains(&text.len()) || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+impl DateTime {
+    /// Reads `YYYY-MM-DD`, optionally followed by `THH:MM` or `THH:MM:SS`
+    /// with a fraction of a second.
+    pub fn parse(text: &str) -> Option<DateTime> {
+        let (date, time) = match text.split_once('T') {
+            Some((date, time)) => (date, Some(time)),
+            None => (text, None),
+        };
+        let mut parts = date.splitn(3, '-');
+        let year = field(parts.next()?, 4..=4)? as i64;
+        let month = field(parts.next()?, 2..=2)?;
+        let day = field(parts.next()?, 2..=2)?;
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        let mut seconds = days_from_civil(year, month, day) as f64 * SECONDS_PER_DAY;
+        if let Some(time) = time {
+            let mut parts = time.splitn(3, ':');
+            let hour = field(parts.next()?, 2..=2)?;
+            let minute = field(parts.next()?, 2..=2)?;
+            let second = match parts.next() {
+                Some(second) => {
+                    let whole = second.split_once('.').map_or(second, |(whole, _)| whole);
+                    field(whole, 2..=2)?;
+                    second.parse::<f64>().ok()?
+                },
+                None => 0.0,
+            };
+            if hour > 23 || minute > 59 || second >= 60.0 {
+                return None;
+            }
+            seconds += f64::from(hour * 3600 + minute * 60) + second;
+        }
+        Some(DateTime { seconds })
+    }
+
+    pub fn now() -> DateTime {
+        let since_epoch = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        DateTime { seconds: since_epoch.as_secs_f64() }
+    }
+
+    /// Whole days since 1970-01-01, rounding down.
+    fn days(self) -> i64 {
+        (self.seconds / SECONDS_PER_DAY).floor() as i64
+    }
+
+    /// The start of the day.
+    pub fn midnight(self) -> DateTime {
+        DateTime { seconds: self.days() as f64 * SECONDS_PER_DAY }
+    }
+
+    pub fn add_seconds(self, seconds: f64) -> DateTime {
+        DateTime { seconds: self.seconds + seconds }
+    }
+
+    /// The English name of the day of the week. 1970-01-01 was a Thursday.
+    pub fn weekday(self) -> &'static str {
+        WEEKDAYS[(self.days() + 3).rem_euclid(7) as usize]
+    }
+
+    /// The calendar date, and the time of day rounded to the microsecond.
+    pub fn fields(self) -> (i64, u32, u32, u32, u32, f64) {
+        let mut days = self.days();
+        let mut micros = ((self.seconds - days as f64 * SECONDS_PER_DAY) * 1e6).round() as i64;
+        if micros == 86_400_000_000 {
+            days += 1;
+            micros = 0;
+        }
+        let (year, month, day) = civil_from_days(days);
+        let (hour, minute) = ((micros / 3_600_000_000) as u32, (micros / 60_000_000 % 60) as u32);
+        (year, month, day, hour, minute, (micros % 60_000_000) as f64 / 1e6)
+    }
+}
+
+/// `2024-06-01` at midnight, and `2024-06-01T12:30:05.25` otherwise, which
+/// `DateTime::parse` reads back.
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.seconds.is_finite() {
+            return write!(f, "invalid date");
+        }
+        let (year, month, day, hour, minute, second) = self.fields();
+        write!(f, "{year:04}-{month:02}-{day:02}")?;
+        if (hour, minute, second) != (0, 0, 0.0) {
+            write!(f, "T{hour:02}:{minute:02}:{:02}", second.trunc())?;
+            if second.fract() != 0.0 {
+                let fraction = format!("{:.6}", second.fract());
+                write!(f, ".{}", fraction.trim_start_matches("0.").trim_end_matches('0'))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> DateTime {
+        DateTime::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parses_dates_and_times() {
+        assert_eq!(parse("1970-01-01").seconds, 0.0);
+        assert_eq!(parse("1970-01-02T01:02:03.5").seconds, 86_400.0 + 3723.5);
+        assert_eq!(parse("2000-03-01").seconds, 951_868_800.0);
+        assert_eq!(parse("1969-12-31T23:00").seconds, -3600.0);
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        let invalid = [
+            "2024-02-30", "2023-02-29", "1900-02-29", "2024-13-01", "2024-00-10", "2024-6-01",
+            "2024-06-01T24:00", "2024-06-01T12:60", "2024-06-01T12:00:60", "2024-06-01T12", "+024-06-01",
+        ];
+        for text in invalid {
+            assert!(DateTime::parse(text).is_none(), "{text}");
+        }
+        assert!(DateTime::parse("2000-02-29").is_some());
+    }
+
+    #[test]
+    fn calendar_round_trips() {
+        for days in [-719_468, -1, 0, 11_016, 19_875, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(parse("1970-01-01").weekday(), "Thursday");
+        assert_eq!(parse("2024-06-01T18:00").weekday(), "Saturday");
+        assert_eq!(parse("1969-12-31T23:59").weekday(), "Wednesday");
+    }
+
+    #[test]
+    fn displays_what_parse_reads_back() {
+        for text in ["2024-06-01", "2024-06-01T12:30:05.25", "1969-12-31T23:59:59.5", "2024-02-29T00:00:01"] {
+            assert_eq!(parse(text).to_string(), text);
+        }
+        assert_eq!(parse("2024-06-01T23:59:59.9999999").to_string(), "2024-06-02");
+        assert_eq!(parse("2024-06-01T12:00").midnight().to_string(), "2024-06-01");
+        assert_eq!(DateTime { seconds: f64::NAN }.to_string(), "invalid date");
+    }
+}
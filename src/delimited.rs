@@ -0,0 +1,114 @@
+//! Converting between numeric CSV or TSV text and row-major matrix data.
+
+/// Picks the delimiter of `line`: a comma, tab or semicolon if it has one, in
+/// that order, and runs of whitespace otherwise.
+fn detect_delimiter(line: &str) -> Option<char> {
+    [',', '\t', ';'].into_iter().find(|delimiter| line.contains(*delimiter))
+}
+
+fn split_fields(line: &str, delimiter: Option<char>) -> Vec<&str> {
+    match delimiter {
+        Some(delimiter) => line.split(delimiter).map(str::trim).collect(),
+        None => line.split_whitespace().collect(),
+    }
+}
+
+fn parse_row(line: &str, delimiter: Option<char>) -> Option<Vec<f64>> {
+    split_fields(line, delimiter).iter().map(|field| field.parse().ok()).collect()
+}
+
+/// Parses `text` into `(rows, cols, data)`. Blank lines are ignored. With
+/// `skip` set, that many leading lines are dropped as a header; otherwise a
+/// first line with no numbers at all is taken to be one, so a mistyped first
+/// row is still reported. Every remaining line must have the same number of
+/// numeric fields.
+pub fn parse(text: &str, skip: Option<usize>) -> Result<(usize, usize, Vec<f64>), String> {
+    let mut lines = text.lines().enumerate()
+        .skip(skip.unwrap_or(0))
+        .filter(|(_, line)| !line.trim().is_empty())
+        .peekable();
+    let Some((_, first)) = lines.peek() else {
+        return Err("no data rows".to_string());
+    };
+    let delimiter = detect_delimiter(first);
+    let is_header = split_fields(first, delimiter).iter().all(|field| field.parse::<f64>().is_err());
+    if skip.is_none() && is_header {
+        lines.next();
+    }
+    let mut data = Vec::new();
+    let mut cols = None;
+    let mut rows = 0;
+    for (index, line) in lines {
+        let row = parse_row(line, delimiter)
+            .ok_or_else(|| format!("line {} is not all numbers", index + 1))?;
+        match cols {
+            Some(cols) if cols != row.len() => {
+                return Err(format!("line {} has {} fields, expected {cols}", index + 1, row.len()));
+            },
+            _ => cols = Some(row.len()),
+        }
+        data.extend(row);
+        rows += 1;
+    }
+    match cols {
+        Some(cols) if cols > 0 => Ok((rows, cols, data)),
+        _ => Err("no data rows".to_string()),
+    }
+}
+
+/// One line per row of the `cols`-wide `data`, fields joined by `delimiter`.
+pub fn format(cols: usize, data: &[f64], delimiter: char, number: impl Fn(f64) -> String) -> String {
+    let mut text = String::new();
+    for row in data.chunks(cols) {
+        let fields = row.iter().map(|elem| number(*elem)).collect::<Vec<_>>();
+        text.push_str(&fields.join(&delimiter.to_string()));
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_delimiters() {
+        let expected = (2, 2, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(parse("1,2\n3,4\n", None).unwrap(), expected);
+        assert_eq!(parse("1\t2\n\n3\t4", None).unwrap(), expected);
+        assert_eq!(parse("1; 2\n3; 4", None).unwrap(), expected);
+        assert_eq!(parse("  1   2\n3 4\n", None).unwrap(), expected);
+    }
+
+    #[test]
+    fn skips_headers() {
+        assert_eq!(parse("x,y\n1,2\n", None).unwrap(), (1, 2, vec![1.0, 2.0]));
+        assert_eq!(parse("# run 4\nx,y\n1,2\n", Some(2)).unwrap(), (1, 2, vec![1.0, 2.0]));
+        // With an explicit count, nothing else is taken for a header
+        assert_eq!(parse("x,y\n1,2\n", Some(0)), Err("line 1 is not all numbers".to_string()));
+    }
+
+    #[test]
+    fn reports_a_mistyped_first_row() {
+        assert_eq!(parse("1,2x\n3,4\n", None), Err("line 1 is not all numbers".to_string()));
+    }
+
+    #[test]
+    fn rejects_ragged_rows() {
+        assert_eq!(parse("1,2\n3,4,5\n", None), Err("line 2 has 3 fields, expected 2".to_string()));
+    }
+
+    #[test]
+    fn rejects_empty_input() {
+        assert_eq!(parse("\n\n", None), Err("no data rows".to_string()));
+        assert_eq!(parse("x,y\n", None), Err("no data rows".to_string()));
+        assert_eq!(parse("1,2\n", Some(1)), Err("no data rows".to_string()));
+    }
+
+    #[test]
+    fn formats_rows() {
+        let text = format(2, &[1.0, 2.0, 3.0, 4.0], ',', |elem| elem.to_string());
+        assert_eq!(text, "1,2\n3,4\n");
+        assert_eq!(parse(&text, None).unwrap(), (2, 2, vec![1.0, 2.0, 3.0, 4.0]));
+    }
+}
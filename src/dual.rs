@@ -0,0 +1,139 @@
+//! Dual numbers `a + b ε` with `ε² = 0`, for forward-mode automatic
+//! differentiation. A function of `x + ε` comes out as `f(x) + f'(x) ε`, so
+//! the `ε` part carries the derivative through each operation by the chain
+//! rule, exact up to the rounding of the formulas.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dual {
+    pub value: f64,
+    pub deriv: f64,
+}
+
+impl Dual {
+    pub fn new(value: f64, deriv: f64) -> Dual {
+        Dual { value, deriv }
+    }
+
+    /// A number that does not depend on the marked variable.
+    pub fn constant(value: f64) -> Dual {
+        Dual::new(value, 0.0)
+    }
+
+    /// The marked variable itself, whose derivative is 1.
+    pub fn variable(value: f64) -> Dual {
+        Dual::new(value, 1.0)
+    }
+
+    /// `value` of a function at `self.value`, whose derivative there is
+    /// `slope`.
+    fn chain(self, value: f64, slope: f64) -> Dual {
+        Dual::new(value, slope * self.deriv)
+    }
+
+    pub fn add(self, rhs: Dual) -> Dual {
+        Dual::new(self.value + rhs.value, self.deriv + rhs.deriv)
+    }
+
+    pub fn sub(self, rhs: Dual) -> Dual {
+        Dual::new(self.value - rhs.value, self.deriv - rhs.deriv)
+    }
+
+    pub fn mul(self, rhs: Dual) -> Dual {
+        Dual::new(self.value * rhs.value, self.deriv * rhs.value + self.value * rhs.deriv)
+    }
+
+    pub fn div(self, rhs: Dual) -> Dual {
+        let value = self.value / rhs.value;
+        Dual::new(value, (self.deriv - value * rhs.deriv) / rhs.value)
+    }
+
+    /// The logarithm in the derivative is left out for a constant exponent,
+    /// so powers of negative bases like `x 3 ^` keep a derivative.
+    pub fn pow(self, rhs: Dual) -> Dual {
+        let value = self.value.powf(rhs.value);
+        // `x 0 ^` is constant, even at 0 where `0 x^-1` would be NaN
+        let base_term = match rhs.value == 0.0 {
+            true => 0.0,
+            false => rhs.value * self.value.powf(rhs.value - 1.0) * self.deriv,
+        };
+        if rhs.deriv == 0.0 {
+            return Dual::new(value, base_term);
+        }
+        Dual::new(value, base_term + value * self.value.ln() * rhs.deriv)
+    }
+
+    pub fn neg(self) -> Dual {
+        Dual::new(-self.value, -self.deriv)
+    }
+
+    /// Takes the slope of the side `self.value` is on, the right one at zero.
+    pub fn abs(self) -> Dual {
+        self.chain(self.value.abs(), self.value.signum())
+    }
+
+    pub fn sqrt(self) -> Dual {
+        let root = self.value.sqrt();
+        self.chain(root, 0.5 / root)
+    }
+
+    pub fn exp(self) -> Dual {
+        let exp = self.value.exp();
+        self.chain(exp, exp)
+    }
+
+    pub fn ln(self) -> Dual {
+        self.chain(self.value.ln(), 1.0 / self.value)
+    }
+
+    pub fn log10(self) -> Dual {
+        self.chain(self.value.log10(), 1.0 / (self.value * std::f64::consts::LN_10))
+    }
+
+    pub fn log2(self) -> Dual {
+        self.chain(self.value.log2(), 1.0 / (self.value * std::f64::consts::LN_2))
+    }
+
+    pub fn sin(self) -> Dual {
+        self.chain(self.value.sin(), self.value.cos())
+    }
+
+    pub fn cos(self) -> Dual {
+        self.chain(self.value.cos(), -self.value.sin())
+    }
+
+    pub fn tan(self) -> Dual {
+        let tan = self.value.tan();
+        self.chain(tan, 1.0 + tan * tan)
+    }
+
+    pub fn asin(self) -> Dual {
+        self.chain(self.value.asin(), 1.0 / (1.0 - self.value * self.value).sqrt())
+    }
+
+    pub fn acos(self) -> Dual {
+        self.chain(self.value.acos(), -1.0 / (1.0 - self.value * self.value).sqrt())
+    }
+
+    pub fn atan(self) -> Dual {
+        self.chain(self.value.atan(), 1.0 / (1.0 + self.value * self.value))
+    }
+
+    pub fn sinh(self) -> Dual {
+        self.chain(self.value.sinh(), self.value.cosh())
+    }
+
+    pub fn cosh(self) -> Dual {
+        self.chain(self.value.cosh(), self.value.sinh())
+    }
+
+    pub fn tanh(self) -> Dual {
+        let tanh = self.value.tanh();
+        self.chain(tanh, 1.0 - tanh * tanh)
+    }
+
+    /// A change of units by `convert`, which is linear, such as degrees to
+    /// radians: the derivative scales by the same factor.
+    pub fn convert(self, convert: impl Fn(f64) -> f64) -> Dual {
+        self.chain(convert(self.value), convert(1.0))
+    }
+}
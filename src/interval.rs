@@ -0,0 +1,234 @@
+//! Closed intervals of `f64` with outward rounding. `f64` has no directed
+//! rounding modes, so the arithmetic recovers the rounding error of each
+//! endpoint and steps past it by one representable value when it is not
+//! exact. The library functions have no exact error to recover and are not
+//! always correctly rounded, so their results are widened by two instead.
+
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+/// Moves `lo` down and `hi` up by `ulps` representable values.
+fn widen(lo: f64, hi: f64, ulps: usize) -> Interval {
+    let (mut lo, mut hi) = (lo, hi);
+    for _ in 0..ulps {
+        lo = lo.next_down();
+        hi = hi.next_up();
+    }
+    Interval { lo, hi }
+}
+
+/// Like `widen`, but keeps endpoints of exactly zero: `sin` and `ln` only
+/// return zero at 0 and 1, where it is the true value.
+fn widen_nonzero(lo: f64, hi: f64) -> Interval {
+    let keep = |x: f64, widened: f64| if x == 0.0 { x } else { widened };
+    let widened = widen(lo, hi, 2);
+    Interval { lo: keep(lo, widened.lo), hi: keep(hi, widened.hi) }
+}
+
+/// The rounded result `approx` of an operation whose exact value is
+/// `approx + err`, rounded down or up. Overflow and underflow lose the
+/// error, so results that are not normal finite numbers always step.
+fn round(approx: f64, err: f64, down: bool) -> f64 {
+    let normal = approx.is_normal() || approx == 0.0 && err == 0.0;
+    match down {
+        true if err < 0.0 || !normal => approx.next_down(),
+        false if err > 0.0 || !normal => approx.next_up(),
+        _ => approx,
+    }
+}
+
+/// `a + b` rounded down or up, with the error from Knuth's two-sum.
+fn add_rounded(a: f64, b: f64, down: bool) -> f64 {
+    let sum = a + b;
+    let b_part = sum - a;
+    let err = (a - (sum - b_part)) + (b - b_part);
+    round(sum, if sum.is_finite() { err } else { 0.0 }, down)
+}
+
+/// `a * b` rounded down or up, with the error from a fused multiply-add. A
+/// zero factor, which includes zero times infinity, gives an exact zero:
+/// the infinity only stands for arbitrarily large finite numbers.
+fn mul_rounded(a: f64, b: f64, down: bool) -> f64 {
+    if a == 0.0 || b == 0.0 {
+        return 0.0;
+    }
+    let product = a * b;
+    round(product, a.mul_add(b, -product), down)
+}
+
+/// `a / b` rounded down or up. The remainder `a - q b` is exact, and the
+/// quotient falls short of the true one when it has the sign of `b`.
+fn div_rounded(a: f64, b: f64, down: bool) -> f64 {
+    if a == 0.0 && b != 0.0 {
+        return 0.0;
+    }
+    let quotient = a / b;
+    let remainder = (-quotient).mul_add(b, a);
+    let err = if b.is_finite() { remainder * b.signum() } else { f64::NAN };
+    round(quotient, err, down)
+}
+
+impl Interval {
+    /// Needs `lo <= hi`, which the caller checks.
+    pub fn new(lo: f64, hi: f64) -> Interval {
+        Interval { lo, hi }
+    }
+
+    pub fn point(x: f64) -> Interval {
+        Interval { lo: x, hi: x }
+    }
+
+    /// The interval around `x` that also holds the values it was rounded from.
+    pub fn around(x: f64) -> Interval {
+        widen(x, x, 1)
+    }
+
+    pub fn contains_zero(self) -> bool {
+        self.lo <= 0.0 && 0.0 <= self.hi
+    }
+
+    pub fn add(self, rhs: Interval) -> Interval {
+        Interval::new(add_rounded(self.lo, rhs.lo, true), add_rounded(self.hi, rhs.hi, false))
+    }
+
+    pub fn sub(self, rhs: Interval) -> Interval {
+        self.add(rhs.neg())
+    }
+
+    pub fn mul(self, rhs: Interval) -> Interval {
+        self.combine(rhs, mul_rounded)
+    }
+
+    /// The whole real line when `rhs` contains zero, since the quotient is
+    /// then unbounded.
+    pub fn div(self, rhs: Interval) -> Interval {
+        if rhs.contains_zero() {
+            return Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+        }
+        self.combine(rhs, div_rounded)
+    }
+
+    /// The smallest and largest of `op` on the four pairs of endpoints, for
+    /// operations that are monotonic in each operand on either side of zero.
+    fn combine(self, rhs: Interval, op: fn(f64, f64, bool) -> f64) -> Interval {
+        let pairs = [(self.lo, rhs.lo), (self.lo, rhs.hi), (self.hi, rhs.lo), (self.hi, rhs.hi)];
+        let lo = pairs.iter().map(|&(a, b)| op(a, b, true)).fold(f64::INFINITY, f64::min);
+        let hi = pairs.iter().map(|&(a, b)| op(a, b, false)).fold(f64::NEG_INFINITY, f64::max);
+        Interval::new(lo, hi)
+    }
+
+    pub fn neg(self) -> Interval {
+        Interval::new(-self.hi, -self.lo)
+    }
+
+    pub fn abs(self) -> Interval {
+        if self.lo >= 0.0 {
+            self
+        } else if self.hi <= 0.0 {
+            self.neg()
+        } else {
+            Interval::new(0.0, self.hi.max(-self.lo))
+        }
+    }
+
+    /// `x^n` by squaring and multiplying point intervals, so the rounding of
+    /// every step is accounted for.
+    fn point_power(x: f64, mut n: u32) -> Interval {
+        let mut base = Interval::point(x);
+        let mut result = Interval::point(1.0);
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.mul(base);
+            }
+            n >>= 1;
+            if n > 0 {
+                base = base.mul(base);
+            }
+        }
+        result
+    }
+
+    /// A non-negative integer power. Odd powers are increasing, and even
+    /// powers are increasing in the absolute value.
+    pub fn powi(self, n: u32) -> Interval {
+        let base = if n.is_multiple_of(2) { self.abs() } else { self };
+        Interval::new(Self::point_power(base.lo, n).lo, Self::point_power(base.hi, n).hi)
+    }
+
+    /// `None` when the whole interval is negative. A negative lower bound is
+    /// cut off at zero, where the square root starts to be defined.
+    pub fn sqrt(self) -> Option<Interval> {
+        if self.hi < 0.0 {
+            return None;
+        }
+        let root = |x: f64, down: bool| {
+            let root = x.sqrt();
+            let err = if root.is_finite() { (-root).mul_add(root, x) } else { 0.0 };
+            round(root, err, down)
+        };
+        Some(Interval::new(root(self.lo.max(0.0), true), root(self.hi, false)).max_lo(0.0))
+    }
+
+    pub fn exp(self) -> Interval {
+        widen(self.lo.exp(), self.hi.exp(), 2).max_lo(0.0)
+    }
+
+    /// `None` when the whole interval is negative; like `sqrt`, the rest is
+    /// cut off at zero.
+    pub fn ln(self) -> Option<Interval> {
+        if self.hi < 0.0 {
+            return None;
+        }
+        Some(widen_nonzero(self.lo.max(0.0).ln(), self.hi.ln()))
+    }
+
+    pub fn sin(self) -> Interval {
+        self.periodic(f64::sin, FRAC_PI_2)
+    }
+
+    pub fn cos(self) -> Interval {
+        self.periodic(f64::cos, 0.0)
+    }
+
+    /// Increasing between its poles at pi/2 + k pi, so the endpoint values
+    /// span it, unless the interval reaches a pole and it is unbounded.
+    pub fn tan(self) -> Interval {
+        let whole = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+        if self.hi - self.lo >= PI || self.lo.is_nan() || self.hi.is_nan() {
+            return whole;
+        }
+        let pole = FRAC_PI_2 + ((self.lo - FRAC_PI_2) / PI).ceil() * PI;
+        let (lo, hi) = (self.lo.tan(), self.hi.tan());
+        // Past a pole the values drop, even if rounding put it outside
+        if pole <= self.hi || lo > hi {
+            return whole;
+        }
+        widen_nonzero(lo, hi)
+    }
+
+    /// `fun` of period 2 pi with its maximum 1 at `peak` and its minimum -1
+    /// half a period later. Between the extrema it is monotonic, so the
+    /// result is spanned by the endpoint values and any extremum inside.
+    fn periodic(self, fun: fn(f64) -> f64, peak: f64) -> Interval {
+        let full = Interval::new(-1.0, 1.0);
+        if self.hi - self.lo >= TAU || self.lo.is_nan() || self.hi.is_nan() {
+            return full;
+        }
+        let reaches = |at: f64| at + ((self.lo - at) / TAU).ceil() * TAU <= self.hi;
+        let (start, end) = (fun(self.lo), fun(self.hi));
+        let rounded = widen_nonzero(start.min(end), start.max(end));
+        let lo = if reaches(peak + PI) { -1.0 } else { rounded.lo.max(-1.0) };
+        let hi = if reaches(peak) { 1.0 } else { rounded.hi.min(1.0) };
+        Interval::new(lo, hi)
+    }
+
+    /// Raises the lower bound to `floor`, for results known not to go below it.
+    fn max_lo(self, floor: f64) -> Interval {
+        Interval::new(self.lo.max(floor), self.hi)
+    }
+}
@@ -0,0 +1,730 @@
+//! Dense linear-algebra kernels behind the matrix operators. Matrices are
+//! row-major slices: `n * n` entries when square, `m * n` otherwise.
+//!
+//! With the `nalgebra` feature, the LU, Cholesky, SVD and symmetric
+//! eigenvalue kernels come from nalgebra instead, behind the same functions.
+//!
+//! The iterative kernels poll for Ctrl-C once per step, so they fail with
+//! `CalcError::Interrupted` rather than running on.
+
+use crate::{check_interrupt, CalcError};
+
+#[cfg(feature = "nalgebra")]
+mod backend;
+#[cfg(feature = "nalgebra")]
+pub use backend::{cholesky, lu, svd, symmetric_eigen};
+
+/// `PA = LU` with `L` unit lower triangular, stored together in `lu`: `U` on
+/// and above the diagonal, `L` below it.
+pub struct Lu {
+    pub n: usize,
+    pub lu: Vec<f64>,
+    /// Row `i` of `PA` is row `perm[i]` of `A`.
+    pub perm: Vec<usize>,
+    /// `+1` or `-1`, the parity of `perm`.
+    pub sign: f64,
+}
+
+/// Doolittle elimination with partial pivoting. A zero pivot column is
+/// skipped rather than rejected, so singular matrices still factor and
+/// `det` comes out as zero.
+#[cfg(not(feature = "nalgebra"))]
+pub fn lu(n: usize, a: &[f64]) -> Result<Lu, CalcError> {
+    let mut lu = a.to_vec();
+    let mut perm = (0..n).collect::<Vec<_>>();
+    let mut sign = 1.0;
+    for k in 0..n {
+        check_interrupt()?;
+        let pivot = (k..n)
+            .max_by(|&i, &j| lu[i*n + k].abs().total_cmp(&lu[j*n + k].abs()))
+            .unwrap();
+        if pivot != k {
+            for j in 0..n {
+                lu.swap(k*n + j, pivot*n + j);
+            }
+            perm.swap(k, pivot);
+            sign = -sign;
+        }
+        let diag = lu[k*n + k];
+        if diag == 0.0 {
+            continue;
+        }
+        for i in k + 1..n {
+            let factor = lu[i*n + k] / diag;
+            lu[i*n + k] = factor;
+            for j in k + 1..n {
+                lu[i*n + j] -= factor * lu[k*n + j];
+            }
+        }
+    }
+    Ok(Lu { n, lu, perm, sign })
+}
+
+impl Lu {
+    pub fn det(&self) -> f64 {
+        (0..self.n).map(|i| self.lu[i*self.n + i]).product::<f64>() * self.sign
+    }
+
+    /// The unit lower triangular factor.
+    pub fn lower(&self) -> Vec<f64> {
+        let n = self.n;
+        let mut data = identity(n);
+        for i in 0..n {
+            data[i*n..i*n + i].copy_from_slice(&self.lu[i*n..i*n + i]);
+        }
+        data
+    }
+
+    pub fn upper(&self) -> Vec<f64> {
+        let n = self.n;
+        let mut data = vec![0.0; n * n];
+        for i in 0..n {
+            data[i*n + i..(i + 1)*n].copy_from_slice(&self.lu[i*n + i..(i + 1)*n]);
+        }
+        data
+    }
+
+    /// The permutation matrix `P` with a one at `(i, perm[i])`.
+    pub fn permutation(&self) -> Vec<f64> {
+        let n = self.n;
+        let mut data = vec![0.0; n * n];
+        for (i, &row) in self.perm.iter().enumerate() {
+            data[i*n + row] = 1.0;
+        }
+        data
+    }
+
+    /// Whether some pivot of `U` is negligible next to the largest entry of
+    /// `U`, in which case solving with it would only amplify rounding noise.
+    pub fn is_singular(&self) -> bool {
+        let n = self.n;
+        let scale = (0..n).flat_map(|i| (i..n).map(move |j| (i, j)))
+            .map(|(i, j)| self.lu[i*n + j].abs())
+            .fold(0.0, f64::max);
+        let tolerance = scale * n as f64 * f64::EPSILON;
+        (0..n).any(|i| self.lu[i*n + i].abs() <= tolerance)
+    }
+
+    /// Solves `AX = B` for the `n x m` right-hand side `b`, by forward and
+    /// back substitution. The factorization must not be singular.
+    pub fn solve(&self, b: &[f64], m: usize) -> Result<Vec<f64>, CalcError> {
+        let n = self.n;
+        let mut x = Vec::with_capacity(n * m);
+        for &row in &self.perm {
+            x.extend_from_slice(&b[row*m..(row + 1)*m]);
+        }
+        for i in 0..n {
+            check_interrupt()?;
+            for k in 0..i {
+                let factor = self.lu[i*n + k];
+                for j in 0..m {
+                    x[i*m + j] -= factor * x[k*m + j];
+                }
+            }
+        }
+        for i in (0..n).rev() {
+            check_interrupt()?;
+            for k in i + 1..n {
+                let factor = self.lu[i*n + k];
+                for j in 0..m {
+                    x[i*m + j] -= factor * x[k*m + j];
+                }
+            }
+            let diag = self.lu[i*n + i];
+            for j in 0..m {
+                x[i*m + j] /= diag;
+            }
+        }
+        Ok(x)
+    }
+}
+
+pub fn identity(n: usize) -> Vec<f64> {
+    let mut data = vec![0.0; n * n];
+    for i in 0..n {
+        data[i*n + i] = 1.0;
+    }
+    data
+}
+
+/// Householder QR of the `m x n` matrix `a`: returns the orthogonal `m x m`
+/// `Q` and the upper triangular `m x n` `R` with `A = Q R`.
+pub fn qr(m: usize, n: usize, a: &[f64]) -> Result<(Vec<f64>, Vec<f64>), CalcError> {
+    let mut r = a.to_vec();
+    let mut q = identity(m);
+    for k in 0..n.min(m.saturating_sub(1)) {
+        check_interrupt()?;
+        let norm = (k..m).map(|i| r[i*n + k].powi(2)).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        // Reflect onto the side away from x[0] to avoid cancellation.
+        let alpha = if r[k*n + k] > 0.0 { -norm } else { norm };
+        let mut v = (k..m).map(|i| r[i*n + k]).collect::<Vec<_>>();
+        v[0] -= alpha;
+        let v_norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        v.iter_mut().for_each(|x| *x /= v_norm);
+        for j in 0..n {
+            let dot = (k..m).map(|i| v[i - k] * r[i*n + j]).sum::<f64>();
+            for i in k..m {
+                r[i*n + j] -= 2.0 * v[i - k] * dot;
+            }
+        }
+        for i in 0..m {
+            let dot = (k..m).map(|j| q[i*m + j] * v[j - k]).sum::<f64>();
+            for j in k..m {
+                q[i*m + j] -= 2.0 * dot * v[j - k];
+            }
+        }
+        for i in k + 1..m {
+            r[i*n + k] = 0.0;
+        }
+    }
+    Ok((q, r))
+}
+
+/// Symmetric to within rounding, relative to the largest entry.
+pub fn is_symmetric(n: usize, a: &[f64]) -> bool {
+    let scale = a.iter().fold(0.0, |acc: f64, x| acc.max(x.abs()));
+    let tolerance = scale * n as f64 * f64::EPSILON;
+    (0..n).all(|i| (0..i).all(|j| (a[i*n + j] - a[j*n + i]).abs() <= tolerance))
+}
+
+/// Cholesky factor `L` with `A = L Lᵀ` of a symmetric `a`, or `None` if it
+/// is not positive definite. Only the lower triangle is read.
+#[cfg(not(feature = "nalgebra"))]
+pub fn cholesky(n: usize, a: &[f64]) -> Option<Vec<f64>> {
+    let mut l = vec![0.0_f64; n * n];
+    for j in 0..n {
+        let diag = a[j*n + j] - (0..j).map(|k| l[j*n + k].powi(2)).sum::<f64>();
+        if diag <= 0.0 || diag.is_nan() {
+            return None;
+        }
+        let diag = diag.sqrt();
+        l[j*n + j] = diag;
+        for i in j + 1..n {
+            let dot = (0..j).map(|k| l[i*n + k] * l[j*n + k]).sum::<f64>();
+            l[i*n + j] = (a[i*n + j] - dot) / diag;
+        }
+    }
+    Some(l)
+}
+
+#[cfg(not(feature = "nalgebra"))]
+fn transpose(m: usize, n: usize, a: &[f64]) -> Vec<f64> {
+    (0..n).flat_map(|j| (0..m).map(move |i| a[i*n + j])).collect()
+}
+
+/// Thin SVD `A = U diag(sigma) Vᵀ` of an `m x n` matrix, with `k = min(m, n)`:
+/// `u` is `m x k`, `vt` is `k x n`, and `sigma` is sorted largest first.
+pub struct Svd {
+    pub u: Vec<f64>,
+    pub sigma: Vec<f64>,
+    pub vt: Vec<f64>,
+}
+
+/// One-sided Jacobi: plane rotations orthogonalize the columns of `A` in
+/// place, after which their norms are the singular values. Fails for
+/// non-finite entries.
+#[cfg(not(feature = "nalgebra"))]
+pub fn svd(m: usize, n: usize, a: &[f64]) -> Result<Svd, CalcError> {
+    check_finite("singular values", a)?;
+    if m < n {
+        let Svd { u, sigma, vt } = svd(n, m, &transpose(m, n, a))?;
+        return Ok(Svd { u: transpose(m, m, &vt), sigma, vt: transpose(n, m, &u) });
+    }
+    let mut w = a.to_vec();
+    let mut v = identity(n);
+    const MAX_SWEEPS: usize = 60;
+    for _ in 0..MAX_SWEEPS {
+        let mut rotated = false;
+        for p in 0..n {
+            check_interrupt()?;
+            for q in p + 1..n {
+                let alpha = (0..m).map(|i| w[i*n + p].powi(2)).sum::<f64>();
+                let beta = (0..m).map(|i| w[i*n + q].powi(2)).sum::<f64>();
+                let gamma = (0..m).map(|i| w[i*n + p] * w[i*n + q]).sum::<f64>();
+                if gamma.abs() <= f64::EPSILON * (alpha * beta).sqrt() {
+                    continue;
+                }
+                rotated = true;
+                let zeta = (beta - alpha) / (2.0 * gamma);
+                let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                let c = 1.0 / (1.0 + t * t).sqrt();
+                let s = c * t;
+                for (mat, rows) in [(&mut w, m), (&mut v, n)] {
+                    for i in 0..rows {
+                        let (x, y) = (mat[i*n + p], mat[i*n + q]);
+                        mat[i*n + p] = c * x - s * y;
+                        mat[i*n + q] = s * x + c * y;
+                    }
+                }
+            }
+        }
+        if !rotated {
+            break;
+        }
+    }
+    let norms = (0..n).map(|j| (0..m).map(|i| w[i*n + j].powi(2)).sum::<f64>().sqrt()).collect::<Vec<_>>();
+    let mut order = (0..n).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| norms[j].total_cmp(&norms[i]));
+    let mut u = vec![0.0; m * n];
+    let mut vt = vec![0.0; n * n];
+    for (col, &j) in order.iter().enumerate() {
+        for i in 0..m {
+            u[i*n + col] = if norms[j] > 0.0 { w[i*n + j] / norms[j] } else { 0.0 };
+        }
+        for i in 0..n {
+            vt[col*n + i] = v[i*n + j];
+        }
+    }
+    complete_columns(m, n, &mut u);
+    Ok(Svd { u, sigma: order.iter().map(|&j| norms[j]).collect(), vt })
+}
+
+/// Replaces the zero columns of the `m x n` matrix `u`, whose other columns
+/// are orthonormal, with unit vectors orthogonal to the rest.
+#[cfg(not(feature = "nalgebra"))]
+fn complete_columns(m: usize, n: usize, u: &mut [f64]) {
+    for col in 0..n {
+        if (0..m).any(|i| u[i*n + col] != 0.0) {
+            continue;
+        }
+        for basis in 0..m {
+            let mut candidate = vec![0.0; m];
+            candidate[basis] = 1.0;
+            for other in (0..n).filter(|&other| other != col) {
+                let dot = (0..m).map(|i| u[i*n + other] * candidate[i]).sum::<f64>();
+                for (i, elem) in candidate.iter_mut().enumerate() {
+                    *elem -= dot * u[i*n + other];
+                }
+            }
+            let norm = candidate.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm > 0.5 {
+                for (i, elem) in candidate.iter().enumerate() {
+                    u[i*n + col] = elem / norm;
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// Eigenvalues of a symmetric matrix, largest first, with the matching unit
+/// eigenvectors as the columns of an `n x n` matrix.
+///
+/// Shifted QR iteration: each step factors `A - μI = QR` and continues with
+/// `RQ + μI`, using the Wilkinson shift of the trailing active block, which
+/// is deflated once its last row decouples. The product of the `Q`s
+/// accumulates the eigenvectors. Fails for non-finite entries, and if that
+/// takes more than `100 n` steps.
+#[cfg(not(feature = "nalgebra"))]
+pub fn symmetric_eigen(n: usize, a: &[f64]) -> Result<(Vec<f64>, Vec<f64>), CalcError> {
+    check_finite("eigenvalues", a)?;
+    let mut a = a.to_vec();
+    let mut vectors = identity(n);
+    let mut size = n;
+    let mut iterations = 0;
+    while size > 1 {
+        let last = size - 1;
+        let diag = a[last*n + last].abs() + a[(last - 1)*n + last - 1].abs();
+        if (0..last).all(|j| a[last*n + j].abs() <= f64::EPSILON * diag) {
+            size -= 1;
+            continue;
+        }
+        if iterations == 100 * n {
+            return Err(not_converged("eigenvalues", n, n));
+        }
+        iterations += 1;
+        check_interrupt()?;
+        let (x, y, z) = (a[(last - 1)*n + last - 1], a[(last - 1)*n + last], a[last*n + last]);
+        let d = (x - z) / 2.0;
+        let sign = if d < 0.0 { -1.0 } else { 1.0 };
+        let shift = z - sign * y * y / (d.abs() + (d * d + y * y).sqrt());
+        for i in 0..n {
+            a[i*n + i] -= shift;
+        }
+        let (q, r) = qr(n, n, &a)?;
+        a = multiply(n, &r, &q);
+        for i in 0..n {
+            a[i*n + i] += shift;
+        }
+        vectors = multiply(n, &vectors, &q);
+    }
+    let mut order = (0..n).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| a[j*n + j].total_cmp(&a[i*n + i]));
+    let values = order.iter().map(|&j| a[j*n + j]).collect();
+    let vectors = (0..n).flat_map(|i| order.iter().map(move |&j| (i, j))).map(|(i, j)| vectors[i*n + j]).collect();
+    Ok((values, vectors))
+}
+
+/// Scales rows and columns by powers of two, which changes no eigenvalue and
+/// rounds nothing, until every row and its column have comparable norms.
+/// The eigenvalues of a balanced matrix come out with less rounding.
+fn balance(n: usize, a: &mut [f64]) {
+    const RADIX: f64 = 2.0;
+    let mut done = false;
+    while !done {
+        done = true;
+        for i in 0..n {
+            let mut col = (0..n).filter(|&j| j != i).map(|j| a[j*n + i].abs()).sum::<f64>();
+            let row = (0..n).filter(|&j| j != i).map(|j| a[i*n + j].abs()).sum::<f64>();
+            if col == 0.0 || row == 0.0 {
+                continue;
+            }
+            let total = col + row;
+            let mut scale = 1.0;
+            while col < row / RADIX {
+                scale *= RADIX;
+                col *= RADIX * RADIX;
+            }
+            while col > row * RADIX {
+                scale /= RADIX;
+                col /= RADIX * RADIX;
+            }
+            if (col + row) / scale < 0.95 * total {
+                done = false;
+                for j in 0..n {
+                    a[i*n + j] /= scale;
+                    a[j*n + i] *= scale;
+                }
+            }
+        }
+    }
+}
+
+/// `|magnitude|` with the sign of `sign`, positive for zero.
+fn with_sign(magnitude: f64, sign: f64) -> f64 {
+    if sign >= 0.0 { magnitude.abs() } else { -magnitude.abs() }
+}
+
+/// The eigenvalues of an upper Hessenberg matrix as real and imaginary
+/// parts, complex ones in conjugate pairs. Francis double-shift QR
+/// iteration on the balanced matrix, deflating one real eigenvalue or a
+/// pair whenever a subdiagonal entry becomes negligible, as in the EISPACK
+/// `hqr`. Fails if an eigenvalue takes more than 30 iterations.
+pub fn hessenberg_eigenvalues(n: usize, h: &[f64]) -> Result<Vec<(f64, f64)>, CalcError> {
+    let mut a = h.to_vec();
+    balance(n, &mut a);
+    let norm = (0..n).map(|i| (i.saturating_sub(1)..n).map(|j| a[i*n + j].abs()).sum::<f64>()).sum::<f64>();
+    let mut values = vec![(0.0, 0.0); n];
+    // The exceptional shifts so far, taken out of the diagonal
+    let mut shift = 0.0;
+    let mut end = n;
+    while end > 0 {
+        let last = end - 1;
+        let mut iterations = 0;
+        loop {
+            // The start of the unreduced block that ends at `last`
+            let mut l = last;
+            while l > 0 {
+                let mut s = a[(l - 1)*n + l - 1].abs() + a[l*n + l].abs();
+                if s == 0.0 {
+                    s = norm;
+                }
+                if a[l*n + l - 1].abs() <= f64::EPSILON * s {
+                    a[l*n + l - 1] = 0.0;
+                    break;
+                }
+                l -= 1;
+            }
+            let mut x = a[last*n + last];
+            if l == last {
+                values[last] = (x + shift, 0.0);
+                end -= 1;
+                break;
+            }
+            let mut y = a[(last - 1)*n + last - 1];
+            let mut w = a[last*n + last - 1] * a[(last - 1)*n + last];
+            if l == last - 1 {
+                // The eigenvalues of the trailing 2x2 block
+                let p = 0.5 * (y - x);
+                let q = p * p + w;
+                let z = q.abs().sqrt();
+                x += shift;
+                if q >= 0.0 {
+                    let z = p + with_sign(z, p);
+                    values[last - 1] = (x + z, 0.0);
+                    values[last] = (if z != 0.0 { x - w / z } else { x + z }, 0.0);
+                } else {
+                    values[last - 1] = (x + p, z);
+                    values[last] = (x + p, -z);
+                }
+                end -= 2;
+                break;
+            }
+            if iterations == 30 {
+                return Err(not_converged("eigenvalues", n, n));
+            }
+            check_interrupt()?;
+            if iterations == 10 || iterations == 20 {
+                shift += x;
+                for i in 0..=last {
+                    a[i*n + i] -= x;
+                }
+                let s = a[last*n + last - 1].abs() + a[(last - 1)*n + last - 2].abs();
+                x = 0.75 * s;
+                y = x;
+                w = -0.4375 * s * s;
+            }
+            iterations += 1;
+
+            // Look for two consecutive small subdiagonal entries to start at
+            let mut m = last - 2;
+            let (mut p, mut q, mut r);
+            loop {
+                let z = a[m*n + m];
+                let (rr, ss) = (x - z, y - z);
+                p = (rr * ss - w) / a[(m + 1)*n + m] + a[m*n + m + 1];
+                q = a[(m + 1)*n + m + 1] - z - rr - ss;
+                r = a[(m + 2)*n + m + 1];
+                let s = p.abs() + q.abs() + r.abs();
+                p /= s;
+                q /= s;
+                r /= s;
+                if m == l {
+                    break;
+                }
+                let u = a[m*n + m - 1].abs() * (q.abs() + r.abs());
+                let v = p.abs() * (a[(m - 1)*n + m - 1].abs() + z.abs() + a[(m + 1)*n + m + 1].abs());
+                if u <= f64::EPSILON * v {
+                    break;
+                }
+                m -= 1;
+            }
+            for i in m..last - 1 {
+                a[(i + 2)*n + i] = 0.0;
+                if i != m {
+                    a[(i + 2)*n + i - 1] = 0.0;
+                }
+            }
+
+            // Chase the bulge down with Householder reflections
+            for k in m..last {
+                if k != m {
+                    p = a[k*n + k - 1];
+                    q = a[(k + 1)*n + k - 1];
+                    r = if k + 1 != last { a[(k + 2)*n + k - 1] } else { 0.0 };
+                    x = p.abs() + q.abs() + r.abs();
+                    if x != 0.0 {
+                        p /= x;
+                        q /= x;
+                        r /= x;
+                    }
+                }
+                let s = with_sign((p * p + q * q + r * r).sqrt(), p);
+                if s == 0.0 {
+                    continue;
+                }
+                if k != m {
+                    a[k*n + k - 1] = -s * x;
+                } else if l != m {
+                    a[k*n + k - 1] = -a[k*n + k - 1];
+                }
+                p += s;
+                x = p / s;
+                y = q / s;
+                let z = r / s;
+                q /= p;
+                r /= p;
+                for j in k..=last {
+                    let mut p = a[k*n + j] + q * a[(k + 1)*n + j];
+                    if k + 1 != last {
+                        p += r * a[(k + 2)*n + j];
+                        a[(k + 2)*n + j] -= p * z;
+                    }
+                    a[(k + 1)*n + j] -= p * y;
+                    a[k*n + j] -= p * x;
+                }
+                for i in l..=last.min(k + 3) {
+                    let mut p = x * a[i*n + k] + y * a[i*n + k + 1];
+                    if k + 1 != last {
+                        p += z * a[i*n + k + 2];
+                        a[i*n + k + 2] -= p * r;
+                    }
+                    a[i*n + k + 1] -= p * q;
+                    a[i*n + k] -= p;
+                }
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn not_converged(what: &str, m: usize, n: usize) -> CalcError {
+    CalcError::InvalidArgument(format!("The {what} of the {m}x{n} matrix did not converge"))
+}
+
+/// NaN and infinite entries leave nothing for the iterations to converge
+/// to, and make some nalgebra kernels panic.
+fn check_finite(what: &str, a: &[f64]) -> Result<(), CalcError> {
+    match a.iter().all(|x| x.is_finite()) {
+        true => Ok(()),
+        false => Err(CalcError::InvalidArgument(format!("The {what} need a matrix of finite numbers"))),
+    }
+}
+
+fn multiply(n: usize, a: &[f64], b: &[f64]) -> Vec<f64> {
+    (0..n).flat_map(|i| (0..n).map(move |j| (0..n).map(|k| a[i*n + k] * b[k*n + j]).sum())).collect()
+}
+
+/// `e^A` by scaling and squaring: `A` is halved until its infinity norm is at
+/// most 1/2, the degree-6 diagonal Padé approximant is evaluated there, and
+/// the result is squared back up. Fails for non-finite entries, and for
+/// norms above 2^63, which would take more than `MAX_SQUARINGS` squarings.
+pub fn expm(n: usize, a: &[f64]) -> Result<Vec<f64>, CalcError> {
+    const DEGREE: i32 = 6;
+    const MAX_SQUARINGS: i32 = 64;
+    if !a.iter().all(|x| x.is_finite()) {
+        return Err(CalcError::InvalidArgument("`expm` needs a matrix of finite numbers".to_string()));
+    }
+    let norm = (0..n).map(|i| a[i*n..(i + 1)*n].iter().map(|x| x.abs()).sum::<f64>()).fold(0.0, f64::max);
+    let squarings = if norm > 0.5 { (norm / 0.5).log2().ceil() } else { 0.0 };
+    if squarings > MAX_SQUARINGS as f64 {
+        return Err(CalcError::InvalidArgument(format!("`expm` needs absolute row sums of at most 2^63, got {norm:e}")));
+    }
+    let squarings = squarings as i32;
+    let scaled = a.iter().map(|x| x / 2f64.powi(squarings)).collect::<Vec<_>>();
+
+    let mut numerator = identity(n);
+    let mut denominator = identity(n);
+    let mut power = identity(n);
+    let mut coefficient = 1.0;
+    for k in 1..=DEGREE {
+        coefficient *= (DEGREE - k + 1) as f64 / (k * (2 * DEGREE - k + 1)) as f64;
+        power = multiply(n, &scaled, &power);
+        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+        for (i, elem) in power.iter().enumerate() {
+            numerator[i] += coefficient * elem;
+            denominator[i] += sign * coefficient * elem;
+        }
+    }
+    let mut result = lu(n, &denominator)?.solve(&numerator, n)?;
+    for _ in 0..squarings {
+        check_interrupt()?;
+        result = multiply(n, &result, &result);
+    }
+    Ok(result)
+}
+
+/// Singular values at or below this are treated as zero, as in `rank`.
+pub fn rank_tolerance(m: usize, n: usize, sigma: &[f64]) -> f64 {
+    sigma.first().copied().unwrap_or(0.0) * m.max(n) as f64 * f64::EPSILON
+}
+
+/// Moore–Penrose pseudo-inverse `V Σ⁺ Uᵀ` of an `m x n` matrix, which is
+/// `n x m`. Negligible singular values are dropped rather than inverted.
+pub fn pinv(m: usize, n: usize, a: &[f64]) -> Result<Vec<f64>, CalcError> {
+    let Svd { u, sigma, vt } = svd(m, n, a)?;
+    let k = sigma.len();
+    let tolerance = rank_tolerance(m, n, &sigma);
+    let mut result = vec![0.0; n * m];
+    for (s, &value) in sigma.iter().enumerate().filter(|(_, value)| **value > tolerance) {
+        for i in 0..n {
+            let scale = vt[s*n + i] / value;
+            for j in 0..m {
+                result[i*m + j] += scale * u[j*k + s];
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() <= 1e-9 * e.abs().max(1.0), "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn lu_determinant_and_solve() {
+        let a = [2.0, 1.0, 1.0, 4.0, -6.0, 0.0, -2.0, 7.0, 2.0];
+        let lu = lu(3, &a).unwrap();
+        assert!((lu.det() - -16.0).abs() < 1e-12);
+        assert!(!lu.is_singular());
+        assert_close(&lu.solve(&[5.0, -2.0, 9.0], 1).unwrap(), &[1.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn lu_factors_with_row_swap() {
+        let lu = lu(2, &[1.0, 1.0, 2.0, 4.0]).unwrap();
+        assert_eq!(lu.permutation(), [0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(lu.lower(), [1.0, 0.0, 0.5, 1.0]);
+        assert_eq!(lu.upper(), [2.0, 4.0, 0.0, -1.0]);
+        assert_eq!(lu.sign, -1.0);
+    }
+
+    #[test]
+    fn lu_of_singular_matrix() {
+        let lu = lu(2, &[1.0, 2.0, 2.0, 4.0]).unwrap();
+        assert_eq!(lu.det(), 0.0);
+        assert!(lu.is_singular());
+    }
+
+    #[test]
+    fn qr_reproduces_matrix() {
+        let a = [12.0, -51.0, 4.0, 6.0, 167.0, -68.0, -4.0, 24.0, -41.0];
+        let (q, r) = qr(3, 3, &a).unwrap();
+        let product = |a: &[f64], b: &[f64]| {
+            (0..9).map(|k| (0..3).map(|j| a[k / 3 * 3 + j] * b[j*3 + k % 3]).sum()).collect::<Vec<f64>>()
+        };
+        assert_close(&product(&q, &r), &a);
+        let qt = (0..9).map(|k| q[k % 3 * 3 + k / 3]).collect::<Vec<_>>();
+        assert_close(&product(&qt, &q), &identity(3));
+        assert!((0..3).all(|i| (0..i).all(|j| r[i*3 + j] == 0.0)));
+    }
+
+    #[test]
+    fn svd_singular_values() {
+        let svd = svd(2, 2, &[3.0, 0.0, 4.0, 5.0]).unwrap();
+        assert_close(&svd.sigma, &[45f64.sqrt(), 5f64.sqrt()]);
+    }
+
+    #[test]
+    fn symmetric_eigenvalues_largest_first() {
+        let (values, vectors) = symmetric_eigen(2, &[2.0, 1.0, 1.0, 2.0]).unwrap();
+        assert_close(&values, &[3.0, 1.0]);
+        let half = 0.5f64.sqrt();
+        assert_close(&vectors.iter().map(|x| x.abs()).collect::<Vec<_>>(), &[half; 4]);
+    }
+
+    #[test]
+    fn expm_of_rotation_generator() {
+        let result = expm(2, &[0.0, 1.0, -1.0, 0.0]).unwrap();
+        let (sin, cos) = 1f64.sin_cos();
+        assert_close(&result, &[cos, sin, -sin, cos]);
+    }
+
+    #[test]
+    fn expm_rejects_non_finite_and_huge_matrices() {
+        assert!(matches!(expm(2, &[f64::INFINITY, 0.0, 0.0, 0.0]), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(expm(2, &[f64::NAN, 0.0, 0.0, 0.0]), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(expm(2, &[1e300, 0.0, 0.0, 1e300]), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn pinv_of_column() {
+        assert_close(&pinv(2, 1, &[3.0, 4.0]).unwrap(), &[0.12, 0.16]);
+    }
+
+    #[test]
+    fn hessenberg_complex_pair() {
+        let values = hessenberg_eigenvalues(2, &[0.0, -1.0, 1.0, 0.0]).unwrap();
+        let mut values = values.into_iter().map(|(re, im)| [re, im]).collect::<Vec<_>>();
+        values.sort_by(|a, b| a[1].total_cmp(&b[1]));
+        assert_close(&values.concat(), &[0.0, -1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn rejects_non_finite_input() {
+        for a in [[f64::NAN, 1.0, 1.0, 1.0], [f64::INFINITY, 1.0, 1.0, 1.0]] {
+            assert!(matches!(svd(2, 2, &a), Err(CalcError::InvalidArgument(_))));
+            assert!(matches!(symmetric_eigen(2, &a), Err(CalcError::InvalidArgument(_))));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! The nalgebra versions of the factorizations, converting to and from the
+//! row-major slices the rest of `linalg` works with.
+//!
+//! nalgebra cannot be interrupted, so the iterative kernels cap its
+//! iterations like the native ones and poll for Ctrl-C once it returns.
+
+use super::{check_finite, not_converged, Lu, Svd};
+use crate::{check_interrupt, CalcError};
+use nalgebra::DMatrix;
+
+fn to_dmatrix(m: usize, n: usize, a: &[f64]) -> DMatrix<f64> {
+    DMatrix::from_row_slice(m, n, a)
+}
+
+fn to_row_major(mat: &DMatrix<f64>) -> Vec<f64> {
+    mat.transpose().as_slice().to_vec()
+}
+
+/// Picks the columns of `mat` in `order`.
+fn reorder_columns(mat: &DMatrix<f64>, order: &[usize]) -> DMatrix<f64> {
+    DMatrix::from_fn(mat.nrows(), order.len(), |i, j| mat[(i, order[j])])
+}
+
+pub fn lu(n: usize, a: &[f64]) -> Result<Lu, CalcError> {
+    let factors = to_dmatrix(n, n, a).lu();
+    let packed = factors.l() - DMatrix::identity(n, n) + factors.u();
+    // Permuting the row indices themselves shows where each row came from
+    let mut perm = DMatrix::from_fn(n, 1, |i, _| i as f64);
+    factors.p().permute_rows(&mut perm);
+    Ok(Lu {
+        n,
+        lu: to_row_major(&packed),
+        perm: perm.iter().map(|row| *row as usize).collect(),
+        sign: factors.p().determinant(),
+    })
+}
+
+pub fn cholesky(n: usize, a: &[f64]) -> Option<Vec<f64>> {
+    let factor = to_dmatrix(n, n, a).cholesky()?;
+    Some(to_row_major(&factor.l()))
+}
+
+pub fn svd(m: usize, n: usize, a: &[f64]) -> Result<Svd, CalcError> {
+    check_finite("singular values", a)?;
+    let svd = to_dmatrix(m, n, a).try_svd(true, true, f64::EPSILON, 100 * m.max(n));
+    check_interrupt()?;
+    let svd = svd.ok_or_else(|| not_converged("singular values", m, n))?;
+    let sigma = svd.singular_values;
+    let mut order = (0..sigma.len()).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| sigma[j].total_cmp(&sigma[i]));
+    let u = reorder_columns(&svd.u.unwrap(), &order);
+    let vt = reorder_columns(&svd.v_t.unwrap().transpose(), &order).transpose();
+    Ok(Svd {
+        u: to_row_major(&u),
+        sigma: order.iter().map(|&i| sigma[i]).collect(),
+        vt: to_row_major(&vt),
+    })
+}
+
+pub fn symmetric_eigen(n: usize, a: &[f64]) -> Result<(Vec<f64>, Vec<f64>), CalcError> {
+    check_finite("eigenvalues", a)?;
+    let eigen = to_dmatrix(n, n, a).try_symmetric_eigen(f64::EPSILON, 100 * n);
+    check_interrupt()?;
+    let eigen = eigen.ok_or_else(|| not_converged("eigenvalues", n, n))?;
+    let values = eigen.eigenvalues;
+    let mut order = (0..n).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| values[j].total_cmp(&values[i]));
+    let vectors = reorder_columns(&eigen.eigenvectors, &order);
+    Ok((order.iter().map(|&i| values[i]).collect(), to_row_major(&vectors)))
+}
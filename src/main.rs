@@ -1,6 +1,39 @@
+mod bigfloat;
+mod bignum;
+mod datetime;
+mod delimited;
+mod dual;
+mod interval;
+mod linalg;
+mod npy;
+mod numtheory;
+mod poly;
+mod quat;
+mod random;
+mod special;
+mod units;
+
+use bigfloat::BigFloat;
 use clap::{arg, Command};
+use datetime::DateTime;
+use dual::Dual;
+use interval::Interval;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Euclid, FromPrimitive, Signed, ToPrimitive, Zero};
+use poly::Poly;
+use quat::Quat;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use units::{Quantity, Unit};
 
-#[derive(Debug)]
+/// Rows of the product per unit of work in `Matrix::matmul`, and the width of
+/// its tiles over the inner dimension.
+const MATMUL_BLOCK: usize = 64;
+/// Multiply-adds below which `Matrix::matmul` stays on the current thread.
+const PARALLEL_MATMUL_WORK: usize = 1 << 21;
+
+#[derive(Debug, Clone, PartialEq)]
 struct Matrix {
     row: usize,
     col: usize,
@@ -10,67 +43,199 @@ struct Matrix {
 impl Matrix {
     fn from(row: usize, col: usize, data: Box<[f64]>) -> Matrix {
         Matrix{
-            row: row,
-            col: col,
-            data: data
+            row,
+            col,
+            data
         }
     }
-}
 
-impl std::fmt::Display for Matrix {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "")?;
-        for i in 0..self.row {
-            write!(f, "   ")?;
-            for j in 0..self.col {
-                write!(f, " {} ", self.data[i*self.col + j])?;
+    fn shape(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    /// A matrix of the same shape with `fun` applied to every element.
+    fn map(&self, fun: impl Fn(f64) -> f64) -> Matrix {
+        Matrix::from(self.row, self.col, self.data.iter().map(|elem| fun(*elem)).collect())
+    }
+
+    /// The row-by-column product, polling for Ctrl-C once per block of rows.
+    /// Blocks run in parallel once the product is big enough to pay for it.
+    fn matmul(&self, rhs: &Matrix) -> Result<Matrix, CalcError> {
+        if self.col != rhs.row {
+            return Err(CalcError::InnerDimensionMismatch { lhs: self.shape(), rhs: rhs.shape() });
+        }
+        let mut data = vec![0.0; self.row * rhs.col];
+        let work = self.row.saturating_mul(self.col).saturating_mul(rhs.col);
+        let blocks = data.chunks_mut(MATMUL_BLOCK * rhs.col).enumerate();
+        if work < PARALLEL_MATMUL_WORK {
+            for (block, out) in blocks {
+                check_interrupt()?;
+                self.multiply_block(rhs, block * MATMUL_BLOCK, out);
+            }
+        } else {
+            data.par_chunks_mut(MATMUL_BLOCK * rhs.col).enumerate().try_for_each(|(block, out)| {
+                check_interrupt()?;
+                self.multiply_block(rhs, block * MATMUL_BLOCK, out);
+                Ok::<(), CalcError>(())
+            })?;
+        }
+        Ok(Matrix::from(self.row, rhs.col, data.into_boxed_slice()))
+    }
+
+    /// Accumulates the product rows from `first_row` on into `out`, walking
+    /// the inner dimension in tiles so the rows of `rhs` in use stay in cache.
+    fn multiply_block(&self, rhs: &Matrix, first_row: usize, out: &mut [f64]) {
+        for tile in (0..self.col).step_by(MATMUL_BLOCK) {
+            let inner = tile..(tile + MATMUL_BLOCK).min(self.col);
+            for (offset, out_row) in out.chunks_mut(rhs.col).enumerate() {
+                let i = first_row + offset;
+                for k in inner.clone() {
+                    let scale = self.data[i*self.col + k];
+                    for (elem, rhs_elem) in out_row.iter_mut().zip(&rhs.data[k*rhs.col..(k + 1)*rhs.col]) {
+                        *elem += scale * rhs_elem;
+                    }
+                }
             }
-            write!(f, "\n")?;
         }
-        write!(f, "")
+    }
+
+    /// Element-wise operations need both operands to have the same dimensions.
+    fn check_same_shape(&self, rhs: &Matrix) -> Result<(), CalcError> {
+        if self.shape() != rhs.shape() {
+            return Err(CalcError::DimensionMismatch { lhs: self.shape(), rhs: rhs.shape() });
+        }
+        Ok(())
+    }
+
+    /// Combines two same-shaped matrices element by element.
+    fn zip_with(&self, rhs: &Matrix, fun: impl Fn(f64, f64) -> f64) -> Result<Matrix, CalcError> {
+        self.check_same_shape(rhs)?;
+        let data = self.data.iter().zip(rhs.data.iter()).map(|(lhs, rhs)| fun(*lhs, *rhs)).collect();
+        Ok(Matrix::from(self.row, self.col, data))
+    }
+}
+
+/// Formats with `digits` significant digits like C's `%g`, or as the shortest
+/// round-tripping representation when `digits` is `None`.
+fn format_number(num: f64, digits: Option<usize>) -> String {
+    let digits = match digits {
+        Some(digits) if num != 0.0 && num.is_finite() => digits.max(1),
+        _ => return format!("{}", num),
+    };
+    let exponent = num.abs().log10().floor() as i32;
+    let (mantissa, suffix) = if (-5..digits as i32).contains(&exponent) {
+        let decimals = (digits as i32 - 1 - exponent).max(0) as usize;
+        (format!("{:.*}", decimals, num), String::new())
+    } else {
+        let formatted = format!("{:.*e}", digits - 1, num);
+        let (mantissa, exponent) = formatted.split_once('e').unwrap();
+        (mantissa.to_string(), format!("e{exponent}"))
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &mantissa
+    };
+    format!("{mantissa}{suffix}")
+}
+
+/// Rows and columns kept at each edge when a large matrix is summarized.
+const EDGE_ITEMS: usize = 3;
+
+/// The indices `Matrix::write_grid` shows out of `len`: all of them, or the
+/// first and last `edge` with a `None` standing for the ones in between.
+fn shown_indices(len: usize, edge: Option<usize>) -> Vec<Option<usize>> {
+    match edge {
+        Some(edge) if len > 2 * edge => (0..edge).map(Some)
+            .chain(std::iter::once(None))
+            .chain((len - edge..len).map(Some))
+            .collect(),
+        _ => (0..len).map(Some).collect(),
     }
 }
 
-impl std::ops::Add<Matrix> for Matrix {
-    type Output = Matrix;
+impl Matrix {
+    /// One row per line with every column right-aligned to its widest entry,
+    /// or with `edge` set, only that many rows and columns at each side and
+    /// `...` for the rest. The alternate form also brackets each row.
+    fn write_grid(&self, f: &mut std::fmt::Formatter<'_>, edge: Option<usize>) -> std::fmt::Result {
+        let rows = shown_indices(self.row, edge);
+        let cols = shown_indices(self.col, edge);
+        let cell = |i: usize, j: Option<usize>| match j {
+            Some(j) => format_number(self.data[i*self.col + j], f.precision()),
+            None => "...".to_string(),
+        };
+        let cells = rows.iter()
+            .map(|i| i.map(|i| cols.iter().map(|j| cell(i, *j)).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let widths = (0..cols.len())
+            .map(|j| cells.iter().flatten().map(|row| row[j].chars().count()).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let (open, close) = if f.alternate() { (" [", "]") } else { ("", "") };
+        writeln!(f)?;
+        for row in &cells {
+            let Some(row) = row else {
+                writeln!(f, "    ...")?;
+                continue;
+            };
+            write!(f, "   {open}")?;
+            for (cell, width) in row.iter().zip(&widths) {
+                write!(f, " {cell:>width$} ")?;
+            }
+            writeln!(f, "{close}")?;
+        }
+        Ok(())
+    }
+}
 
-    fn add(self, rhs: Matrix) -> Self::Output {
-        Matrix::from(self.row, self.col, std::iter::zip(self.data.iter(), rhs.data.iter())
-            .map(|(a,b)| a+b)
-            .collect::<Vec<f64>>()
-            .into_boxed_slice())
+/// Honours the formatter's precision as a count of significant digits.
+impl std::fmt::Display for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_grid(f, None)
     }
 }
 
-impl std::ops::Sub<Matrix> for Matrix {
-    type Output = Matrix;
+/// A value as the print operators show it: a matrix with more than
+/// `threshold` elements is cut down to its corners.
+struct Summary<'a> {
+    value: &'a Value,
+    threshold: Option<usize>,
+}
 
-    fn sub(self, rhs: Matrix) -> Self::Output {
-        Matrix::from(self.row, self.col, std::iter::zip(self.data.iter(), rhs.data.iter())
-            .map(|(a,b)| a-b)
-            .collect::<Vec<f64>>()
-            .into_boxed_slice())
+impl std::fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.value {
+            Value::Matrix(mat) if self.threshold.is_some_and(|limit| mat.data.len() > limit) => {
+                mat.write_grid(f, Some(EDGE_ITEMS))
+            },
+            value => std::fmt::Display::fmt(value, f),
+        }
     }
 }
 
+#[derive(Clone, PartialEq)]
 enum Value {
     Number(f64),
     Matrix(Matrix),
+    BigInt(BigInt),
+    BigFloat(BigFloat),
+    Bool(bool),
+    Str(String),
+    Interval(Interval),
+    Quantity(Quantity),
+    Dual(Dual),
+    Poly(Poly),
+    Quat(Quat),
+    Date(DateTime),
 }
 
 impl Value {
     fn is_number(&self) -> bool {
-        match self {
-            Value::Number(_) => true,
-            _ => false,
-        }
+        matches!(self, Value::Number(_))
     }
 
     fn is_matrix(&self) -> bool {
-        match self {
-            Value::Matrix(_) => true,
-            _ => false,
-        }
+        matches!(self, Value::Matrix(_))
     }
 
     fn to_number(&self) -> Option<f64> {
@@ -79,399 +244,5941 @@ impl Value {
             _ => None,
         }
     }
+}
 
-    fn to_matrix<'lt>(&'lt self) -> Option<&'lt Matrix> {
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Value::Matrix(ref mat) => Some(mat),
-            _ => None
+            Self::Number(num) => write!(f, "{}", format_number(*num, f.precision())),
+            Self::Matrix(mat) => std::fmt::Display::fmt(mat, f),
+            Self::BigInt(int) => write!(f, "{int}"),
+            Self::BigFloat(x) => write!(f, "{}", bigfloat::format(x, f.precision())),
+            Self::Bool(bool) => write!(f, "{bool}"),
+            Self::Str(string) => write!(f, "{string:?}"),
+            Self::Interval(interval) => {
+                let digits = f.precision();
+                write!(f, "[{}, {}]", format_number(interval.lo, digits), format_number(interval.hi, digits))
+            },
+            Self::Quantity(quantity) => write!(f, "{} {}", format_number(quantity.value, f.precision()), quantity.unit),
+            Self::Dual(dual) => {
+                let (sign, deriv) = if dual.deriv.is_sign_negative() { ('-', -dual.deriv) } else { ('+', dual.deriv) };
+                write!(f, "{} {sign} {}ε", format_number(dual.value, f.precision()), format_number(deriv, f.precision()))
+            },
+            Self::Poly(poly) => std::fmt::Display::fmt(poly, f),
+            Self::Quat(quat) => write!(f, "{}", quat_text(quat, |c| format_number(c, f.precision()))),
+            Self::Date(date) => write!(f, "{date}"),
         }
     }
 }
 
-impl std::fmt::Display for Value {
+#[derive(Debug)]
+enum CalcError {
+    StackUnderflow { operator: String, needed: usize, found: usize },
+    TypeMismatch(String),
+    DimensionMismatch { lhs: (usize, usize), rhs: (usize, usize) },
+    InnerDimensionMismatch { lhs: (usize, usize), rhs: (usize, usize) },
+    NotSquare { operator: String, shape: (usize, usize) },
+    Singular(String),
+    UnknownOperator { name: String, suggestions: Vec<&'static str> },
+    Syntax(String),
+    InvalidArgument(String),
+    NonFinite(String),
+    DivisionByZero,
+    DepthExceeded(usize),
+    Interrupted,
+    NothingToUndo,
+    NothingToRedo,
+    UnknownCommand(String),
+    Io(String),
+    MissingName(String),
+    EmptyRegister(usize),
+    UnitMismatch { operator: String, lhs: String, rhs: String },
+}
+
+impl std::fmt::Display for CalcError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Number(num) => write!(f, "{}", num),
-            Self::Matrix(mat) => write!(f, "{}", mat),
-        }
-    }
-}
-
-const HANDLERS: phf::Map<&'static str, fn(&mut Vec<Value>)> = phf::phf_map!{
-    "pi" => exec_pi,
-    "+" => exec_plus,
-    "-" => exec_sub,
-    "*" => exec_mul,
-    "/" => exec_div,
-    "^" => exec_pow,
-    "sin" => exec_sin,
-    "cos" => exec_cos,
-    "tan" => exec_tan,
-    "cot" => exec_cot,
-    "exp" => exec_exp,
-    "asin" => exec_asin,
-    "acos" => exec_acos,
-    "atan" => exec_atan,
-    "acot" => exec_acot,
-    "atan2" => exec_atan2,
-    "p" => exec_print,
-    "matrix" => exec_matrix,
-};
-
-/**
-Stack changes:
-
-- 1 push
- */
-fn exec_pi(stack: &mut Vec<Value>) {
-    stack.push(Value::Number(std::f64::consts::PI));
+            Self::StackUnderflow { operator, needed, found } => {
+                let noun = if *needed == 1 { "operand" } else { "operands" };
+                write!(f, "`{operator}` needs {needed} {noun}, stack has {found}")
+            },
+            Self::TypeMismatch(msg) => write!(f, "{}", msg),
+            Self::DimensionMismatch { lhs, rhs } => {
+                write!(f, "Dimension mismatch {}x{} vs {}x{}", lhs.0, lhs.1, rhs.0, rhs.1)
+            },
+            Self::InnerDimensionMismatch { lhs, rhs } => {
+                write!(f, "Cannot multiply {}x{} by {}x{}: inner dimensions differ", lhs.0, lhs.1, rhs.0, rhs.1)
+            },
+            Self::NotSquare { operator, shape } => {
+                write!(f, "`{operator}` needs a square matrix, got {}x{}", shape.0, shape.1)
+            },
+            Self::Singular(operator) => write!(f, "`{operator}` needs a non-singular matrix"),
+            Self::UnknownOperator { name, suggestions } => {
+                write!(f, "Unknown operator `{name}`")?;
+                if !suggestions.is_empty() {
+                    let quoted = suggestions.iter().map(|s| format!("`{s}`")).collect::<Vec<_>>();
+                    write!(f, "; did you mean {}?", quoted.join(" or "))?;
+                }
+                Ok(())
+            },
+            Self::Syntax(msg) => write!(f, "{}", msg),
+            Self::InvalidArgument(msg) => write!(f, "{}", msg),
+            Self::NonFinite(operator) => write!(f, "`{operator}` produced a NaN or infinite result"),
+            Self::DivisionByZero => write!(f, "Division by zero"),
+            Self::DepthExceeded(limit) => write!(f, "Evaluation depth exceeded (limit {limit})"),
+            Self::Interrupted => write!(f, "Interrupted"),
+            Self::NothingToUndo => write!(f, "Nothing to undo"),
+            Self::NothingToRedo => write!(f, "Nothing to redo"),
+            Self::UnknownCommand(name) => write!(f, "Unknown command `:{name}`"),
+            Self::Io(msg) => write!(f, "{}", msg),
+            Self::MissingName(word) => write!(f, "`{word}` needs a name after it"),
+            Self::EmptyRegister(register) => write!(f, "Register {register} is empty"),
+            Self::UnitMismatch { operator, lhs, rhs } => {
+                write!(f, "`{operator}` needs matching units, got {lhs} and {rhs}")
+            },
+        }
+    }
 }
 
-/**
-Variables: `col` then `row`
+impl std::error::Error for CalcError {}
 
-Stack changes:
+/// Exit codes for `-s` and script mode. 2 is left to clap's usage errors.
+const EXIT_RUNTIME_ERROR: i32 = 1;
+const EXIT_PARSE_ERROR: i32 = 3;
+const EXIT_FILE_ERROR: i32 = 4;
 
-- `row`*`col` + 2 pop
-- 1 push
- */
-fn exec_matrix(stack: &mut Vec<Value>) {
-    let col_value = stack.pop().unwrap();
-    let row_value = stack.pop().unwrap();
-    if row_value.is_matrix() || col_value.is_matrix() {
-        panic!("Matrix size must be numbers");
-    }
-    // We checked before, so unwrap cannot panic
-    let row = row_value.to_number().unwrap() as usize;
-    let col = col_value.to_number().unwrap() as usize;
-    let mut mat = Vec::<f64>::with_capacity(row*col);
-    for _ in 0..(row*col) {
-        mat.push(stack.pop().unwrap().to_number().expect("Matrix elements must be numbers"));
+impl CalcError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Self::UnknownOperator { .. } | Self::Syntax(_) => EXIT_PARSE_ERROR,
+            Self::Io(_) => EXIT_FILE_ERROR,
+            _ => EXIT_RUNTIME_ERROR,
+        }
     }
-    mat.reverse();
-    stack.push(Value::Matrix(Matrix::from(row, col, mat.into_boxed_slice())));
 }
 
-/**
-Stack changes:
+/// Set by the Ctrl-C handler in interactive mode. Evaluation polls it between
+/// tokens and long-running handlers should poll it inside their loops.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
-- 2 pop
-- 1 push
- */
-fn exec_plus(stack: &mut Vec<Value>) {
-    let val2 = stack.pop().unwrap();
-    let val1 = stack.pop().unwrap();
-    match (val1, val2) {
-        (Value::Number(lhs), Value::Number(rhs)) => {
-            stack.push(Value::Number(lhs+rhs));
-        }
-        (Value::Matrix(lhs), Value::Matrix(rhs)) => {
-            stack.push(Value::Matrix(lhs+rhs));
-        }
-        (lhs,rhs) => panic!("Unsupported operations on {} and {}", lhs, rhs),
+fn check_interrupt() -> Result<(), CalcError> {
+    if INTERRUPTED.swap(false, Ordering::Relaxed) {
+        return Err(CalcError::Interrupted);
     }
+    Ok(())
 }
 
-/**
-Stack changes:
+/// How forgiving evaluation is about questionable input.
+#[derive(Clone, Copy, PartialEq)]
+enum Strictness {
+    /// Unknown tokens are skipped with a warning.
+    Lenient,
+    Normal,
+    /// Every warning is an error, including NaN and infinite results.
+    Strict,
+}
 
-- 2 pop
-- 1 push
- */
-fn exec_sub(stack: &mut Vec<Value>) {
-    let val2 = stack.pop().unwrap();
-    let val1 = stack.pop().unwrap();
-    match (val1, val2) {
-        (Value::Number(lhs), Value::Number(rhs)) => {
-            stack.push(Value::Number(lhs-rhs));
-        }
-        (Value::Matrix(lhs), Value::Matrix(rhs)) => {
-            stack.push(Value::Matrix(lhs-rhs));
+/// The unit trigonometric operators take and inverse ones return angles in.
+#[derive(Clone, Copy, PartialEq)]
+enum AngleMode {
+    Radians,
+    Degrees,
+    Gradians,
+}
+
+impl AngleMode {
+    fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            Self::Radians => angle,
+            Self::Degrees => angle.to_radians(),
+            Self::Gradians => angle * std::f64::consts::PI / 200.0,
         }
-        (lhs,rhs) => panic!("Unsupported operations on {} and {}", lhs, rhs),
     }
-}
 
-/**
-Stack changes:
+    fn big_to_radians(self, angle: &BigFloat, bits: usize) -> BigFloat {
+        let half_turn = match self {
+            Self::Radians => return angle.clone(),
+            Self::Degrees => 180.0,
+            Self::Gradians => 200.0,
+        };
+        let pi = bigfloat::constant("pi", bits).unwrap();
+        bigfloat::div(&bigfloat::mul(angle, &pi, bits), &bigfloat::from_f64(half_turn, bits), bits)
+    }
 
-- 2 pop
-- 1 push
- */
-fn exec_mul(stack: &mut Vec<Value>) {
-    let val2 = stack.pop().unwrap();
-    let val1 = stack.pop().unwrap();
-    match (val1, val2) {
-        (Value::Number(lhs), Value::Number(rhs)) => {
-            stack.push(Value::Number(lhs*rhs));
+    /// Converting is a multiplication by a rounded factor, so the result is
+    /// widened by the error of that factor.
+    fn interval_to_radians(self, angle: Interval) -> Interval {
+        match self {
+            Self::Radians => angle,
+            _ => angle.mul(Interval::around(self.to_radians(1.0))),
         }
-        (lhs,rhs) => panic!("Unsupported operations on {} and {}", lhs, rhs),
     }
-}
 
-/**
-Stack changes:
+    fn radians_to_unit(self, angle: f64) -> f64 {
+        match self {
+            Self::Radians => angle,
+            Self::Degrees => angle.to_degrees(),
+            Self::Gradians => angle * 200.0 / std::f64::consts::PI,
+        }
+    }
 
-- 2 pop
-- 1 push
- */
-fn exec_div(stack: &mut Vec<Value>) {
-    let val2 = stack.pop().unwrap();
-    let val1 = stack.pop().unwrap();
-    match (val1, val2) {
-        (Value::Number(lhs), Value::Number(rhs)) => {
-            stack.push(Value::Number(lhs/rhs));
+    fn name(self) -> &'static str {
+        match self {
+            Self::Radians => "rad",
+            Self::Degrees => "deg",
+            Self::Gradians => "grad",
         }
-        (lhs,rhs) => panic!("Unsupported operations on {} and {}", lhs, rhs),
     }
 }
 
-/**
-Stack changes:
+/// What the integer operators do with an operand that has a fractional part.
+#[derive(Clone, Copy, PartialEq)]
+enum IntPolicy {
+    Truncate,
+    Error,
+}
 
-- 2 pop
-- 1 push
- */
-fn exec_pow(stack: &mut Vec<Value>) {
-    let val2 = stack.pop().unwrap();
-    let val1 = stack.pop().unwrap();
-    match (val1, val2) {
-        (Value::Number(lhs), Value::Number(rhs)) => {
-            stack.push(Value::Number(lhs.powf(rhs)));
-        }
-        (lhs,rhs) => panic!("Unsupported operations on {} and {}", lhs, rhs),
-    }
+/// Everything an operator can read or change: the stack and the evaluation settings.
+struct State {
+    stack: Vec<Value>,
+    /// Treat NaN or infinite results as errors instead of pushing them.
+    strict_fp: bool,
+    /// Make division by zero an error instead of producing an infinity.
+    div_trap: bool,
+    /// How many evaluations are currently nested inside each other.
+    depth: usize,
+    max_depth: usize,
+    /// Keep whatever a failing REPL line left on the stack instead of rolling back.
+    keep_partial: bool,
+    strictness: Strictness,
+    /// The active stack after each REPL line that changed it, oldest first, bounded
+    /// by `HISTORY_LIMIT`. `history[history_pos]` is the state `undo` and `redo`
+    /// step away from.
+    history: Vec<Snapshot>,
+    history_pos: usize,
+    /// Name of the active stack, `main` unless `stack NAME` switched away from it.
+    stack_name: String,
+    /// The inactive named stacks that hold anything.
+    parked: std::collections::HashMap<String, Vec<Value>>,
+    /// dc-style numbered registers, shared by every stack.
+    registers: std::collections::HashMap<usize, Value>,
+    angle: AngleMode,
+    /// Significant digits shown when printing, everything when `None`.
+    precision: Option<usize>,
+    /// Print matrix rows between brackets.
+    brackets: bool,
+    /// Matrices with more elements than this print only their corners.
+    print_threshold: Option<usize>,
+    rng: random::Rng,
+    int_policy: IntPolicy,
+    /// Header lines `readcsv` drops, or `None` to skip a non-numeric first line.
+    csv_skip: Option<usize>,
+    /// Precision in bits that number literals are entered with, plain `f64`
+    /// when `None`.
+    bits: Option<usize>,
 }
 
-/**
-Stack changes:
+#[derive(Clone, PartialEq)]
+struct Snapshot {
+    stack_name: String,
+    stack: Vec<Value>,
+}
 
-- 1 pop
-- 1 push
- */
-fn exec_sin(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(value.sin()));
-        },
-        value => panic!("Unsupported operation on {}", value),
-    }
+/// The registers and settings an operator can change, put back with the stack
+/// when a REPL line fails.
+struct Checkpoint {
+    registers: std::collections::HashMap<usize, Value>,
+    strict_fp: bool,
+    div_trap: bool,
+    angle: AngleMode,
+    precision: Option<usize>,
+    brackets: bool,
+    print_threshold: Option<usize>,
+    rng: random::Rng,
+    int_policy: IntPolicy,
+    csv_skip: Option<usize>,
+    bits: Option<usize>,
 }
 
-/**
-Stack changes:
+/// Deep enough for any real nesting, and well short of a 2 MB thread stack even
+/// in debug builds.
+const DEFAULT_MAX_DEPTH: usize = 200;
+const DEFAULT_PRINT_THRESHOLD: usize = 1000;
+const HISTORY_LIMIT: usize = 100;
+const MAIN_STACK: &str = "main";
 
-- 1 pop
-- 1 push
- */
-fn exec_cos(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(value.cos()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+impl State {
+    fn new() -> State {
+        State {
+            stack: Vec::new(),
+            strict_fp: false,
+            div_trap: false,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            keep_partial: false,
+            strictness: Strictness::Normal,
+            history: vec![Snapshot { stack_name: MAIN_STACK.to_string(), stack: Vec::new() }],
+            history_pos: 0,
+            stack_name: MAIN_STACK.to_string(),
+            parked: std::collections::HashMap::new(),
+            registers: std::collections::HashMap::new(),
+            angle: AngleMode::Radians,
+            precision: None,
+            brackets: false,
+            print_threshold: Some(DEFAULT_PRINT_THRESHOLD),
+            rng: random::Rng::from_entropy(),
+            int_policy: IntPolicy::Truncate,
+            csv_skip: None,
+            bits: None,
+        }
     }
-}
 
-/**
-Stack changes:
+    /// Makes `name` the active stack, parking the current one under its own name.
+    fn switch_stack(&mut self, name: &str) {
+        if name == self.stack_name {
+            return;
+        }
+        let stack = self.parked.remove(name).unwrap_or_default();
+        let previous = std::mem::replace(&mut self.stack, stack);
+        let previous_name = std::mem::replace(&mut self.stack_name, name.to_string());
+        if !previous.is_empty() {
+            self.parked.insert(previous_name, previous);
+        }
+    }
 
-- 1 pop
-- 1 push
- */
-fn exec_tan(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(value.tan()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+    fn snapshot(&self) -> Snapshot {
+        Snapshot { stack_name: self.stack_name.clone(), stack: self.stack.clone() }
     }
-}
 
-/**
-Stack changes:
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            registers: self.registers.clone(),
+            strict_fp: self.strict_fp,
+            div_trap: self.div_trap,
+            angle: self.angle,
+            precision: self.precision,
+            brackets: self.brackets,
+            print_threshold: self.print_threshold,
+            rng: self.rng.clone(),
+            int_policy: self.int_policy,
+            csv_skip: self.csv_skip,
+            bits: self.bits,
+        }
+    }
 
-- 1 pop
-- 1 push
- */
-fn exec_cot(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(1.0/value.tan()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+    fn restore_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.registers = checkpoint.registers;
+        self.strict_fp = checkpoint.strict_fp;
+        self.div_trap = checkpoint.div_trap;
+        self.angle = checkpoint.angle;
+        self.precision = checkpoint.precision;
+        self.brackets = checkpoint.brackets;
+        self.print_threshold = checkpoint.print_threshold;
+        self.rng = checkpoint.rng;
+        self.int_policy = checkpoint.int_policy;
+        self.csv_skip = checkpoint.csv_skip;
+        self.bits = checkpoint.bits;
     }
-}
 
-/**
-Stack changes:
+    /// Makes the current stack a new undo step, dropping any redo steps.
+    fn record_history(&mut self) {
+        if self.history[self.history_pos].stack_name == self.stack_name
+            && self.history[self.history_pos].stack == self.stack {
+            return;
+        }
+        self.history.truncate(self.history_pos + 1);
+        self.history.push(self.snapshot());
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+        self.history_pos = self.history.len() - 1;
+    }
 
-- 1 pop
-- 1 push
- */
-fn exec_exp(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(value.exp()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+    /// Goes back to `history[pos]`, switching to the stack it was taken from.
+    fn restore_history(&mut self, pos: usize) {
+        let snapshot = self.history[pos].clone();
+        self.history_pos = pos;
+        self.switch_stack(&snapshot.stack_name);
+        self.stack = snapshot.stack;
     }
-}
 
-/**
-Stack changes:
+    /// Converts an operand of an integer operator, truncating or failing on a
+    /// fractional part as `int_policy` says. Strict mode always fails.
+    fn coerce_int(&self, operator: &str, value: f64) -> Result<i64, CalcError> {
+        if let Some(integer) = to_i64(value) {
+            return Ok(integer);
+        }
+        let lossy_allowed = self.int_policy == IntPolicy::Truncate
+            && self.strictness != Strictness::Strict;
+        match to_i64(value.trunc()) {
+            Some(integer) if lossy_allowed => Ok(integer),
+            Some(_) => Err(CalcError::InvalidArgument(
+                format!("`{operator}` needs an integer, got {value}")
+            )),
+            None => Err(CalcError::InvalidArgument(
+                format!("`{operator}` needs an integer up to 2^53, got {value}")
+            )),
+        }
+    }
 
-- 1 pop
-- 1 push
- */
-fn exec_exp2(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(value.exp2()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+    /// Formats a value for printing with the current display precision.
+    fn show(&self, value: &Value) -> String {
+        self.show_summary(value, self.print_threshold)
     }
-}
 
-/**
-Stack changes:
+    /// Formats `value` like `show`, summarizing matrices larger than `threshold`.
+    fn show_summary(&self, value: &Value, threshold: Option<usize>) -> String {
+        let value = Summary { value, threshold };
+        match (self.precision, self.brackets) {
+            (Some(digits), true) => format!("{:#.*}", digits, value),
+            (Some(digits), false) => format!("{:.*}", digits, value),
+            (None, true) => format!("{:#}", value),
+            (None, false) => format!("{}", value),
+        }
+    }
 
-- 1 pop
-- 1 push
- */
-fn exec_asin(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(value.asin()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+    /// Reports a recoverable problem. Strict mode turns it into an error.
+    fn warn(&self, problem: CalcError) -> Result<(), CalcError> {
+        if self.strictness == Strictness::Strict {
+            return Err(problem);
+        }
+        eprintln!("Warning: {problem}");
+        Ok(())
     }
 }
 
-/**
-Stack changes:
+type Handler = fn(&mut State) -> Result<(), CalcError>;
 
-- 1 pop
-- 1 push
- */
-fn exec_acos(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(value.acos()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+/// Every operator is registered with the number of operands it needs, which
+/// `exec_identifier` checks before calling the handler.
+const HANDLERS: phf::Map<&'static str, (usize, Handler)> = phf::phf_map!{
+    "pi" => (0, exec_pi),
+    "+" => (2, exec_plus),
+    "-" => (2, exec_sub),
+    "*" => (2, exec_mul),
+    "/" => (2, exec_div),
+    "^" => (2, exec_pow),
+    ".*" => (2, exec_elem_mul),
+    "./" => (2, exec_elem_div),
+    ".^" => (2, exec_elem_pow),
+    "mod" => (2, exec_mod),
+    "rem" => (2, exec_rem),
+    "//" => (2, exec_int_div),
+    "%" => (1, exec_percent),
+    "p%" => (1, exec_percent),
+    "addpct" => (2, exec_addpct),
+    "pctof" => (2, exec_pctof),
+    "int" => (1, exec_int),
+    "big" => (1, exec_big),
+    "float" => (1, exec_float),
+    "interval" => (2, exec_interval),
+    "bounds" => (1, exec_bounds),
+    "unit" => (1, exec_unit),
+    "to" => (2, exec_to),
+    "dual" => (1, exec_dual),
+    "parts" => (1, exec_parts),
+    "poly" => (1, exec_poly),
+    "polyval" => (2, exec_polyval),
+    "polyderiv" => (1, exec_polyderiv),
+    "polyint" => (1, exec_polyint),
+    "roots" => (1, exec_roots),
+    "quat" => (4, exec_quat),
+    "conj" => (1, exec_conj),
+    "axisangle" => (2, exec_axisangle),
+    "rotmat" => (1, exec_rotmat),
+    "toquat" => (1, exec_toquat),
+    "date" => (1, exec_date),
+    "now" => (0, exec_now),
+    "weekday" => (1, exec_weekday),
+    "days_between" => (2, exec_days_between),
+    "tobytes" => (1, exec_tobytes),
+    "tosibytes" => (1, exec_tosibytes),
+    "toiecbytes" => (1, exec_toiecbytes),
+    "idiv" => (2, exec_idiv),
+    "imod" => (2, exec_imod),
+    "sin" => (1, exec_sin),
+    "cos" => (1, exec_cos),
+    "tan" => (1, exec_tan),
+    "cot" => (1, exec_cot),
+    "exp" => (1, exec_exp),
+    "exp2" => (1, exec_exp2),
+    "exp10" => (1, exec_exp10),
+    "sqrt" => (1, exec_sqrt),
+    "expm1" => (1, exec_expm1),
+    "ln1p" => (1, exec_ln1p),
+    "asin" => (1, exec_asin),
+    "acos" => (1, exec_acos),
+    "atan" => (1, exec_atan),
+    "acot" => (1, exec_acot),
+    "sinh" => (1, exec_sinh),
+    "cosh" => (1, exec_cosh),
+    "tanh" => (1, exec_tanh),
+    "coth" => (1, exec_coth),
+    "asinh" => (1, exec_asinh),
+    "acosh" => (1, exec_acosh),
+    "atanh" => (1, exec_atanh),
+    "atan2" => (2, exec_atan2),
+    "hypot" => (2, exec_hypot),
+    "dist2d" => (4, exec_dist2d),
+    "ln" => (1, exec_ln),
+    "log" => (1, exec_log),
+    "log2" => (1, exec_log2),
+    "logb" => (2, exec_logb),
+    "abs" => (1, exec_abs),
+    "sign" => (1, exec_sign),
+    "neg" => (1, exec_neg),
+    "floor" => (1, exec_floor),
+    "ceil" => (1, exec_ceil),
+    "round" => (1, exec_round),
+    "trunc" => (1, exec_trunc),
+    "frac" => (1, exec_frac),
+    "roundto" => (2, exec_roundto),
+    "min" => (2, exec_min),
+    "max" => (2, exec_max),
+    "clamp" => (3, exec_clamp),
+    "<" => (2, exec_less),
+    ">" => (2, exec_greater),
+    "<=" => (2, exec_less_equal),
+    ">=" => (2, exec_greater_equal),
+    "==" => (2, exec_equal),
+    "!=" => (2, exec_not_equal),
+    "and" => (2, exec_and),
+    "or" => (2, exec_or),
+    "not" => (1, exec_not),
+    "true" => (0, exec_true),
+    "false" => (0, exec_false),
+    "concat" => (2, exec_concat),
+    "len" => (1, exec_len),
+    "upper" => (1, exec_upper),
+    "lower" => (1, exec_lower),
+    "tonum" => (1, exec_tonum),
+    "tostr" => (1, exec_tostr),
+    "eval" => (1, exec_eval),
+    "!" => (1, exec_fact),
+    "fact" => (1, exec_fact),
+    "gamma" => (1, exec_gamma),
+    "lgamma" => (1, exec_lgamma),
+    "erf" => (1, exec_erf),
+    "erfc" => (1, exec_erfc),
+    "beta" => (2, exec_beta),
+    "ncr" => (2, exec_ncr),
+    "npr" => (2, exec_npr),
+    "gcd" => (2, exec_gcd),
+    "lcm" => (2, exec_lcm),
+    "isprime" => (1, exec_isprime),
+    "factor" => (1, exec_factor),
+    "modpow" => (3, exec_modpow),
+    "modinv" => (2, exec_modinv),
+    "rand" => (0, exec_rand),
+    "randint" => (2, exec_randint),
+    "randn" => (0, exec_randn),
+    "randmat" => (2, exec_randmat),
+    "randnmat" => (2, exec_randnmat),
+    "seed" => (1, exec_seed),
+    "p" => (0, exec_print),
+    "pt" => (1, exec_print_top),
+    "full" => (1, exec_print_full),
+    "tolatex" => (1, exec_tolatex),
+    "tomatlab" => (1, exec_tomatlab),
+    "." => (1, exec_print_top),
+    ".," => (1, exec_print_pop),
+    "dup" => (1, exec_dup),
+    "swap" => (2, exec_swap),
+    "x" => (2, exec_swap),
+    "dup2" => (2, exec_dup2),
+    "swap2" => (4, exec_swap2),
+    "drop" => (1, exec_drop),
+    "dropn" => (1, exec_dropn),
+    "clear" => (0, exec_clear),
+    "rot" => (3, exec_rot),
+    "roll" => (1, exec_roll),
+    "over" => (2, exec_over),
+    "nip" => (2, exec_nip),
+    "tuck" => (2, exec_tuck),
+    "depth" => (0, exec_depth),
+    "pick" => (1, exec_pick),
+    "rev" => (0, exec_rev),
+    "sto" => (2, exec_sto),
+    "rcl" => (1, exec_rcl),
+    "undo" => (0, exec_undo),
+    "redo" => (0, exec_redo),
+    "matrix" => (2, exec_matrix),
+    "eye" => (1, exec_eye),
+    "zeros" => (2, exec_zeros),
+    "ones" => (2, exec_ones),
+    "fill" => (3, exec_fill),
+    "det" => (1, exec_det),
+    "inv" => (1, exec_inv),
+    "solve" => (2, exec_solve),
+    "lu" => (1, exec_lu),
+    "qr" => (1, exec_qr),
+    "chol" => (1, exec_chol),
+    "svd" => (1, exec_svd),
+    "eig" => (1, exec_eig),
+    "eigv" => (1, exec_eigv),
+    "trace" => (1, exec_trace),
+    "diag" => (1, exec_diag),
+    "reshape" => (3, exec_reshape),
+    "hcat" => (2, exec_hcat),
+    "vcat" => (2, exec_vcat),
+    "get" => (3, exec_get),
+    "set" => (4, exec_set),
+    "row" => (2, exec_row),
+    "col" => (2, exec_col),
+    "delrow" => (2, exec_delrow),
+    "delcol" => (2, exec_delcol),
+    "slice" => (5, exec_slice),
+    "kron" => (2, exec_kron),
+    "aeq" => (3, exec_aeq),
+    "rot2d" => (1, exec_rot2d),
+    "rotx" => (1, exec_rotx),
+    "roty" => (1, exec_roty),
+    "rotz" => (1, exec_rotz),
+    "trans2d" => (2, exec_trans2d),
+    "trans3d" => (3, exec_trans3d),
+    "expm" => (1, exec_expm),
+    "dot" => (2, exec_dot),
+    "cross" => (2, exec_cross),
+    "normalize" => (1, exec_normalize),
+    "angle" => (2, exec_angle),
+    "sumrows" => (1, exec_sumrows),
+    "sumcols" => (1, exec_sumcols),
+    "mean" => (1, exec_mean),
+    "minv" => (1, exec_minv),
+    "maxv" => (1, exec_maxv),
+    "sort" => (1, exec_sort),
+    "rsort" => (1, exec_rsort),
+    "sortrows" => (2, exec_sortrows),
+    "rank" => (1, exec_rank),
+    "cond" => (1, exec_cond),
+    "pinv" => (1, exec_pinv),
+    "lstsq" => (2, exec_lstsq),
+    "deg" => (0, exec_deg),
+    "rad" => (0, exec_rad),
+    "grad" => (0, exec_grad),
+    "prec" => (1, exec_prec),
+    "setprec" => (1, exec_setprec),
+    "fptrap" => (0, exec_fptrap),
+    "brackets" => (0, exec_brackets),
+    "divtrap" => (0, exec_divtrap),
+};
+
+/// Converts a stack number into a count or index, if it is a non-negative integer.
+fn to_usize(value: f64) -> Option<usize> {
+    if value >= 0.0 && value.fract() == 0.0 && value <= usize::MAX as f64 {
+        Some(value as usize)
+    } else {
+        None
     }
 }
 
-/**
-Stack changes:
+/// Pops the count on top of the stack for operators like `dropn`, after checking
+/// that it is a non-negative integer and that `needed(count)` values sit below it.
+fn take_count(
+    state: &mut State,
+    operator: &str,
+    needed: fn(usize) -> usize,
+) -> Result<usize, CalcError> {
+    let count = match state.stack.last().unwrap() {
+        Value::Number(num) => to_usize(*num).ok_or_else(|| CalcError::InvalidArgument(
+            format!("`{operator}` needs a non-negative integer count, got {num}")
+        ))?,
+        other => return Err(CalcError::TypeMismatch(
+            format!("`{operator}` needs a number as its count, got {other}")
+        )),
+    };
+    if state.stack.len() - 1 < needed(count) {
+        return Err(CalcError::StackUnderflow {
+            operator: operator.to_string(),
+            needed: needed(count).saturating_add(1),
+            found: state.stack.len(),
+        });
+    }
+    state.stack.pop();
+    Ok(count)
+}
 
-- 1 pop
-- 1 push
- */
-fn exec_atan(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number(value.atan()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+/// Whether dividing by `value` would divide by zero somewhere.
+fn has_zero(value: &Value) -> bool {
+    match value {
+        Value::Number(num) => *num == 0.0,
+        Value::Matrix(mat) => mat.data.contains(&0.0),
+        Value::BigInt(int) => int.is_zero(),
+        Value::BigFloat(x) => x.is_zero(),
+        Value::Bool(_) | Value::Str(_) | Value::Date(_) => false,
+        Value::Interval(interval) => interval.contains_zero(),
+        Value::Quantity(quantity) => quantity.value == 0.0,
+        Value::Dual(dual) => dual.value == 0.0,
+        Value::Poly(poly) => poly.is_zero(),
+        Value::Quat(quat) => quat.is_zero(),
     }
 }
 
-/**
-Stack changes:
+/// Big integer results above this many bits are refused rather than left to
+/// run out of memory; it is about 315,000 decimal digits.
+const MAX_BIG_BITS: u64 = 1 << 20;
 
-- 1 pop
-- 1 push
- */
-fn exec_acot(stack: &mut Vec<Value>) {
-    let val = stack.pop().unwrap();
-    match val {
-        Value::Number(value) => {
-            stack.push(Value::Number((1.0/value).atan()));
-        },
-        value => panic!("Unsupported operation on {}", value),
+fn check_big_bits(operator: &str, bits: u64) -> Result<(), CalcError> {
+    if bits > MAX_BIG_BITS {
+        return Err(CalcError::InvalidArgument(
+            format!("`{operator}` would need more than {MAX_BIG_BITS} bits")
+        ));
     }
+    Ok(())
 }
 
-/**
-Stack changes:
+/// Very large big integers become infinite, like any other `f64` overflow.
+fn big_to_f64(int: &BigInt) -> f64 {
+    int.to_f64().unwrap_or(match int.is_negative() {
+        true => f64::NEG_INFINITY,
+        false => f64::INFINITY,
+    })
+}
 
-- 2 pop
-- 1 push
- */
-fn exec_atan2(stack: &mut Vec<Value>) {
-    let val2 = stack.pop().unwrap();
-    let val1 = stack.pop().unwrap();
-    match (val1, val2) {
-        (Value::Number(lhs), Value::Number(rhs)) => {
-            stack.push(Value::Number(lhs.atan2(rhs)));
-        }
-        (lhs,rhs) => panic!("Unsupported operations on {} and {}", lhs, rhs),
+fn to_big(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::BigInt(int) => Some(int.clone()),
+        Value::Number(num) if num.fract() == 0.0 => BigInt::from_f64(*num),
+        _ => None,
     }
 }
 
-/**
-Stack changes:
+/// Reads the top `N` values as big integers without popping them, deepest
+/// first. This only happens when at least one of them already is a big
+/// integer and the rest are integral numbers, so plain numbers keep their
+/// `f64` arithmetic.
+fn peek_big<const N: usize>(state: &State) -> Option<[BigInt; N]> {
+    let values = &state.stack[state.stack.len() - N..];
+    if !values.iter().any(|value| matches!(value, Value::BigInt(_))) {
+        return None;
+    }
+    values.iter().map(to_big).collect::<Option<Vec<_>>>()?.try_into().ok()
+}
 
-- No change
- */
-fn exec_print(stack: &mut Vec<Value>) {
-    for (i, elem) in stack.iter().rev().enumerate() {
-        println!("{}: {}", !(i as isize), elem);
+/// Turns big integers and big floats among the top `count` values into
+/// numbers, where an operation has no exact version for them.
+fn demote_big(state: &mut State, count: usize) {
+    let len = state.stack.len();
+    for value in &mut state.stack[len - count..] {
+        match value {
+            Value::BigInt(int) => *value = Value::Number(big_to_f64(int)),
+            Value::BigFloat(x) => *value = Value::Number(bigfloat::to_f64(x)),
+            _ => {},
+        }
     }
 }
 
-fn exec_identifier(stack: &mut Vec<Value>, identifier: &str) {
-    match HANDLERS.get(identifier) {
-        Some(fun) => fun(stack),
-        None => panic!("Undefined operator: {identifier}"),
+/// A number or big integer as a big float with `bits` bits.
+fn to_bigfloat(value: &Value, bits: usize) -> Option<BigFloat> {
+    match value {
+        Value::BigFloat(x) => Some(x.clone()),
+        Value::Number(num) => Some(bigfloat::from_f64(*num, bits)),
+        Value::BigInt(int) => Some(bigfloat::parse(&int.to_string(), bits, big_to_f64(int))),
+        Value::Matrix(_) | Value::Bool(_) | Value::Str(_) | Value::Interval(_) | Value::Quantity(_) | Value::Dual(_) | Value::Poly(_)
+        | Value::Quat(_) | Value::Date(_) => None,
     }
 }
 
-fn exec_expression(expr: &str) {
-    let mut stack = Vec::<Value>::new();
-    exec(&mut stack, expr);
+/// Applies `fun` when `peek_big` reads the top two values as big integers.
+/// Otherwise big integers among them are demoted and `None` is returned, for
+/// the caller to go on with numbers and matrices.
+fn exec_big_binary(
+    state: &mut State,
+    fun: impl FnOnce(BigInt, BigInt) -> Result<BigInt, CalcError>,
+) -> Option<Result<(), CalcError>> {
+    let Some([lhs, rhs]) = peek_big::<2>(state) else {
+        demote_big(state, 2);
+        return None;
+    };
+    Some(fun(lhs, rhs).map(|result| {
+        state.stack.truncate(state.stack.len() - 2);
+        state.stack.push(Value::BigInt(result));
+    }))
 }
 
-fn exec(stack: &mut Vec<Value>, expr: &str) {
-    for tok in expr.split_whitespace() {
-        let num = tok.parse::<f64>();
-        match num {
-            Ok(number) => stack.push(Value::Number(number)),
-            Err(_) => exec_identifier(stack, tok)
-        }
-        // println!("\"{tok}\": {:?}", stack);
+/// A big float an `f64` holds exactly is kept as a plain number, so counts,
+/// indices and sizes keep working in `--bits` mode.
+fn bigfloat_value(x: BigFloat) -> Value {
+    let num = bigfloat::to_f64(&x);
+    if x.is_nan() || bigfloat::from_f64(num, bigfloat::precision(&x)) == x {
+        Value::Number(num)
+    } else {
+        Value::BigFloat(x)
     }
 }
 
-use rustyline::error::ReadlineError;
-fn interactive() -> Result<bool, ReadlineError> {
-    use rustyline::{DefaultEditor};
-    
-    let mut rl = DefaultEditor::new().unwrap();
-    let mut stack = Vec::<Value>::new();
+/// The precision to compute a result from `operands` at: the finest among
+/// the big floats, or the `--bits` precision for plain numbers. `None` when
+/// the `f64` or big integer arithmetic applies instead.
+fn bigfloat_bits(state: &State, operands: &[Value]) -> Option<usize> {
+    let finest = operands.iter()
+        .filter_map(|value| match value {
+            Value::BigFloat(x) => Some(bigfloat::precision(x)),
+            _ => None,
+        })
+        .max();
+    match finest {
+        Some(bits) => Some(bits.max(state.bits.unwrap_or(0))),
+        None if operands.iter().all(Value::is_number) => state.bits,
+        None => None,
+    }
+}
 
-    loop {
-        let line = rl.readline("> ");
-        match line {
-            Ok(line_string) => {
-                rl.add_history_entry(&line_string)?;
-                exec(&mut stack, &line_string);
-            },
-            Err(_) => break,
-        }
+/// Applies `fun` at the precision `bigfloat_bits` picks, if it picks one and
+/// neither operand is a matrix. Returns whether it did.
+fn exec_bigfloat_binary(state: &mut State, fun: fn(&BigFloat, &BigFloat, usize) -> BigFloat) -> bool {
+    let operands = &state.stack[state.stack.len() - 2..];
+    let Some(bits) = bigfloat_bits(state, operands) else {
+        return false;
     };
-    return Ok(true);
+    let (Some(lhs), Some(rhs)) = (to_bigfloat(&operands[0], bits), to_bigfloat(&operands[1], bits)) else {
+        return false;
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(bigfloat_value(fun(&lhs, &rhs, bits)));
+    true
+}
+
+/// The single operand version of `exec_bigfloat_binary`.
+fn exec_bigfloat_unary(state: &mut State, fun: impl Fn(&BigFloat, usize) -> BigFloat) -> bool {
+    let operand = std::slice::from_ref(state.stack.last().unwrap());
+    let Some(bits) = bigfloat_bits(state, operand) else {
+        return false;
+    };
+    let Some(x) = to_bigfloat(&operand[0], bits) else {
+        return false;
+    };
+    *state.stack.last_mut().unwrap() = bigfloat_value(fun(&x, bits));
+    true
+}
+
+/// A number as the interval holding just it, and a big value as the interval
+/// around its nearest `f64`.
+fn to_interval(value: &Value) -> Option<Interval> {
+    match value {
+        Value::Interval(interval) => Some(*interval),
+        Value::Number(num) => Some(Interval::point(*num)),
+        Value::BigInt(int) => Some(Interval::around(big_to_f64(int))),
+        Value::BigFloat(x) => Some(Interval::around(bigfloat::to_f64(x))),
+        _ => None,
+    }
+}
+
+/// Applies `fun` when either of the top two values is an interval and the
+/// other is one too, or a number or big value. Returns whether it did.
+fn exec_interval_binary(state: &mut State, fun: fn(Interval, Interval) -> Interval) -> bool {
+    let operands = &state.stack[state.stack.len() - 2..];
+    if !operands.iter().any(|value| matches!(value, Value::Interval(_))) {
+        return false;
+    }
+    let (Some(lhs), Some(rhs)) = (to_interval(&operands[0]), to_interval(&operands[1])) else {
+        return false;
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Interval(fun(lhs, rhs)));
+    true
+}
+
+/// Applies `fun` when the top value is an interval, and returns `None`
+/// otherwise. `fun` gives `None` for an interval entirely outside its domain.
+fn exec_interval_unary(
+    state: &mut State,
+    operator: &str,
+    fun: impl Fn(Interval) -> Option<Interval>,
+) -> Option<Result<(), CalcError>> {
+    let Value::Interval(interval) = state.stack.last_mut().unwrap() else {
+        return None;
+    };
+    let Some(result) = fun(*interval) else {
+        return Some(Err(CalcError::InvalidArgument(
+            format!("`{operator}` is not defined anywhere on {}", Value::Interval(*interval))
+        )));
+    };
+    *interval = result;
+    Some(Ok(()))
+}
+
+/// A quantity whose units cancel is a plain number again, in SI units, so
+/// `1km 1m /` is 1000.
+fn quantity_value(quantity: Quantity) -> Value {
+    match quantity.unit.is_dimensionless() {
+        true => Value::Number(quantity.value * quantity.unit.factor()),
+        false => Value::Quantity(quantity),
+    }
+}
+
+/// A number or big value as a quantity without units.
+fn to_quantity(value: &Value) -> Option<Quantity> {
+    match value {
+        Value::Quantity(quantity) => Some(quantity.clone()),
+        Value::Number(num) => Some(Quantity::new(*num, Unit::one())),
+        Value::BigInt(int) => Some(Quantity::new(big_to_f64(int), Unit::one())),
+        Value::BigFloat(x) => Some(Quantity::new(bigfloat::to_f64(x), Unit::one())),
+        _ => None,
+    }
+}
+
+/// Applies `fun` when either of the top two values is a quantity and the
+/// other is one too, or a number or big value. Returns `None` otherwise, and
+/// fails when `fun` finds the dimensions do not match.
+fn exec_quantity_binary(
+    state: &mut State,
+    operator: &str,
+    fun: impl Fn(&Quantity, &Quantity) -> Option<Quantity>,
+) -> Option<Result<(), CalcError>> {
+    let operands = &state.stack[state.stack.len() - 2..];
+    if !operands.iter().any(|value| matches!(value, Value::Quantity(_))) {
+        return None;
+    }
+    let (lhs, rhs) = (to_quantity(&operands[0])?, to_quantity(&operands[1])?);
+    let Some(result) = fun(&lhs, &rhs) else {
+        return Some(Err(CalcError::UnitMismatch {
+            operator: operator.to_string(),
+            lhs: operands[0].to_string(),
+            rhs: operands[1].to_string(),
+        }));
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(quantity_value(result));
+    Some(Ok(()))
+}
+
+/// A number or big value as a dual number that does not depend on the
+/// marked variable.
+fn to_dual(value: &Value) -> Option<Dual> {
+    match value {
+        Value::Dual(dual) => Some(*dual),
+        Value::Number(num) => Some(Dual::constant(*num)),
+        Value::BigInt(int) => Some(Dual::constant(big_to_f64(int))),
+        Value::BigFloat(x) => Some(Dual::constant(bigfloat::to_f64(x))),
+        _ => None,
+    }
+}
+
+/// Applies `fun` when either of the top two values is a dual number and the
+/// other is one too, or a number or big value. Returns whether it did.
+fn exec_dual_binary(state: &mut State, fun: fn(Dual, Dual) -> Dual) -> bool {
+    let operands = &state.stack[state.stack.len() - 2..];
+    if !operands.iter().any(|value| matches!(value, Value::Dual(_))) {
+        return false;
+    }
+    let (Some(lhs), Some(rhs)) = (to_dual(&operands[0]), to_dual(&operands[1])) else {
+        return false;
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Dual(fun(lhs, rhs)));
+    true
+}
+
+/// Applies `fun` when the top value is a dual number. Returns whether it did.
+fn exec_dual_unary(state: &mut State, fun: impl Fn(Dual) -> Dual) -> bool {
+    let Value::Dual(dual) = state.stack.last_mut().unwrap() else {
+        return false;
+    };
+    *dual = fun(*dual);
+    true
+}
+
+/// A number or big value as a constant polynomial.
+fn to_poly(value: &Value) -> Option<Poly> {
+    match value {
+        Value::Poly(poly) => Some(poly.clone()),
+        Value::Number(num) => Some(Poly::constant(*num)),
+        Value::BigInt(int) => Some(Poly::constant(big_to_f64(int))),
+        Value::BigFloat(x) => Some(Poly::constant(bigfloat::to_f64(x))),
+        _ => None,
+    }
+}
+
+/// Applies `fun` when either of the top two values is a polynomial and the
+/// other is one too, or a number or big value. Returns whether it did.
+fn exec_poly_binary(state: &mut State, fun: fn(&Poly, &Poly) -> Poly) -> bool {
+    let operands = &state.stack[state.stack.len() - 2..];
+    if !operands.iter().any(|value| matches!(value, Value::Poly(_))) {
+        return false;
+    }
+    let (Some(lhs), Some(rhs)) = (to_poly(&operands[0]), to_poly(&operands[1])) else {
+        return false;
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Poly(fun(&lhs, &rhs)));
+    true
+}
+
+/// A number or big value as a quaternion with only a real part.
+fn to_quat(value: &Value) -> Option<Quat> {
+    match value {
+        Value::Quat(quat) => Some(*quat),
+        Value::Number(num) => Some(Quat::scalar(*num)),
+        Value::BigInt(int) => Some(Quat::scalar(big_to_f64(int))),
+        Value::BigFloat(x) => Some(Quat::scalar(bigfloat::to_f64(x))),
+        _ => None,
+    }
+}
+
+/// Applies `fun` when either of the top two values is a quaternion and the
+/// other is one too, or a number or big value. Returns whether it did.
+fn exec_quat_binary(state: &mut State, fun: fn(Quat, Quat) -> Quat) -> bool {
+    let operands = &state.stack[state.stack.len() - 2..];
+    if !operands.iter().any(|value| matches!(value, Value::Quat(_))) {
+        return false;
+    }
+    let (Some(lhs), Some(rhs)) = (to_quat(&operands[0]), to_quat(&operands[1])) else {
+        return false;
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Quat(fun(lhs, rhs)));
+    true
+}
+
+/// A quantity of time in seconds.
+fn duration_seconds(value: &Value) -> Option<f64> {
+    match value {
+        Value::Quantity(quantity) => quantity.value_in(&Unit::parse("s").unwrap()),
+        _ => None,
+    }
+}
+
+/// Shifts a date by a duration for `+` and `-`, and subtracts two dates into
+/// the days between them. Returns `None` unless one of the top two values is
+/// a date, and fails when the other does not go with it.
+fn exec_date_binary(state: &mut State, operator: &str) -> Option<Result<(), CalcError>> {
+    let len = state.stack.len();
+    let (lhs, rhs) = (&state.stack[len - 2], &state.stack[len - 1]);
+    let result = match (lhs, rhs, operator) {
+        (Value::Date(x), Value::Date(y), "-") => {
+            quantity_value(Quantity::new((x.seconds - y.seconds) / 86_400.0, Unit::parse("day").unwrap()))
+        },
+        (Value::Date(date), duration, "+") | (duration, Value::Date(date), "+") if duration_seconds(duration).is_some() => {
+            Value::Date(date.add_seconds(duration_seconds(duration).unwrap()))
+        },
+        (Value::Date(date), duration, "-") if duration_seconds(duration).is_some() => {
+            Value::Date(date.add_seconds(-duration_seconds(duration).unwrap()))
+        },
+        (Value::Date(_), _, _) | (_, Value::Date(_), _) => return Some(Err(CalcError::TypeMismatch(
+            format!("`{operator}` needs a date and a duration, got {lhs} and {rhs}")
+        ))),
+        _ => return None,
+    };
+    state.stack.truncate(len - 2);
+    state.stack.push(result);
+    Some(Ok(()))
+}
+
+/// Applies `fun` to a number, or to every element of a matrix. This is how
+/// the scalar functions work on matrices without each of them matching on
+/// `Value`.
+fn exec_unary(state: &mut State, fun: impl Fn(f64) -> f64) -> Result<(), CalcError> {
+    exec_unary_checked(state, |value| Ok(fun(value)))
+}
+
+/// Like `exec_unary` for functions with a restricted domain. The first
+/// element `fun` rejects fails the whole operation, leaving the operand on
+/// the stack.
+fn exec_unary_checked(state: &mut State, fun: impl Fn(f64) -> Result<f64, CalcError>) -> Result<(), CalcError> {
+    let result = match state.stack.last().unwrap() {
+        Value::Number(value) => Value::Number(fun(*value)?),
+        Value::Matrix(mat) => {
+            let data = mat.data.iter().map(|elem| fun(*elem)).collect::<Result<_, _>>()?;
+            Value::Matrix(Matrix::from(mat.row, mat.col, data))
+        },
+        Value::BigInt(int) => Value::Number(fun(big_to_f64(int))?),
+        Value::BigFloat(x) => Value::Number(fun(bigfloat::to_f64(x))?),
+        value @ (Value::Bool(_) | Value::Str(_) | Value::Interval(_) | Value::Quantity(_) | Value::Dual(_) | Value::Poly(_) | Value::Quat(_) | Value::Date(_)) => return Err(CalcError::TypeMismatch(format!("Unsupported operation on {}", value))),
+    };
+    *state.stack.last_mut().unwrap() = result;
+    Ok(())
+}
+
+/// Puts both operands back so a failed binary operation leaves the stack unchanged.
+fn restore_binary(stack: &mut Vec<Value>, lhs: Value, rhs: Value, err: CalcError) -> CalcError {
+    stack.push(lhs);
+    stack.push(rhs);
+    err
+}
+
+fn unsupported_binary(stack: &mut Vec<Value>, lhs: Value, rhs: Value) -> CalcError {
+    let msg = format!("Unsupported operations on {} and {}", lhs, rhs);
+    restore_binary(stack, lhs, rhs, CalcError::TypeMismatch(msg))
+}
+
+/**
+Stack changes:
+
+- 1 push
+ */
+fn exec_pi(state: &mut State) -> Result<(), CalcError> {
+    let pi = match state.bits {
+        Some(bits) => Value::BigFloat(bigfloat::constant("pi", bits).unwrap()),
+        None => Value::Number(std::f64::consts::PI),
+    };
+    state.stack.push(pi);
+    Ok(())
+}
+
+/// Reads a `row col` size whose `col` sits `depth` values below the top.
+fn peek_shape(state: &State, depth: usize) -> Result<(usize, usize), CalcError> {
+    let stack = &state.stack;
+    let top = stack.len() - depth;
+    let (row, col) = match (&stack[top - 2], &stack[top - 1]) {
+        (Value::Number(row), Value::Number(col)) => (*row, *col),
+        _ => return Err(CalcError::TypeMismatch("Matrix size must be numbers".to_string())),
+    };
+    match (to_usize(row), to_usize(col)) {
+        (Some(row), Some(col)) if row > 0 && col > 0 => Ok((row, col)),
+        _ => Err(CalcError::InvalidArgument(
+            format!("Matrix size must be positive integers, got {row}x{col}")
+        )),
+    }
+}
+
+/// Checks a size that is not backed by values on the stack, so a typo cannot
+/// ask for more memory than the machine has.
+fn check_generated_size(row: usize, col: usize) -> Result<(), CalcError> {
+    const MAX_GENERATED_ELEMENTS: usize = 1 << 24;
+    if row.saturating_mul(col) > MAX_GENERATED_ELEMENTS {
+        return Err(CalcError::InvalidArgument(
+            format!("Matrix size {row}x{col} exceeds {MAX_GENERATED_ELEMENTS} elements")
+        ));
+    }
+    Ok(())
+}
+
+/// Pops the `depth` size arguments and pushes a `row x col` matrix of `data`.
+fn replace_with_matrix(state: &mut State, depth: usize, row: usize, col: usize, data: Vec<f64>) {
+    state.stack.truncate(state.stack.len() - depth);
+    state.stack.push(Value::Matrix(Matrix::from(row, col, data.into_boxed_slice())));
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the `n x n` identity matrix
+ */
+fn exec_eye(state: &mut State) -> Result<(), CalcError> {
+    let n = match state.stack.last().unwrap() {
+        Value::Number(num) => match to_usize(*num) {
+            Some(n) if n > 0 => n,
+            _ => return Err(CalcError::InvalidArgument(
+                format!("Matrix size must be positive integers, got {num}")
+            )),
+        },
+        _ => return Err(CalcError::TypeMismatch("Matrix size must be numbers".to_string())),
+    };
+    check_generated_size(n, n)?;
+    replace_with_matrix(state, 1, n, n, linalg::identity(n));
+    Ok(())
+}
+
+/**
+Variables: `col` then `row`
+
+Stack changes:
+
+- 2 pop
+- 1 push, a `row x col` matrix of zeros
+ */
+fn exec_zeros(state: &mut State) -> Result<(), CalcError> {
+    let (row, col) = peek_shape(state, 0)?;
+    check_generated_size(row, col)?;
+    replace_with_matrix(state, 2, row, col, vec![0.0; row * col]);
+    Ok(())
+}
+
+/**
+Variables: `col` then `row`
+
+Stack changes:
+
+- 2 pop
+- 1 push, a `row x col` matrix of ones
+ */
+fn exec_ones(state: &mut State) -> Result<(), CalcError> {
+    let (row, col) = peek_shape(state, 0)?;
+    check_generated_size(row, col)?;
+    replace_with_matrix(state, 2, row, col, vec![1.0; row * col]);
+    Ok(())
+}
+
+/**
+Variables: `value`, then `col`, then `row`
+
+Stack changes:
+
+- 3 pop
+- 1 push, a `row x col` matrix with every element set to `value`
+ */
+fn exec_fill(state: &mut State) -> Result<(), CalcError> {
+    let value = match state.stack.last().unwrap() {
+        Value::Number(num) => *num,
+        other => return Err(CalcError::TypeMismatch(format!("`fill` needs a number, got {other}"))),
+    };
+    let (row, col) = peek_shape(state, 1)?;
+    check_generated_size(row, col)?;
+    replace_with_matrix(state, 3, row, col, vec![value; row * col]);
+    Ok(())
+}
+
+/**
+Variables: `col` then `row`
+
+Stack changes:
+
+- `row`*`col` + 2 pop
+- 1 push
+ */
+fn exec_matrix(state: &mut State) -> Result<(), CalcError> {
+    let (row, col) = peek_shape(state, 0)?;
+    let stack = &mut state.stack;
+    let count = row.saturating_mul(col);
+    if stack.len() - 2 < count {
+        return Err(CalcError::StackUnderflow {
+            operator: "matrix".to_string(),
+            needed: count.saturating_add(2),
+            found: stack.len(),
+        });
+    }
+    let elements_start = stack.len() - 2 - count;
+    if !stack[elements_start..stack.len() - 2].iter().all(Value::is_number) {
+        return Err(CalcError::TypeMismatch("Matrix elements must be numbers".to_string()));
+    }
+    stack.truncate(stack.len() - 2);
+    let mat = stack.drain(elements_start..)
+        .map(|value| value.to_number().unwrap())
+        .collect::<Vec<f64>>();
+    stack.push(Value::Matrix(Matrix::from(row, col, mat.into_boxed_slice())));
+    Ok(())
+}
+
+/// Borrows the matrix `depth` values below the top of the stack.
+fn peek_matrix<'a>(state: &'a State, op: &str, depth: usize) -> Result<&'a Matrix, CalcError> {
+    match &state.stack[state.stack.len() - 1 - depth] {
+        Value::Matrix(mat) => Ok(mat),
+        value => Err(CalcError::TypeMismatch(format!("`{op}` needs a matrix, got {}", value))),
+    }
+}
+
+/// Borrows the matrix on top of the stack, checking that it is square.
+fn peek_square<'a>(state: &'a State, op: &str) -> Result<&'a Matrix, CalcError> {
+    let mat = peek_matrix(state, op, 0)?;
+    if mat.row != mat.col {
+        return Err(CalcError::NotSquare { operator: op.to_string(), shape: mat.shape() });
+    }
+    Ok(mat)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+Computed from an LU factorization with partial pivoting.
+ */
+fn exec_det(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_square(state, "det")?;
+    let det = linalg::lu(mat.row, &mat.data)?.det();
+    state.stack.pop();
+    state.stack.push(Value::Number(det));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+Solves against the identity with an LU factorization. A matrix whose pivots
+vanish to within rounding is reported as singular.
+ */
+fn exec_inv(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_square(state, "inv")?;
+    let n = mat.row;
+    let lu = linalg::lu(n, &mat.data)?;
+    if lu.is_singular() {
+        return Err(CalcError::Singular("inv".to_string()));
+    }
+    let inverse = lu.solve(&linalg::identity(n), n)?;
+    state.stack.pop();
+    state.stack.push(Value::Matrix(Matrix::from(n, n, inverse.into_boxed_slice())));
+    Ok(())
+}
+
+/**
+Variables: `b`, then `A`
+
+Stack changes:
+
+- 2 pop
+- 1 push, the `x` with `A x = b`
+
+`A` must be square; `b` may have several columns, each solved for in turn.
+Uses an LU factorization of `A` rather than its inverse.
+ */
+fn exec_solve(state: &mut State) -> Result<(), CalcError> {
+    let rhs = match state.stack.last().unwrap() {
+        Value::Matrix(mat) => mat,
+        value => return Err(CalcError::TypeMismatch(format!("`solve` needs a matrix right-hand side, got {}", value))),
+    };
+    let len = state.stack.len();
+    let lhs = match &state.stack[len - 2] {
+        Value::Matrix(mat) if mat.row == mat.col => mat,
+        Value::Matrix(mat) => return Err(CalcError::NotSquare { operator: "solve".to_string(), shape: mat.shape() }),
+        value => return Err(CalcError::TypeMismatch(format!("`solve` needs a matrix, got {}", value))),
+    };
+    if rhs.row != lhs.row {
+        return Err(CalcError::DimensionMismatch { lhs: lhs.shape(), rhs: rhs.shape() });
+    }
+    let lu = linalg::lu(lhs.row, &lhs.data)?;
+    if lu.is_singular() {
+        return Err(CalcError::Singular("solve".to_string()));
+    }
+    let solution = Matrix::from(rhs.row, rhs.col, lu.solve(&rhs.data, rhs.col)?.into_boxed_slice());
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Matrix(solution));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 3 push: `L`, `U`, then `P` on top
+
+`P A = L U` with `L` unit lower triangular and `P` a permutation matrix.
+Singular matrices factor too; their `U` has a zero on the diagonal.
+ */
+fn exec_lu(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_square(state, "lu")?;
+    let n = mat.row;
+    let lu = linalg::lu(n, &mat.data)?;
+    state.stack.pop();
+    for data in [lu.lower(), lu.upper(), lu.permutation()] {
+        state.stack.push(Value::Matrix(Matrix::from(n, n, data.into_boxed_slice())));
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 2 push: `Q`, then `R` on top
+
+Householder QR of any `m x n` matrix: `Q` is an `m x m` orthogonal matrix and
+`R` an `m x n` upper triangular one with `A = Q R`.
+ */
+fn exec_qr(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "qr", 0)?;
+    let (m, n) = mat.shape();
+    let (q, r) = linalg::qr(m, n, &mat.data)?;
+    state.stack.pop();
+    state.stack.push(Value::Matrix(Matrix::from(m, m, q.into_boxed_slice())));
+    state.stack.push(Value::Matrix(Matrix::from(m, n, r.into_boxed_slice())));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the lower triangular `L` with `A = L Lᵀ`
+
+`A` must be symmetric and positive definite.
+ */
+fn exec_chol(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_square(state, "chol")?;
+    let n = mat.row;
+    if !linalg::is_symmetric(n, &mat.data) {
+        return Err(CalcError::InvalidArgument("`chol` needs a symmetric matrix".to_string()));
+    }
+    let factor = linalg::cholesky(n, &mat.data).ok_or_else(|| CalcError::InvalidArgument(
+        "`chol` needs a positive-definite matrix".to_string()
+    ))?;
+    state.stack.pop();
+    state.stack.push(Value::Matrix(Matrix::from(n, n, factor.into_boxed_slice())));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 3 push: `U`, `Σ`, then `Vᵀ` on top
+
+The thin SVD `A = U diag(Σ) Vᵀ` of an `m x n` matrix with `k = min(m, n)`:
+`U` is `m x k`, `Vᵀ` is `k x n`, and `Σ` is a `1 x k` row of singular values
+from largest to smallest.
+ */
+fn exec_svd(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "svd", 0)?;
+    let (m, n) = mat.shape();
+    let k = m.min(n);
+    let linalg::Svd { u, sigma, vt } = linalg::svd(m, n, &mat.data)?;
+    state.stack.pop();
+    state.stack.push(Value::Matrix(Matrix::from(m, k, u.into_boxed_slice())));
+    state.stack.push(Value::Matrix(Matrix::from(1, k, sigma.into_boxed_slice())));
+    state.stack.push(Value::Matrix(Matrix::from(k, n, vt.into_boxed_slice())));
+    Ok(())
+}
+
+/// Eigen-decomposes the square matrix on top of the stack without popping
+/// it. Only symmetric matrices are supported, since their eigenvalues are real.
+fn peek_eigen(state: &State, op: &str) -> Result<(usize, Vec<f64>, Vec<f64>), CalcError> {
+    let mat = peek_square(state, op)?;
+    let n = mat.row;
+    if !linalg::is_symmetric(n, &mat.data) {
+        return Err(CalcError::InvalidArgument(format!("`{op}` only supports symmetric matrices")));
+    }
+    let (values, vectors) = linalg::symmetric_eigen(n, &mat.data)?;
+    Ok((n, values, vectors))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, a `1 x n` row of eigenvalues from largest to smallest
+ */
+fn exec_eig(state: &mut State) -> Result<(), CalcError> {
+    let (n, values, _) = peek_eigen(state, "eig")?;
+    state.stack.pop();
+    state.stack.push(Value::Matrix(Matrix::from(1, n, values.into_boxed_slice())));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, an `n x n` matrix whose columns are unit eigenvectors
+
+Column `i` belongs to the `i`-th value pushed by `eig`.
+ */
+fn exec_eigv(state: &mut State) -> Result<(), CalcError> {
+    let (n, _, vectors) = peek_eigen(state, "eigv")?;
+    state.stack.pop();
+    state.stack.push(Value::Matrix(Matrix::from(n, n, vectors.into_boxed_slice())));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the sum of the diagonal
+ */
+fn exec_trace(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_square(state, "trace")?;
+    let trace = (0..mat.row).map(|i| mat.data[i*mat.col + i]).sum();
+    state.stack.pop();
+    state.stack.push(Value::Number(trace));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+Given an `n x 1` or `1 x n` vector, builds the `n x n` matrix with it on the
+diagonal. Given a square matrix, extracts its diagonal as an `n x 1` vector.
+ */
+fn exec_diag(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "diag", 0)?;
+    let result = if mat.row == mat.col {
+        let n = mat.row;
+        Matrix::from(n, 1, (0..n).map(|i| mat.data[i*n + i]).collect())
+    } else if mat.row == 1 || mat.col == 1 {
+        let n = mat.data.len();
+        check_generated_size(n, n)?;
+        let mut data = vec![0.0; n * n];
+        for (i, elem) in mat.data.iter().enumerate() {
+            data[i*n + i] = *elem;
+        }
+        Matrix::from(n, n, data.into_boxed_slice())
+    } else {
+        return Err(CalcError::InvalidArgument(
+            format!("`diag` needs a vector or a square matrix, got {}x{}", mat.row, mat.col)
+        ));
+    };
+    state.stack.pop();
+    state.stack.push(Value::Matrix(result));
+    Ok(())
+}
+
+/**
+Variables: `col`, then `row`, then the matrix
+
+Stack changes:
+
+- 3 pop
+- 1 push, the same elements in row-major order as a `row x col` matrix
+
+`1 n reshape` flattens an `n`-element matrix into a row.
+ */
+fn exec_reshape(state: &mut State) -> Result<(), CalcError> {
+    let (row, col) = peek_shape(state, 0)?;
+    let mat = peek_matrix(state, "reshape", 2)?;
+    if row.checked_mul(col) != Some(mat.data.len()) {
+        return Err(CalcError::InvalidArgument(format!(
+            "Cannot reshape {}x{} into {row}x{col}: element counts differ", mat.row, mat.col
+        )));
+    }
+    let data = mat.data.clone();
+    state.stack.truncate(state.stack.len() - 3);
+    state.stack.push(Value::Matrix(Matrix::from(row, col, data)));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the two matrices side by side
+
+Both must have the same number of rows.
+ */
+fn exec_hcat(state: &mut State) -> Result<(), CalcError> {
+    let (lhs, rhs) = (peek_matrix(state, "hcat", 1)?, peek_matrix(state, "hcat", 0)?);
+    if lhs.row != rhs.row {
+        return Err(CalcError::DimensionMismatch { lhs: lhs.shape(), rhs: rhs.shape() });
+    }
+    let (row, col) = (lhs.row, lhs.col + rhs.col);
+    let data = (0..row)
+        .flat_map(|i| lhs.data[i*lhs.col..(i + 1)*lhs.col].iter().chain(&rhs.data[i*rhs.col..(i + 1)*rhs.col]))
+        .copied()
+        .collect();
+    replace_with_matrix(state, 2, row, col, data);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the top matrix stacked below the other
+
+Both must have the same number of columns.
+ */
+fn exec_vcat(state: &mut State) -> Result<(), CalcError> {
+    let (lhs, rhs) = (peek_matrix(state, "vcat", 1)?, peek_matrix(state, "vcat", 0)?);
+    if lhs.col != rhs.col {
+        return Err(CalcError::DimensionMismatch { lhs: lhs.shape(), rhs: rhs.shape() });
+    }
+    let (row, col) = (lhs.row + rhs.row, lhs.col);
+    let data = lhs.data.iter().chain(rhs.data.iter()).copied().collect();
+    replace_with_matrix(state, 2, row, col, data);
+    Ok(())
+}
+
+/// Reads the zero-based index `depth` values below the top, which must be
+/// below `bound`, the number of rows or columns it indexes.
+fn peek_index(state: &State, op: &str, depth: usize, bound: usize) -> Result<usize, CalcError> {
+    let num = match &state.stack[state.stack.len() - 1 - depth] {
+        Value::Number(num) => *num,
+        other => return Err(CalcError::TypeMismatch(format!("`{op}` needs a number index, got {other}"))),
+    };
+    match to_usize(num) {
+        Some(index) if index < bound => Ok(index),
+        _ => Err(CalcError::InvalidArgument(format!(
+            "`{op}` index {num} is out of bounds, it must be an integer from 0 to {}", bound - 1
+        ))),
+    }
+}
+
+/**
+Variables: `j`, then `i`, then the matrix
+
+Stack changes:
+
+- 3 pop
+- 1 push, the element at row `i` and column `j`
+
+Indices start at 0.
+ */
+fn exec_get(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "get", 2)?;
+    let i = peek_index(state, "get", 1, mat.row)?;
+    let j = peek_index(state, "get", 0, mat.col)?;
+    let elem = mat.data[i*mat.col + j];
+    state.stack.truncate(state.stack.len() - 3);
+    state.stack.push(Value::Number(elem));
+    Ok(())
+}
+
+/**
+Variables: `v`, then `j`, then `i`, then the matrix
+
+Stack changes:
+
+- 4 pop
+- 1 push, the matrix with the element at row `i` and column `j` set to `v`
+
+Indices start at 0.
+ */
+fn exec_set(state: &mut State) -> Result<(), CalcError> {
+    let value = match state.stack.last().unwrap() {
+        Value::Number(num) => *num,
+        other => return Err(CalcError::TypeMismatch(format!("`set` needs a number, got {other}"))),
+    };
+    let mat = peek_matrix(state, "set", 3)?;
+    let i = peek_index(state, "set", 2, mat.row)?;
+    let j = peek_index(state, "set", 1, mat.col)?;
+    let (row, col) = mat.shape();
+    let mut data = mat.data.to_vec();
+    data[i*col + j] = value;
+    replace_with_matrix(state, 4, row, col, data);
+    Ok(())
+}
+
+/**
+Variables: `i`, then the matrix
+
+Stack changes:
+
+- 2 pop
+- 1 push, row `i` as a `1 x n` matrix
+ */
+fn exec_row(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "row", 1)?;
+    let i = peek_index(state, "row", 0, mat.row)?;
+    let data = mat.data[i*mat.col..(i + 1)*mat.col].to_vec();
+    let col = mat.col;
+    replace_with_matrix(state, 2, 1, col, data);
+    Ok(())
+}
+
+/**
+Variables: `j`, then the matrix
+
+Stack changes:
+
+- 2 pop
+- 1 push, column `j` as an `n x 1` matrix
+ */
+fn exec_col(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "col", 1)?;
+    let j = peek_index(state, "col", 0, mat.col)?;
+    let data = mat.data.iter().skip(j).step_by(mat.col).copied().collect();
+    let row = mat.row;
+    replace_with_matrix(state, 2, row, 1, data);
+    Ok(())
+}
+
+/**
+Variables: `i`, then the matrix
+
+Stack changes:
+
+- 2 pop
+- 1 push, the matrix without row `i`
+ */
+fn exec_delrow(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "delrow", 1)?;
+    let i = peek_index(state, "delrow", 0, mat.row)?;
+    if mat.row == 1 {
+        return Err(CalcError::InvalidArgument("`delrow` cannot remove the only row".to_string()));
+    }
+    let (row, col) = mat.shape();
+    let mut data = mat.data.to_vec();
+    data.drain(i*col..(i + 1)*col);
+    replace_with_matrix(state, 2, row - 1, col, data);
+    Ok(())
+}
+
+/**
+Variables: `j`, then the matrix
+
+Stack changes:
+
+- 2 pop
+- 1 push, the matrix without column `j`
+ */
+fn exec_delcol(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "delcol", 1)?;
+    let j = peek_index(state, "delcol", 0, mat.col)?;
+    if mat.col == 1 {
+        return Err(CalcError::InvalidArgument("`delcol` cannot remove the only column".to_string()));
+    }
+    let (row, col) = mat.shape();
+    let data = mat.data.iter().enumerate()
+        .filter(|(index, _)| index % col != j)
+        .map(|(_, elem)| *elem)
+        .collect();
+    replace_with_matrix(state, 2, row, col - 1, data);
+    Ok(())
+}
+
+/// Reads a half-open `start end` range whose `end` sits `depth` values below
+/// the top. It must be non-empty and fit within `0..bound`.
+fn peek_range(state: &State, op: &str, depth: usize, bound: usize) -> Result<std::ops::Range<usize>, CalcError> {
+    let top = state.stack.len() - depth;
+    let (start, end) = match (&state.stack[top - 2], &state.stack[top - 1]) {
+        (Value::Number(start), Value::Number(end)) => (*start, *end),
+        _ => return Err(CalcError::TypeMismatch(format!("`{op}` needs number bounds"))),
+    };
+    match (to_usize(start), to_usize(end)) {
+        (Some(from), Some(to)) if from < to && to <= bound => Ok(from..to),
+        _ => Err(CalcError::InvalidArgument(format!(
+            "`{op}` range {start}..{end} must be non-empty and within 0..{bound}"
+        ))),
+    }
+}
+
+/**
+Variables: `c1`, then `c0`, then `r1`, then `r0`, then the matrix
+
+Stack changes:
+
+- 5 pop
+- 1 push, rows `r0` up to but excluding `r1`, and columns `c0` up to but
+  excluding `c1`
+
+Indices start at 0, so `0 2 0 2 slice` is the top-left 2x2 block.
+ */
+fn exec_slice(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "slice", 4)?;
+    let rows = peek_range(state, "slice", 2, mat.row)?;
+    let cols = peek_range(state, "slice", 0, mat.col)?;
+    let (row, col) = (rows.len(), cols.len());
+    let data = rows
+        .flat_map(|i| mat.data[i*mat.col + cols.start..i*mat.col + cols.end].iter().copied())
+        .collect();
+    replace_with_matrix(state, 5, row, col, data);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the Kronecker product
+
+For an `m x n` matrix `A` and a `p x q` matrix `B`, the result is the
+`mp x nq` block matrix whose block `(i, j)` is `A[i][j] * B`.
+ */
+fn exec_kron(state: &mut State) -> Result<(), CalcError> {
+    let (lhs, rhs) = (peek_matrix(state, "kron", 1)?, peek_matrix(state, "kron", 0)?);
+    let (row, col) = (lhs.row.saturating_mul(rhs.row), lhs.col.saturating_mul(rhs.col));
+    check_generated_size(row, col)?;
+    let mut data = Vec::with_capacity(row * col);
+    for i in 0..row {
+        for j in 0..col {
+            let outer = lhs.data[(i / rhs.row)*lhs.col + j / rhs.col];
+            data.push(outer * rhs.data[(i % rhs.row)*rhs.col + j % rhs.col]);
+        }
+    }
+    replace_with_matrix(state, 2, row, col, data);
+    Ok(())
+}
+
+/**
+Variables: `tol`, then `b`, then `a`
+
+Stack changes:
+
+- 3 pop
+- 2 push: the number of elements that differ by more than `tol`, then 1 on
+  top if there were none and 0 otherwise
+
+`a` and `b` are two numbers or two matrices of the same dimensions. NaN never
+matches anything.
+ */
+fn exec_aeq(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let tolerance = match &state.stack[len - 1] {
+        Value::Number(tol) if *tol >= 0.0 => *tol,
+        Value::Number(tol) => return Err(CalcError::InvalidArgument(
+            format!("`aeq` needs a non-negative tolerance, got {tol}")
+        )),
+        other => return Err(CalcError::TypeMismatch(format!("`aeq` needs a number tolerance, got {other}"))),
+    };
+    let (lhs, rhs) = match (&state.stack[len - 3], &state.stack[len - 2]) {
+        (Value::Number(lhs), Value::Number(rhs)) => (std::slice::from_ref(lhs), std::slice::from_ref(rhs)),
+        (Value::Matrix(lhs), Value::Matrix(rhs)) => {
+            lhs.check_same_shape(rhs)?;
+            (&*lhs.data, &*rhs.data)
+        },
+        (lhs, rhs) => return Err(CalcError::TypeMismatch(
+            format!("`aeq` needs two numbers or two matrices, got {lhs} and {rhs}")
+        )),
+    };
+    let close = |lhs: f64, rhs: f64| (lhs - rhs).abs() <= tolerance;
+    let mismatches = lhs.iter().zip(rhs).filter(|(lhs, rhs)| !close(**lhs, **rhs)).count();
+    state.stack.truncate(len - 3);
+    state.stack.push(Value::Number(mismatches as f64));
+    state.stack.push(Value::Number(if mismatches == 0 { 1.0 } else { 0.0 }));
+    Ok(())
+}
+
+/// Reads the top `N` values, which must all be numbers, deepest first.
+fn peek_numbers<const N: usize>(state: &State, op: &str) -> Result<[f64; N], CalcError> {
+    let mut numbers = [0.0; N];
+    for (number, value) in numbers.iter_mut().zip(&state.stack[state.stack.len() - N..]) {
+        *number = match value {
+            Value::Number(num) => *num,
+            other => return Err(CalcError::TypeMismatch(format!("`{op}` needs numbers, got {other}"))),
+        };
+    }
+    Ok(numbers)
+}
+
+/// Reads the angle on top, in the current angle mode, as its cosine and sine.
+fn peek_rotation(state: &State, op: &str) -> Result<(f64, f64), CalcError> {
+    let [angle] = peek_numbers::<1>(state, op)?;
+    let radians = state.angle.to_radians(angle);
+    Ok((radians.cos(), radians.sin()))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the 2x2 matrix rotating column vectors counterclockwise by the
+  angle
+ */
+fn exec_rot2d(state: &mut State) -> Result<(), CalcError> {
+    let (c, s) = peek_rotation(state, "rot2d")?;
+    replace_with_matrix(state, 1, 2, 2, vec![c, -s, s, c]);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the 3x3 matrix rotating by the angle about the x axis
+ */
+fn exec_rotx(state: &mut State) -> Result<(), CalcError> {
+    let (c, s) = peek_rotation(state, "rotx")?;
+    replace_with_matrix(state, 1, 3, 3, vec![1.0, 0.0, 0.0, 0.0, c, -s, 0.0, s, c]);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the 3x3 matrix rotating by the angle about the y axis
+ */
+fn exec_roty(state: &mut State) -> Result<(), CalcError> {
+    let (c, s) = peek_rotation(state, "roty")?;
+    replace_with_matrix(state, 1, 3, 3, vec![c, 0.0, s, 0.0, 1.0, 0.0, -s, 0.0, c]);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the 3x3 matrix rotating by the angle about the z axis
+ */
+fn exec_rotz(state: &mut State) -> Result<(), CalcError> {
+    let (c, s) = peek_rotation(state, "rotz")?;
+    replace_with_matrix(state, 1, 3, 3, vec![c, -s, 0.0, s, c, 0.0, 0.0, 0.0, 1.0]);
+    Ok(())
+}
+
+/**
+Variables: `ty`, then `tx`
+
+Stack changes:
+
+- 2 pop
+- 1 push, the 3x3 homogeneous transform translating by `(tx, ty)`
+ */
+fn exec_trans2d(state: &mut State) -> Result<(), CalcError> {
+    let [tx, ty] = peek_numbers::<2>(state, "trans2d")?;
+    replace_with_matrix(state, 2, 3, 3, vec![1.0, 0.0, tx, 0.0, 1.0, ty, 0.0, 0.0, 1.0]);
+    Ok(())
+}
+
+/**
+Variables: `tz`, then `ty`, then `tx`
+
+Stack changes:
+
+- 3 pop
+- 1 push, the 4x4 homogeneous transform translating by `(tx, ty, tz)`
+ */
+fn exec_trans3d(state: &mut State) -> Result<(), CalcError> {
+    let [tx, ty, tz] = peek_numbers::<3>(state, "trans3d")?;
+    let mut data = linalg::identity(4);
+    data[3] = tx;
+    data[7] = ty;
+    data[11] = tz;
+    replace_with_matrix(state, 3, 4, 4, data);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the matrix exponential `e^A`
+
+Unlike `exp`, which would act on each element, this is the sum of `A^k / k!`,
+the solution operator of `x' = A x`.
+ */
+fn exec_expm(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_square(state, "expm")?;
+    let n = mat.row;
+    let result = linalg::expm(n, &mat.data)?;
+    replace_with_matrix(state, 1, n, n, result);
+    Ok(())
+}
+
+/// Borrows the elements of the `1 x n` or `n x 1` matrix `depth` values
+/// below the top. Vectors are plain matrices with a single row or column.
+fn peek_vector<'a>(state: &'a State, op: &str, depth: usize) -> Result<&'a Matrix, CalcError> {
+    let mat = peek_matrix(state, op, depth)?;
+    if mat.row != 1 && mat.col != 1 {
+        return Err(CalcError::InvalidArgument(
+            format!("`{op}` needs a vector, got a {}x{} matrix", mat.row, mat.col)
+        ));
+    }
+    Ok(mat)
+}
+
+/// Borrows two vectors of equal length from the top of the stack. A row and a
+/// column vector may be mixed.
+fn peek_vector_pair<'a>(state: &'a State, op: &str) -> Result<(&'a Matrix, &'a Matrix), CalcError> {
+    let (lhs, rhs) = (peek_vector(state, op, 1)?, peek_vector(state, op, 0)?);
+    if lhs.data.len() != rhs.data.len() {
+        return Err(CalcError::DimensionMismatch { lhs: lhs.shape(), rhs: rhs.shape() });
+    }
+    Ok((lhs, rhs))
+}
+
+fn dot(lhs: &[f64], rhs: &[f64]) -> f64 {
+    lhs.iter().zip(rhs).map(|(lhs, rhs)| lhs * rhs).sum()
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the dot product of two vectors of equal length
+ */
+fn exec_dot(state: &mut State) -> Result<(), CalcError> {
+    let (lhs, rhs) = peek_vector_pair(state, "dot")?;
+    let product = dot(&lhs.data, &rhs.data);
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Number(product));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the cross product of two 3-vectors, shaped like the first
+ */
+fn exec_cross(state: &mut State) -> Result<(), CalcError> {
+    let (lhs, rhs) = peek_vector_pair(state, "cross")?;
+    let (a, b) = match (&*lhs.data, &*rhs.data) {
+        (&[a0, a1, a2], &[b0, b1, b2]) => ([a0, a1, a2], [b0, b1, b2]),
+        _ => return Err(CalcError::InvalidArgument("`cross` needs 3-element vectors".to_string())),
+    };
+    let (row, col) = lhs.shape();
+    let data = vec![a[1]*b[2] - a[2]*b[1], a[2]*b[0] - a[0]*b[2], a[0]*b[1] - a[1]*b[0]];
+    replace_with_matrix(state, 2, row, col, data);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the vector or quaternion scaled to unit length
+ */
+fn exec_normalize(state: &mut State) -> Result<(), CalcError> {
+    if let Value::Quat(quat) = *state.stack.last().unwrap() {
+        if quat.is_zero() {
+            return Err(CalcError::InvalidArgument("`normalize` needs a non-zero quaternion".to_string()));
+        }
+        *state.stack.last_mut().unwrap() = Value::Quat(quat.scale(1.0 / quat.norm()));
+        return Ok(());
+    }
+    let vector = peek_vector(state, "normalize", 0)?;
+    let length = dot(&vector.data, &vector.data).sqrt();
+    if length == 0.0 {
+        return Err(CalcError::InvalidArgument("`normalize` needs a non-zero vector".to_string()));
+    }
+    let (row, col) = vector.shape();
+    let data = vector.data.iter().map(|elem| elem / length).collect();
+    replace_with_matrix(state, 1, row, col, data);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the angle between two vectors, from 0 to a half turn
+
+The angle is in the current angle mode.
+ */
+fn exec_angle(state: &mut State) -> Result<(), CalcError> {
+    let (lhs, rhs) = peek_vector_pair(state, "angle")?;
+    let lengths = (dot(&lhs.data, &lhs.data) * dot(&rhs.data, &rhs.data)).sqrt();
+    if lengths == 0.0 {
+        return Err(CalcError::InvalidArgument("`angle` needs non-zero vectors".to_string()));
+    }
+    let cosine = (dot(&lhs.data, &rhs.data) / lengths).clamp(-1.0, 1.0);
+    let angle = state.angle.radians_to_unit(cosine.acos());
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Number(angle));
+    Ok(())
+}
+
+/// Which way a reduction collapses a matrix.
+#[derive(Clone, Copy)]
+enum Axis {
+    /// Reduce each row, giving a column vector.
+    Rows,
+    /// Reduce each column, giving a row vector.
+    Cols,
+}
+
+/// Collapses the matrix on top of the stack along `axis` with `fun`. A vector
+/// is collapsed whole into a number, whichever way it is oriented.
+fn exec_reduce(state: &mut State, op: &str, axis: Axis, fun: fn(&[f64]) -> f64) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, op, 0)?;
+    let result = if mat.row == 1 || mat.col == 1 {
+        Value::Number(fun(&mat.data))
+    } else {
+        match axis {
+            Axis::Rows => {
+                let data = mat.data.chunks(mat.col).map(fun).collect();
+                Value::Matrix(Matrix::from(mat.row, 1, data))
+            },
+            Axis::Cols => {
+                let data = (0..mat.col)
+                    .map(|j| fun(&mat.data.iter().skip(j).step_by(mat.col).copied().collect::<Vec<_>>()))
+                    .collect();
+                Value::Matrix(Matrix::from(1, mat.col, data))
+            },
+        }
+    };
+    *state.stack.last_mut().unwrap() = result;
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, an `m x 1` vector of row sums, or the total of a vector
+ */
+fn exec_sumrows(state: &mut State) -> Result<(), CalcError> {
+    exec_reduce(state, "sumrows", Axis::Rows, |elems| elems.iter().sum())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, a `1 x n` vector of column sums, or the total of a vector
+ */
+fn exec_sumcols(state: &mut State) -> Result<(), CalcError> {
+    exec_reduce(state, "sumcols", Axis::Cols, |elems| elems.iter().sum())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, a `1 x n` vector of column means, or the mean of a vector
+ */
+fn exec_mean(state: &mut State) -> Result<(), CalcError> {
+    exec_reduce(state, "mean", Axis::Cols, |elems| elems.iter().sum::<f64>() / elems.len() as f64)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, a `1 x n` vector of column minimums, or the minimum of a vector
+ */
+fn exec_minv(state: &mut State) -> Result<(), CalcError> {
+    exec_reduce(state, "minv", Axis::Cols, |elems| elems.iter().copied().fold(f64::INFINITY, f64::min))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, a `1 x n` vector of column maximums, or the maximum of a vector
+ */
+fn exec_maxv(state: &mut State) -> Result<(), CalcError> {
+    exec_reduce(state, "maxv", Axis::Cols, |elems| elems.iter().copied().fold(f64::NEG_INFINITY, f64::max))
+}
+
+/// Sorts the vector on top of the stack in place, keeping its orientation.
+fn sort_vector(state: &mut State, op: &str, descending: bool) -> Result<(), CalcError> {
+    let vector = peek_vector(state, op, 0)?;
+    let (row, col) = vector.shape();
+    let mut data = vector.data.to_vec();
+    data.sort_by(f64::total_cmp);
+    if descending {
+        data.reverse();
+    }
+    replace_with_matrix(state, 1, row, col, data);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the vector sorted from smallest to largest
+ */
+fn exec_sort(state: &mut State) -> Result<(), CalcError> {
+    sort_vector(state, "sort", false)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the vector sorted from largest to smallest
+ */
+fn exec_rsort(state: &mut State) -> Result<(), CalcError> {
+    sort_vector(state, "rsort", true)
+}
+
+/**
+Variables: `k`, then the matrix
+
+Stack changes:
+
+- 2 pop
+- 1 push, the rows ordered by their values in column `k`, smallest first
+
+The sort is stable, so rows with equal keys keep their order. Columns are
+counted from 0.
+ */
+fn exec_sortrows(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "sortrows", 1)?;
+    let key = peek_index(state, "sortrows", 0, mat.col)?;
+    let (row, col) = mat.shape();
+    let mut rows = mat.data.chunks(col).collect::<Vec<_>>();
+    rows.sort_by(|lhs, rhs| lhs[key].total_cmp(&rhs[key]));
+    let data = rows.concat();
+    replace_with_matrix(state, 2, row, col, data);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+The number of singular values above `max(m, n) * ε * σ_max`, so entries that
+only differ by rounding do not count as independent.
+ */
+fn exec_rank(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "rank", 0)?;
+    let (m, n) = mat.shape();
+    let sigma = linalg::svd(m, n, &mat.data)?.sigma;
+    let tolerance = linalg::rank_tolerance(m, n, &sigma);
+    let rank = sigma.iter().filter(|&&value| value > tolerance).count();
+    state.stack.pop();
+    state.stack.push(Value::Number(rank as f64));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+The 2-norm condition number `σ_max / σ_min`, infinite for a rank-deficient
+matrix. Roughly, a `solve` loses `log10(cond)` significant digits.
+ */
+fn exec_cond(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "cond", 0)?;
+    let (m, n) = mat.shape();
+    let sigma = linalg::svd(m, n, &mat.data)?.sigma;
+    let cond = match (sigma.first(), sigma.last()) {
+        (Some(_), Some(&0.0)) => f64::INFINITY,
+        (Some(max), Some(min)) => max / min,
+        _ => 1.0,
+    };
+    state.stack.pop();
+    state.stack.push(Value::Number(cond));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the `n x m` Moore–Penrose pseudo-inverse of an `m x n` matrix
+
+Computed from the SVD; singular values that `rank` would not count are
+treated as zero.
+ */
+fn exec_pinv(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_matrix(state, "pinv", 0)?;
+    let (m, n) = mat.shape();
+    let inverse = linalg::pinv(m, n, &mat.data)?;
+    replace_with_matrix(state, 1, n, m, inverse);
+    Ok(())
+}
+
+/**
+Variables: `b`, then `A`
+
+Stack changes:
+
+- 2 pop
+- 1 push, the `x` minimizing `‖A x − b‖`
+
+`A` may have any shape; `b` needs as many rows. When several `x` fit equally
+well, as for a rank-deficient `A`, the shortest one is chosen.
+ */
+fn exec_lstsq(state: &mut State) -> Result<(), CalcError> {
+    let (lhs, rhs) = (peek_matrix(state, "lstsq", 1)?, peek_matrix(state, "lstsq", 0)?);
+    if lhs.row != rhs.row {
+        return Err(CalcError::DimensionMismatch { lhs: lhs.shape(), rhs: rhs.shape() });
+    }
+    let (m, n) = lhs.shape();
+    let inverse = Matrix::from(n, m, linalg::pinv(m, n, &lhs.data)?.into_boxed_slice());
+    let solution = inverse.matmul(rhs)?;
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Matrix(solution));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+A number and a matrix combine element by element, in either order.
+Quantities need the same dimensions, and the result is in the unit of the
+lower one, so `1km 300m +` is 1.3 km. A duration moves a date later.
+ */
+fn exec_plus(state: &mut State) -> Result<(), CalcError> {
+    if let Some(result) = exec_date_binary(state, "+") {
+        return result;
+    }
+    if let Some(result) = exec_quantity_binary(state, "+", Quantity::add) {
+        return result;
+    }
+    if exec_dual_binary(state, Dual::add) {
+        return Ok(());
+    }
+    if exec_poly_binary(state, Poly::add) {
+        return Ok(());
+    }
+    if exec_quat_binary(state, Quat::add) {
+        return Ok(());
+    }
+    if exec_interval_binary(state, Interval::add) {
+        return Ok(());
+    }
+    if exec_bigfloat_binary(state, bigfloat::add) {
+        return Ok(());
+    }
+    if let Some(result) = exec_big_binary(state, |lhs, rhs| Ok(lhs + rhs)) {
+        return result;
+    }
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs+rhs));
+        }
+        (Value::Matrix(lhs), Value::Matrix(rhs)) => match lhs.zip_with(&rhs, |a, b| a + b) {
+            Ok(mat) => state.stack.push(Value::Matrix(mat)),
+            Err(err) => return Err(restore_binary(&mut state.stack, Value::Matrix(lhs), Value::Matrix(rhs), err)),
+        },
+        (Value::Number(lhs), Value::Matrix(rhs)) => {
+            state.stack.push(Value::Matrix(rhs.map(|elem| lhs+elem)));
+        }
+        (Value::Matrix(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Matrix(lhs.map(|elem| elem+rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+A number and a matrix combine element by element, in either order.
+Quantities need the same dimensions, and the result is in the unit of the
+lower one, so `1km 300m +` is 1.3 km. A duration moves a date earlier, and
+the difference of two dates is the duration between them in days.
+ */
+fn exec_sub(state: &mut State) -> Result<(), CalcError> {
+    if let Some(result) = exec_date_binary(state, "-") {
+        return result;
+    }
+    if let Some(result) = exec_quantity_binary(state, "-", Quantity::sub) {
+        return result;
+    }
+    if exec_dual_binary(state, Dual::sub) {
+        return Ok(());
+    }
+    if exec_poly_binary(state, Poly::sub) {
+        return Ok(());
+    }
+    if exec_quat_binary(state, Quat::sub) {
+        return Ok(());
+    }
+    if exec_interval_binary(state, Interval::sub) {
+        return Ok(());
+    }
+    if exec_bigfloat_binary(state, bigfloat::sub) {
+        return Ok(());
+    }
+    if let Some(result) = exec_big_binary(state, |lhs, rhs| Ok(lhs - rhs)) {
+        return result;
+    }
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs-rhs));
+        }
+        (Value::Matrix(lhs), Value::Matrix(rhs)) => match lhs.zip_with(&rhs, |a, b| a - b) {
+            Ok(mat) => state.stack.push(Value::Matrix(mat)),
+            Err(err) => return Err(restore_binary(&mut state.stack, Value::Matrix(lhs), Value::Matrix(rhs), err)),
+        },
+        (Value::Number(lhs), Value::Matrix(rhs)) => {
+            state.stack.push(Value::Matrix(rhs.map(|elem| lhs-elem)));
+        }
+        (Value::Matrix(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Matrix(lhs.map(|elem| elem-rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+A number and a matrix combine element by element, in either order.
+Quantities multiply their units too.
+ */
+fn exec_mul(state: &mut State) -> Result<(), CalcError> {
+    if let Some(result) = exec_quantity_binary(state, "*", |lhs, rhs| Some(lhs.mul(rhs))) {
+        return result;
+    }
+    if exec_dual_binary(state, Dual::mul) {
+        return Ok(());
+    }
+    if exec_poly_binary(state, Poly::mul) {
+        return Ok(());
+    }
+    if exec_quat_binary(state, Quat::mul) {
+        return Ok(());
+    }
+    if exec_interval_binary(state, Interval::mul) {
+        return Ok(());
+    }
+    if exec_bigfloat_binary(state, bigfloat::mul) {
+        return Ok(());
+    }
+    let big = exec_big_binary(state, |lhs, rhs| {
+        check_big_bits("*", lhs.bits() + rhs.bits())?;
+        Ok(lhs * rhs)
+    });
+    if let Some(result) = big {
+        return result;
+    }
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs*rhs));
+        }
+        (Value::Matrix(lhs), Value::Matrix(rhs)) => match lhs.matmul(&rhs) {
+            Ok(product) => state.stack.push(Value::Matrix(product)),
+            Err(err) => return Err(restore_binary(&mut state.stack, Value::Matrix(lhs), Value::Matrix(rhs), err)),
+        },
+        (Value::Number(lhs), Value::Matrix(rhs)) => {
+            state.stack.push(Value::Matrix(rhs.map(|elem| lhs*elem)));
+        }
+        (Value::Matrix(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Matrix(lhs.map(|elem| elem*rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+A number and a matrix combine element by element, in either order. Big
+integers are divided as numbers; `//` keeps them exact. Quantities divide
+their units too, and `5m 3s /` is 1.6667 m/s.
+ */
+fn exec_div(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let (lhs, rhs) = (&state.stack[len - 2], &state.stack[len - 1]);
+    if state.div_trap && !(lhs.is_matrix() && rhs.is_matrix()) && has_zero(rhs) {
+        return Err(CalcError::DivisionByZero);
+    }
+    if let Some(result) = exec_quantity_binary(state, "/", |lhs, rhs| Some(lhs.div(rhs))) {
+        return result;
+    }
+    if exec_dual_binary(state, Dual::div) {
+        return Ok(());
+    }
+    if exec_quat_binary(state, Quat::div) {
+        return Ok(());
+    }
+    if exec_interval_binary(state, Interval::div) {
+        return Ok(());
+    }
+    if exec_bigfloat_binary(state, bigfloat::div) {
+        return Ok(());
+    }
+    demote_big(state, 2);
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs/rhs));
+        }
+        (Value::Number(lhs), Value::Matrix(rhs)) => {
+            state.stack.push(Value::Matrix(rhs.map(|elem| lhs/elem)));
+        }
+        (Value::Matrix(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Matrix(lhs.map(|elem| elem/rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+A square matrix raised to an integer is repeated matrix multiplication;
+`A 0 ^` is the identity and negative powers use the inverse. A big integer
+stays exact under a non-negative integer power. A quantity takes integer
+powers, which apply to its unit too.
+ */
+fn exec_pow(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    if let [Value::Interval(base), exponent] = &state.stack[len - 2..] {
+        let result = interval_power(*base, exponent)?;
+        state.stack.truncate(len - 2);
+        state.stack.push(Value::Interval(result));
+        return Ok(());
+    }
+    if let [Value::Quantity(base), exponent] = &state.stack[len - 2..] {
+        let result = quantity_power(base, exponent)?;
+        state.stack.truncate(len - 2);
+        state.stack.push(quantity_value(result));
+        return Ok(());
+    }
+    if exec_dual_binary(state, Dual::pow) {
+        return Ok(());
+    }
+    if exec_bigfloat_binary(state, bigfloat::pow) {
+        return Ok(());
+    }
+    if let Some([base, exponent]) = peek_big::<2>(state) {
+        if let Some(exponent) = exponent.to_u32() {
+            check_big_bits("^", base.bits().saturating_mul(exponent as u64))?;
+            state.stack.truncate(state.stack.len() - 2);
+            state.stack.push(Value::BigInt(base.pow(exponent)));
+            return Ok(());
+        }
+    }
+    demote_big(state, 2);
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs.powf(rhs)));
+        }
+        (Value::Matrix(lhs), Value::Number(rhs)) => match matrix_power(&lhs, rhs) {
+            Ok(power) => state.stack.push(Value::Matrix(power)),
+            Err(err) => return Err(restore_binary(&mut state.stack, Value::Matrix(lhs), Value::Number(rhs), err)),
+        },
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/// Raises an interval to an integer power. Negative powers take the
+/// reciprocal, which is unbounded if the interval contains zero.
+fn interval_power(base: Interval, value: &Value) -> Result<Interval, CalcError> {
+    let exponent = match value {
+        Value::Number(num) => to_i64(*num).and_then(|n| i32::try_from(n).ok()),
+        _ => None,
+    };
+    let Some(exponent) = exponent else {
+        return Err(CalcError::InvalidArgument(format!("Interval powers need an integer exponent, got {value}")));
+    };
+    let power = base.powi(exponent.unsigned_abs());
+    Ok(if exponent < 0 { Interval::point(1.0).div(power) } else { power })
+}
+
+/// Raises a quantity to an integer power, which its unit is raised to too.
+fn quantity_power(base: &Quantity, value: &Value) -> Result<Quantity, CalcError> {
+    let exponent = match value {
+        Value::Number(num) => to_i64(*num).and_then(|n| i32::try_from(n).ok()),
+        _ => None,
+    };
+    let Some(exponent) = exponent else {
+        return Err(CalcError::InvalidArgument(format!("Powers of quantities need an integer exponent, got {value}")));
+    };
+    Ok(Quantity::new(base.value.powi(exponent), base.unit.powi(exponent)))
+}
+
+/// Raises a square matrix to an integer power by repeated squaring. Negative
+/// powers invert the matrix first.
+fn matrix_power(mat: &Matrix, exponent: f64) -> Result<Matrix, CalcError> {
+    if mat.row != mat.col {
+        return Err(CalcError::NotSquare { operator: "^".to_string(), shape: mat.shape() });
+    }
+    let exponent = to_i64(exponent).ok_or_else(|| CalcError::InvalidArgument(
+        format!("Matrix powers need an integer exponent, got {exponent}")
+    ))?;
+    let n = mat.row;
+    let mut base = if exponent < 0 {
+        let lu = linalg::lu(n, &mat.data)?;
+        if lu.is_singular() {
+            return Err(CalcError::Singular("^".to_string()));
+        }
+        Matrix::from(n, n, lu.solve(&linalg::identity(n), n)?.into_boxed_slice())
+    } else {
+        mat.clone()
+    };
+    let mut result = Matrix::from(n, n, linalg::identity(n).into_boxed_slice());
+    let mut exponent = exponent.unsigned_abs();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.matmul(&base)?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = base.matmul(&base)?;
+        }
+    }
+    Ok(result)
+}
+
+/// Applies `fun` element by element: matrices of equal dimensions pair up
+/// their entries, and a number is broadcast over a matrix.
+fn exec_elementwise(state: &mut State, fun: fn(f64, f64) -> f64) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(fun(lhs, rhs)));
+        }
+        (Value::Matrix(lhs), Value::Matrix(rhs)) => match lhs.zip_with(&rhs, fun) {
+            Ok(result) => state.stack.push(Value::Matrix(result)),
+            Err(err) => return Err(restore_binary(&mut state.stack, Value::Matrix(lhs), Value::Matrix(rhs), err)),
+        },
+        (Value::Number(lhs), Value::Matrix(rhs)) => {
+            state.stack.push(Value::Matrix(rhs.map(|elem| fun(lhs, elem))));
+        }
+        (Value::Matrix(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Matrix(lhs.map(|elem| fun(elem, rhs))));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+Multiplies entry by entry, unlike `*` which is the matrix product.
+ */
+fn exec_elem_mul(state: &mut State) -> Result<(), CalcError> {
+    exec_elementwise(state, |lhs, rhs| lhs*rhs)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+Divides entry by entry. With `divtrap` on, any zero divisor is an error.
+ */
+fn exec_elem_div(state: &mut State) -> Result<(), CalcError> {
+    let divisor = state.stack.last().unwrap();
+    if state.div_trap && has_zero(divisor) {
+        return Err(CalcError::DivisionByZero);
+    }
+    exec_elementwise(state, |lhs, rhs| lhs/rhs)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+Raises each entry to the power of the matching entry.
+ */
+fn exec_elem_pow(state: &mut State) -> Result<(), CalcError> {
+    exec_elementwise(state, f64::powf)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_sin(state: &mut State) -> Result<(), CalcError> {
+    let angle = state.angle;
+    if exec_dual_unary(state, |dual| dual.convert(|x| angle.to_radians(x)).sin()) {
+        return Ok(());
+    }
+    if let Some(result) = exec_interval_unary(state, "sin", |interval| Some(angle.interval_to_radians(interval).sin())) {
+        return result;
+    }
+    if exec_bigfloat_unary(state, |x, bits| bigfloat::sin(&angle.big_to_radians(x, bits), bits)) {
+        return Ok(());
+    }
+    exec_unary(state, |value| angle.to_radians(value).sin())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_cos(state: &mut State) -> Result<(), CalcError> {
+    let angle = state.angle;
+    if exec_dual_unary(state, |dual| dual.convert(|x| angle.to_radians(x)).cos()) {
+        return Ok(());
+    }
+    if let Some(result) = exec_interval_unary(state, "cos", |interval| Some(angle.interval_to_radians(interval).cos())) {
+        return result;
+    }
+    if exec_bigfloat_unary(state, |x, bits| bigfloat::cos(&angle.big_to_radians(x, bits), bits)) {
+        return Ok(());
+    }
+    exec_unary(state, |value| angle.to_radians(value).cos())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_tan(state: &mut State) -> Result<(), CalcError> {
+    let angle = state.angle;
+    if exec_dual_unary(state, |dual| dual.convert(|x| angle.to_radians(x)).tan()) {
+        return Ok(());
+    }
+    if let Some(result) = exec_interval_unary(state, "tan", |interval| Some(angle.interval_to_radians(interval).tan())) {
+        return result;
+    }
+    if exec_bigfloat_unary(state, |x, bits| bigfloat::tan(&angle.big_to_radians(x, bits), bits)) {
+        return Ok(());
+    }
+    exec_unary(state, |value| angle.to_radians(value).tan())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_cot(state: &mut State) -> Result<(), CalcError> {
+    let angle = state.angle;
+    exec_unary(state, |value| 1.0/angle.to_radians(value).tan())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_exp(state: &mut State) -> Result<(), CalcError> {
+    if exec_dual_unary(state, Dual::exp) {
+        return Ok(());
+    }
+    if let Some(result) = exec_interval_unary(state, "exp", |interval| Some(interval.exp())) {
+        return result;
+    }
+    if exec_bigfloat_unary(state, bigfloat::exp) {
+        return Ok(());
+    }
+    exec_unary(state, |value| value.exp())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_exp2(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.exp2())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+`e^x - 1`, accurate for `x` near zero where `exp 1 -` loses every digit.
+ */
+fn exec_expm1(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.exp_m1())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+`ln(1 + x)`, accurate for `x` near zero.
+ */
+fn exec_ln1p(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.ln_1p())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_exp10(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| 10f64.powf(value))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_sqrt(state: &mut State) -> Result<(), CalcError> {
+    if exec_dual_unary(state, Dual::sqrt) {
+        return Ok(());
+    }
+    if let Some(result) = exec_interval_unary(state, "sqrt", Interval::sqrt) {
+        return result;
+    }
+    if let Value::Quantity(quantity) = state.stack.last_mut().unwrap() {
+        let Some(unit) = quantity.unit.root(2) else {
+            return Err(CalcError::InvalidArgument(format!("`sqrt` needs even powers of every unit, got {}", quantity.unit)));
+        };
+        *quantity = Quantity::new(quantity.value.sqrt(), unit);
+        return Ok(());
+    }
+    if exec_bigfloat_unary(state, bigfloat::sqrt) {
+        return Ok(());
+    }
+    exec_unary(state, f64::sqrt)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_asin(state: &mut State) -> Result<(), CalcError> {
+    let angle = state.angle;
+    if exec_dual_unary(state, |dual| dual.asin().convert(|x| angle.radians_to_unit(x))) {
+        return Ok(());
+    }
+    exec_unary(state, |value| angle.radians_to_unit(value.asin()))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_acos(state: &mut State) -> Result<(), CalcError> {
+    let angle = state.angle;
+    if exec_dual_unary(state, |dual| dual.acos().convert(|x| angle.radians_to_unit(x))) {
+        return Ok(());
+    }
+    exec_unary(state, |value| angle.radians_to_unit(value.acos()))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_atan(state: &mut State) -> Result<(), CalcError> {
+    let angle = state.angle;
+    if exec_dual_unary(state, |dual| dual.atan().convert(|x| angle.radians_to_unit(x))) {
+        return Ok(());
+    }
+    exec_unary(state, |value| angle.radians_to_unit(value.atan()))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_acot(state: &mut State) -> Result<(), CalcError> {
+    let angle = state.angle;
+    exec_unary(state, |value| angle.radians_to_unit((1.0/value).atan()))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_sinh(state: &mut State) -> Result<(), CalcError> {
+    if exec_dual_unary(state, Dual::sinh) {
+        return Ok(());
+    }
+    exec_unary(state, |value| value.sinh())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_cosh(state: &mut State) -> Result<(), CalcError> {
+    if exec_dual_unary(state, Dual::cosh) {
+        return Ok(());
+    }
+    exec_unary(state, |value| value.cosh())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_tanh(state: &mut State) -> Result<(), CalcError> {
+    if exec_dual_unary(state, Dual::tanh) {
+        return Ok(());
+    }
+    exec_unary(state, |value| value.tanh())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_coth(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| 1.0/value.tanh())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_asinh(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.asinh())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_acosh(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.acosh())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_atanh(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.atanh())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_ln(state: &mut State) -> Result<(), CalcError> {
+    if exec_dual_unary(state, Dual::ln) {
+        return Ok(());
+    }
+    if let Some(result) = exec_interval_unary(state, "ln", Interval::ln) {
+        return result;
+    }
+    if exec_bigfloat_unary(state, bigfloat::ln) {
+        return Ok(());
+    }
+    exec_unary(state, |value| value.ln())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_log(state: &mut State) -> Result<(), CalcError> {
+    if exec_dual_unary(state, Dual::log10) {
+        return Ok(());
+    }
+    exec_unary(state, |value| value.log10())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_log2(state: &mut State) -> Result<(), CalcError> {
+    if exec_dual_unary(state, Dual::log2) {
+        return Ok(());
+    }
+    exec_unary(state, |value| value.log2())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+`x b logb` is the base `b` logarithm of `x`.
+ */
+fn exec_logb(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs.log(rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+The absolute value of a quaternion is its norm.
+ */
+fn exec_abs(state: &mut State) -> Result<(), CalcError> {
+    if let Value::Quat(quat) = *state.stack.last().unwrap() {
+        *state.stack.last_mut().unwrap() = Value::Number(quat.norm());
+        return Ok(());
+    }
+    if exec_dual_unary(state, Dual::abs) {
+        return Ok(());
+    }
+    if let Some(result) = exec_interval_unary(state, "abs", |interval| Some(interval.abs())) {
+        return result;
+    }
+    if exec_bigfloat_unary(state, |x, _| x.abs()) {
+        return Ok(());
+    }
+    if let Value::Quantity(quantity) = state.stack.last_mut().unwrap() {
+        quantity.value = quantity.value.abs();
+        return Ok(());
+    }
+    if let Value::BigInt(int) = state.stack.last_mut().unwrap() {
+        *int = int.abs();
+        return Ok(());
+    }
+    exec_unary(state, |value| value.abs())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+Zero keeps its sign, unlike `f64::signum` which maps it to 1.
+ */
+fn exec_sign(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| if value == 0.0 { value } else { value.signum() })
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_neg(state: &mut State) -> Result<(), CalcError> {
+    if let Value::Quat(quat) = state.stack.last_mut().unwrap() {
+        *quat = quat.neg();
+        return Ok(());
+    }
+    if let Value::Poly(poly) = state.stack.last_mut().unwrap() {
+        *poly = poly.neg();
+        return Ok(());
+    }
+    if exec_dual_unary(state, Dual::neg) {
+        return Ok(());
+    }
+    if let Some(result) = exec_interval_unary(state, "neg", |interval| Some(interval.neg())) {
+        return result;
+    }
+    if exec_bigfloat_unary(state, |x, _| x.neg()) {
+        return Ok(());
+    }
+    if let Value::Quantity(quantity) = state.stack.last_mut().unwrap() {
+        quantity.value = -quantity.value;
+        return Ok(());
+    }
+    if let Value::BigInt(int) = state.stack.last_mut().unwrap() {
+        *int = -std::mem::take(int);
+        return Ok(());
+    }
+    exec_unary(state, |value| -value)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_floor(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.floor())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_ceil(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.ceil())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+Halfway cases round away from zero.
+ */
+fn exec_round(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.round())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_trunc(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.trunc())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+Keeps the sign of the value, so `-2.5 frac` is -0.5.
+ */
+fn exec_frac(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value.fract())
+}
+
+/**
+Variables: `n`, then the value
+
+Stack changes:
+
+- 2 pop
+- 1 push, the value rounded to `n` decimal places
+
+A negative `n` rounds to tens, hundreds, ...
+ */
+fn exec_roundto(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) if rhs.fract() != 0.0 || !rhs.is_finite() => {
+            let err = CalcError::InvalidArgument(
+                format!("`roundto` needs an integer place count, got {rhs}")
+            );
+            return Err(restore_binary(&mut state.stack, Value::Number(lhs), Value::Number(rhs), err));
+        }
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            let factor = 10f64.powi(rhs as i32);
+            state.stack.push(Value::Number((lhs*factor).round()/factor));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/// Division with `divtrap` support, shared by `mod`, `rem` and `//`. Big
+/// integers use `divide_big`, and dividing them by zero is always an error
+/// since integers have no infinity.
+fn exec_integer_division(
+    state: &mut State,
+    divide: fn(f64, f64) -> f64,
+    divide_big: fn(&BigInt, &BigInt) -> BigInt,
+) -> Result<(), CalcError> {
+    let big = exec_big_binary(state, |lhs, rhs| match rhs.is_zero() {
+        true => Err(CalcError::DivisionByZero),
+        false => Ok(divide_big(&lhs, &rhs)),
+    });
+    if let Some(result) = big {
+        return result;
+    }
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) if state.div_trap && rhs == 0.0 => {
+            let (lhs, rhs) = (Value::Number(lhs), Value::Number(rhs));
+            return Err(restore_binary(&mut state.stack, lhs, rhs, CalcError::DivisionByZero));
+        }
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(divide(lhs, rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+Euclidean remainder, never negative: `-7 3 mod` is 2.
+ */
+fn exec_mod(state: &mut State) -> Result<(), CalcError> {
+    exec_integer_division(state, f64::rem_euclid, Euclid::rem_euclid)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+Truncated remainder with the sign of the dividend: `-7 3 rem` is -1.
+ */
+fn exec_rem(state: &mut State) -> Result<(), CalcError> {
+    exec_integer_division(state, |lhs, rhs| lhs % rhs, |lhs, rhs| lhs % rhs)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+Euclidean quotient, the counterpart of `mod`: `-7 3 //` is -3, so that
+`a b // b * a b mod +` is `a` again.
+ */
+fn exec_int_div(state: &mut State) -> Result<(), CalcError> {
+    exec_integer_division(state, f64::div_euclid, Euclid::div_euclid)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+ */
+fn exec_min(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs.min(rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+ */
+fn exec_max(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs.max(rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Variables: `hi`, `lo`, then the value
+
+Stack changes:
+
+- 3 pop
+- 1 push, the value limited to `lo..=hi`
+ */
+fn exec_clamp(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let (value, lo, hi) = match &state.stack[len - 3..] {
+        [Value::Number(value), Value::Number(lo), Value::Number(hi)] => (*value, *lo, *hi),
+        _ => return Err(CalcError::TypeMismatch("`clamp` needs three numbers".to_string())),
+    };
+    // Also rejects NaN bounds, which f64::clamp would panic on
+    if lo > hi || lo.is_nan() || hi.is_nan() {
+        return Err(CalcError::InvalidArgument(format!("`clamp` needs lo <= hi, got {lo} and {hi}")));
+    }
+    state.stack.truncate(len - 3);
+    state.stack.push(Value::Number(value.clamp(lo, hi)));
+    Ok(())
+}
+
+/// Orders a big integer against a number without rounding either of them.
+fn compare_big_number(int: &BigInt, num: f64) -> Option<std::cmp::Ordering> {
+    if num.is_nan() {
+        return None;
+    }
+    let Some(floor) = BigInt::from_f64(num.floor()) else {
+        // Only the infinities have no integer part
+        return Some(if num > 0.0 { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater });
+    };
+    match int.cmp(&floor) {
+        std::cmp::Ordering::Equal if num.fract() != 0.0 => Some(std::cmp::Ordering::Less),
+        ordering => Some(ordering),
+    }
+}
+
+/// Orders two numbers, big integers or big floats. `None` if either is NaN.
+fn compare_scalars(lhs: &Value, rhs: &Value) -> Option<std::cmp::Ordering> {
+    match (lhs, rhs) {
+        (Value::Number(lhs), Value::Number(rhs)) => lhs.partial_cmp(rhs),
+        (Value::BigInt(lhs), Value::BigInt(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::BigInt(lhs), Value::Number(rhs)) => compare_big_number(lhs, *rhs),
+        (Value::Number(lhs), Value::BigInt(rhs)) => compare_big_number(rhs, *lhs).map(std::cmp::Ordering::reverse),
+        (Value::BigFloat(x), Value::BigFloat(y)) => x.partial_cmp(y),
+        (Value::BigFloat(x), other) => x.partial_cmp(&to_bigfloat(other, bigfloat::precision(x))?),
+        (other, Value::BigFloat(y)) => to_bigfloat(other, bigfloat::precision(y))?.partial_cmp(y),
+        _ => None,
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(value, Value::Number(_) | Value::BigInt(_) | Value::BigFloat(_))
+}
+
+/// Compares the top two values and pushes whether `holds` accepts their
+/// ordering. NaN is unordered, so every comparison with it is false.
+/// Quantities compare in the same dimensions only.
+fn exec_compare(state: &mut State, operator: &str, holds: fn(std::cmp::Ordering) -> bool) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let (lhs, rhs) = (&state.stack[len - 2], &state.stack[len - 1]);
+    let ordering = match (lhs, rhs) {
+        (Value::Quantity(x), Value::Quantity(y)) => x.compare(y).ok_or_else(|| CalcError::UnitMismatch {
+            operator: operator.to_string(),
+            lhs: lhs.to_string(),
+            rhs: rhs.to_string(),
+        })?,
+        (Value::Date(x), Value::Date(y)) => x.seconds.partial_cmp(&y.seconds),
+        _ if is_scalar(lhs) && is_scalar(rhs) => compare_scalars(lhs, rhs),
+        _ => return Err(CalcError::TypeMismatch(format!("`{operator}` needs two numbers, got {lhs} and {rhs}"))),
+    };
+    let result = ordering.is_some_and(holds);
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Bool(result));
+    Ok(())
+}
+
+/// Numbers and big values are equal when they are numerically equal, and so
+/// are quantities of the same dimensions, like `1km` and `1000m`. Any
+/// other values only when they are the same kind with the same contents.
+fn values_equal(lhs: &Value, rhs: &Value) -> bool {
+    if is_scalar(lhs) && is_scalar(rhs) {
+        return compare_scalars(lhs, rhs) == Some(std::cmp::Ordering::Equal);
+    }
+    if let (Value::Quantity(x), Value::Quantity(y)) = (lhs, rhs) {
+        return x.compare(y) == Some(Some(std::cmp::Ordering::Equal));
+    }
+    lhs == rhs
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, whether the lower value is less than the top one
+ */
+fn exec_less(state: &mut State) -> Result<(), CalcError> {
+    exec_compare(state, "<", std::cmp::Ordering::is_lt)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, whether the lower value is greater than the top one
+ */
+fn exec_greater(state: &mut State) -> Result<(), CalcError> {
+    exec_compare(state, ">", std::cmp::Ordering::is_gt)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, whether the lower value is less than or equal to the top one
+ */
+fn exec_less_equal(state: &mut State) -> Result<(), CalcError> {
+    exec_compare(state, "<=", std::cmp::Ordering::is_le)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, whether the lower value is greater than or equal to the top one
+ */
+fn exec_greater_equal(state: &mut State) -> Result<(), CalcError> {
+    exec_compare(state, ">=", std::cmp::Ordering::is_ge)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, whether the two values are equal
+
+Works on any two values: `5n 5 ==` is true, and matrices are equal when they
+have the same dimensions and elements. See `aeq` for a tolerance.
+ */
+fn exec_equal(state: &mut State) -> Result<(), CalcError> {
+    let rhs = state.stack.pop().unwrap();
+    let lhs = state.stack.pop().unwrap();
+    state.stack.push(Value::Bool(values_equal(&lhs, &rhs)));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, whether the two values differ
+ */
+fn exec_not_equal(state: &mut State) -> Result<(), CalcError> {
+    let rhs = state.stack.pop().unwrap();
+    let lhs = state.stack.pop().unwrap();
+    state.stack.push(Value::Bool(!values_equal(&lhs, &rhs)));
+    Ok(())
+}
+
+/// The truth of a value for the logical operators. Numbers are true unless
+/// they are zero, so the 1 or 0 from `isprime` or `aeq` can be combined too.
+fn truth(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(bool) => Some(*bool),
+        Value::Matrix(_) | Value::Str(_) | Value::Interval(_) | Value::Quantity(_) | Value::Dual(_) | Value::Poly(_)
+        | Value::Quat(_) | Value::Date(_) => None,
+        scalar => Some(!has_zero(scalar)),
+    }
+}
+
+/// Pops two truth values and pushes `apply` of them, for `and` and `or`.
+fn exec_logical(state: &mut State, operator: &str, apply: fn(bool, bool) -> bool) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let (lhs, rhs) = (&state.stack[len - 2], &state.stack[len - 1]);
+    let (Some(lhs), Some(rhs)) = (truth(lhs), truth(rhs)) else {
+        return Err(CalcError::TypeMismatch(format!("`{operator}` needs two booleans, got {lhs} and {rhs}")));
+    };
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Bool(apply(lhs, rhs)));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, true if both values are true
+ */
+fn exec_and(state: &mut State) -> Result<(), CalcError> {
+    exec_logical(state, "and", |lhs, rhs| lhs && rhs)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, true if either value is true
+ */
+fn exec_or(state: &mut State) -> Result<(), CalcError> {
+    exec_logical(state, "or", |lhs, rhs| lhs || rhs)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the opposite truth value
+ */
+fn exec_not(state: &mut State) -> Result<(), CalcError> {
+    let value = state.stack.last().unwrap();
+    let Some(bool) = truth(value) else {
+        return Err(CalcError::TypeMismatch(format!("`not` needs a boolean, got {value}")));
+    };
+    *state.stack.last_mut().unwrap() = Value::Bool(!bool);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push
+ */
+fn exec_true(state: &mut State) -> Result<(), CalcError> {
+    state.stack.push(Value::Bool(true));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push
+ */
+fn exec_false(state: &mut State) -> Result<(), CalcError> {
+    state.stack.push(Value::Bool(false));
+    Ok(())
+}
+
+/// The text `tostr` and `concat` turn a value into: strings as they are, and
+/// scalars as printed with the current `prec`.
+fn value_text(state: &State, value: &Value, operator: &str) -> Result<String, CalcError> {
+    match value {
+        Value::Str(string) => Ok(string.clone()),
+        Value::Matrix(_) => Err(CalcError::TypeMismatch(format!("`{operator}` cannot convert a matrix"))),
+        scalar => Ok(state.show(scalar)),
+    }
+}
+
+/// Reads the top value as a string without popping it.
+fn peek_str<'a>(state: &'a State, operator: &str) -> Result<&'a str, CalcError> {
+    match state.stack.last().unwrap() {
+        Value::Str(string) => Ok(string),
+        other => Err(CalcError::TypeMismatch(format!("`{operator}` needs a string, got {other}"))),
+    }
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the two joined, lower value first
+
+A value that is not a string is converted like `tostr` does, so
+`"x = " 2 sqrt concat` makes a label.
+ */
+fn exec_concat(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let lhs = value_text(state, &state.stack[len - 2], "concat")?;
+    let rhs = value_text(state, &state.stack[len - 1], "concat")?;
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Str(lhs + &rhs));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the number of characters in the string
+ */
+fn exec_len(state: &mut State) -> Result<(), CalcError> {
+    let len = peek_str(state, "len")?.chars().count();
+    *state.stack.last_mut().unwrap() = Value::Number(len as f64);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_upper(state: &mut State) -> Result<(), CalcError> {
+    let upper = peek_str(state, "upper")?.to_uppercase();
+    *state.stack.last_mut().unwrap() = Value::Str(upper);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_lower(state: &mut State) -> Result<(), CalcError> {
+    let lower = peek_str(state, "lower")?.to_lowercase();
+    *state.stack.last_mut().unwrap() = Value::Str(lower);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop, a string of pc source
+- whatever the source does to the rest of the stack
+
+Evaluations nest, so a string that evaluates itself stops at `--max-depth`.
+ */
+fn exec_eval(state: &mut State) -> Result<(), CalcError> {
+    let source = peek_str(state, "eval")?.to_string();
+    state.stack.pop();
+    exec(state, &source).map_err(|err| err.error)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the number the string spells
+
+Accepts what a number literal may be, including big integers like `"12n"`;
+surrounding whitespace is ignored.
+ */
+fn exec_tonum(state: &mut State) -> Result<(), CalcError> {
+    let text = peek_str(state, "tonum")?.trim();
+    let Some(value) = parse_number_literal(state, text) else {
+        return Err(CalcError::InvalidArgument(format!("{text:?} is not a number")));
+    };
+    *state.stack.last_mut().unwrap() = value;
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the value as a string, with the digits set by `prec`
+ */
+fn exec_tostr(state: &mut State) -> Result<(), CalcError> {
+    let text = value_text(state, state.stack.last().unwrap(), "tostr")?;
+    *state.stack.last_mut().unwrap() = Value::Str(text);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+Only defined for non-negative integers, see `gamma` for everything else. The
+factorial of a big integer is exact: `30n fact`.
+ */
+fn exec_fact(state: &mut State) -> Result<(), CalcError> {
+    if let Value::BigInt(int) = state.stack.last().unwrap() {
+        let n = int.to_u64().ok_or_else(|| CalcError::InvalidArgument(
+            format!("Factorial needs a non-negative integer, got {int}")
+        ))?;
+        check_big_bits("fact", bignum::factorial_bits(n))?;
+        *state.stack.last_mut().unwrap() = Value::BigInt(bignum::factorial(n));
+        return Ok(());
+    }
+    exec_unary_checked(state, |value| match to_usize(value) {
+        Some(n) => Ok(special::factorial(n as u64)),
+        None => Err(CalcError::InvalidArgument(
+            format!("Factorial needs a non-negative integer, got {value}")
+        )),
+    })
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_gamma(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, special::gamma)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+The natural logarithm of the absolute value of `gamma`.
+ */
+fn exec_lgamma(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, special::ln_gamma)
+}
+
+/// Reads the top two values as non-negative integers without popping them.
+fn peek_integer_pair(state: &State, operator: &str) -> Result<(u64, u64), CalcError> {
+    let len = state.stack.len();
+    match &state.stack[len - 2..] {
+        [Value::Number(lhs), Value::Number(rhs)] => match (to_usize(*lhs), to_usize(*rhs)) {
+            (Some(lhs), Some(rhs)) => Ok((lhs as u64, rhs as u64)),
+            _ => Err(CalcError::InvalidArgument(
+                format!("`{operator}` needs non-negative integers, got {lhs} and {rhs}")
+            )),
+        },
+        _ => Err(CalcError::TypeMismatch(format!("`{operator}` needs two numbers"))),
+    }
+}
+
+/// Checks the big integer `n` and `k` of `ncr` and `npr`. Returns `k`, and
+/// whether it is more than `n`, in which case there are no ways to pick.
+fn peek_big_choice(n: &BigInt, k: &BigInt, operator: &str) -> Result<(u64, bool), CalcError> {
+    if n.is_negative() || k.is_negative() {
+        return Err(CalcError::InvalidArgument(
+            format!("`{operator}` needs non-negative integers, got {n} and {k}")
+        ));
+    }
+    match k.to_u64() {
+        Some(small) if k <= n => Ok((small, false)),
+        _ if k > n => Ok((0, true)),
+        _ => Err(CalcError::InvalidArgument(format!("`{operator}` cannot pick {k} values"))),
+    }
+}
+
+/**
+Variables: `k`, then `n`
+
+Stack changes:
+
+- 2 pop
+- 1 push, the number of ways to choose `k` out of `n`
+ */
+fn exec_ncr(state: &mut State) -> Result<(), CalcError> {
+    if let Some([n, k]) = peek_big::<2>(state) {
+        let (k, beyond) = peek_big_choice(&n, &k, "ncr")?;
+        // Choosing k is choosing the n - k left out, whichever is fewer
+        let k = (&n - k).to_u64().map_or(k, |rest| rest.min(k));
+        check_big_bits("ncr", k.saturating_mul(n.bits()))?;
+        state.stack.truncate(state.stack.len() - 2);
+        let result = if beyond { BigInt::zero() } else { bignum::binomial(&n, k) };
+        state.stack.push(Value::BigInt(result));
+        return Ok(());
+    }
+    let (n, k) = peek_integer_pair(state, "ncr")?;
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Number(special::binomial(n, k)));
+    Ok(())
+}
+
+/**
+Variables: `k`, then `n`
+
+Stack changes:
+
+- 2 pop
+- 1 push, the number of ordered ways to pick `k` out of `n`
+ */
+fn exec_npr(state: &mut State) -> Result<(), CalcError> {
+    if let Some([n, k]) = peek_big::<2>(state) {
+        let (k, beyond) = peek_big_choice(&n, &k, "npr")?;
+        check_big_bits("npr", k.saturating_mul(n.bits()))?;
+        state.stack.truncate(state.stack.len() - 2);
+        let result = if beyond { BigInt::zero() } else { bignum::permutations(&n, k) };
+        state.stack.push(Value::BigInt(result));
+        return Ok(());
+    }
+    let (n, k) = peek_integer_pair(state, "npr")?;
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Number(special::permutations(n, k)));
+    Ok(())
+}
+
+/// Largest integer magnitude an `f64` holds exactly, 2^53.
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Converts a stack number into an integer, if it is one `f64` represents exactly.
+fn to_i64(value: f64) -> Option<i64> {
+    if value.fract() == 0.0 && value.abs() <= MAX_EXACT_INTEGER {
+        Some(value as i64)
+    } else {
+        None
+    }
+}
+
+/// Reads the top `N` values as exact integers without popping them, deepest first.
+fn peek_exact<const N: usize>(state: &State, operator: &str) -> Result<[i64; N], CalcError> {
+    let mut integers = [0; N];
+    for (integer, value) in integers.iter_mut().zip(&state.stack[state.stack.len() - N..]) {
+        *integer = match value {
+            Value::Number(num) => to_i64(*num).ok_or_else(|| CalcError::InvalidArgument(
+                format!("`{operator}` needs integers up to 2^53, got {num}")
+            ))?,
+            Value::BigInt(int) => int.to_i64().filter(|n| n.unsigned_abs() <= 1 << 53).ok_or_else(|| {
+                CalcError::InvalidArgument(format!("`{operator}` needs integers up to 2^53, got {int}"))
+            })?,
+            other => return Err(CalcError::TypeMismatch(
+                format!("`{operator}` needs numbers, got {other}")
+            )),
+        };
+    }
+    Ok(integers)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the greatest common divisor, never negative
+ */
+fn exec_gcd(state: &mut State) -> Result<(), CalcError> {
+    if let Some(result) = exec_big_binary(state, |lhs, rhs| Ok(lhs.gcd(&rhs))) {
+        return result;
+    }
+    let [lhs, rhs] = peek_exact::<2>(state, "gcd")?;
+    state.stack.truncate(state.stack.len() - 2);
+    let gcd = numtheory::gcd(lhs.unsigned_abs(), rhs.unsigned_abs());
+    state.stack.push(Value::Number(gcd as f64));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the least common multiple, never negative
+ */
+fn exec_lcm(state: &mut State) -> Result<(), CalcError> {
+    if let Some(result) = exec_big_binary(state, |lhs, rhs| Ok(lhs.lcm(&rhs))) {
+        return result;
+    }
+    let [lhs, rhs] = peek_exact::<2>(state, "lcm")?;
+    state.stack.truncate(state.stack.len() - 2);
+    let lcm = numtheory::lcm(lhs.unsigned_abs(), rhs.unsigned_abs());
+    state.stack.push(Value::Number(lcm as f64));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, 1 if the value is a prime and 0 otherwise
+
+Numbers are exact integers only up to 2^53; larger ones are tested as big
+integers, like `2n 89 ^ 1 - isprime`. Past 3.3 * 10^24 the test is
+probabilistic, though a wrong answer is vanishingly unlikely.
+ */
+fn exec_isprime(state: &mut State) -> Result<(), CalcError> {
+    if let Value::BigInt(int) = state.stack.last().unwrap() {
+        let prime = bignum::is_probable_prime(int);
+        *state.stack.last_mut().unwrap() = Value::Number(if prime { 1.0 } else { 0.0 });
+        return Ok(());
+    }
+    exec_unary_checked(state, |value| match to_i64(value) {
+        Some(n) => {
+            let prime = n > 0 && numtheory::is_prime(n as u64);
+            Ok(if prime { 1.0 } else { 0.0 })
+        },
+        None => Err(CalcError::InvalidArgument(
+            format!("`isprime` needs an integer up to 2^53 or a big integer like 123n, got {value}")
+        )),
+    })
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, a 1xk matrix of the prime factors in ascending order
+
+Numbers go up to 2^53, and big integers up to 2^64 - 1.
+ */
+fn exec_factor(state: &mut State) -> Result<(), CalcError> {
+    let n = match state.stack.last().unwrap() {
+        Value::Number(value) => match to_i64(*value) {
+            Some(n) if n > 1 => n as u64,
+            _ => return Err(CalcError::InvalidArgument(
+                format!("`factor` needs an integer from 2 to 2^53, got {value}")
+            )),
+        },
+        Value::BigInt(int) => match int.to_u64() {
+            Some(n) if n > 1 => n,
+            _ => return Err(CalcError::InvalidArgument(
+                format!("`factor` needs an integer from 2 to 2^64 - 1, got {int}")
+            )),
+        },
+        value => return Err(CalcError::TypeMismatch(format!("Unsupported operation on {}", value))),
+    };
+    state.stack.pop();
+    let factors = numtheory::factorize(n).into_iter()
+        .map(|factor| factor as f64)
+        .collect::<Vec<f64>>();
+    state.stack.push(Value::Matrix(Matrix::from(1, factors.len(), factors.into_boxed_slice())));
+    Ok(())
+}
+
+/**
+Variables: `m`, `b`, then `a`
+
+Stack changes:
+
+- 3 pop
+- 1 push, `a` to the power `b` modulo `m`
+ */
+fn exec_modpow(state: &mut State) -> Result<(), CalcError> {
+    let invalid = || CalcError::InvalidArgument(
+        "`modpow` needs a non-negative exponent and a positive modulus".to_string()
+    );
+    if let Some([base, exponent, modulus]) = peek_big::<3>(state) {
+        if exponent.is_negative() || !modulus.is_positive() {
+            return Err(invalid());
+        }
+        state.stack.truncate(state.stack.len() - 3);
+        state.stack.push(Value::BigInt(base.modpow(&exponent, &modulus)));
+        return Ok(());
+    }
+    let [base, exponent, modulus] = peek_exact::<3>(state, "modpow")?;
+    if exponent < 0 || modulus <= 0 {
+        return Err(invalid());
+    }
+    state.stack.truncate(state.stack.len() - 3);
+    let base = base.rem_euclid(modulus) as u64;
+    let result = numtheory::pow_mod(base, exponent as u64, modulus as u64);
+    state.stack.push(Value::Number(result as f64));
+    Ok(())
+}
+
+/**
+Variables: `m`, then `a`
+
+Stack changes:
+
+- 2 pop
+- 1 push, the `x` in `0..m` with `a x` congruent to 1 modulo `m`
+ */
+fn exec_modinv(state: &mut State) -> Result<(), CalcError> {
+    let [value, modulus] = peek_exact::<2>(state, "modinv")?;
+    if modulus <= 0 {
+        return Err(CalcError::InvalidArgument("`modinv` needs a positive modulus".to_string()));
+    }
+    let Some(inverse) = numtheory::inverse_mod(value.rem_euclid(modulus) as u64, modulus as u64) else {
+        return Err(CalcError::InvalidArgument(format!("{value} has no inverse modulo {modulus}")));
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Number(inverse as f64));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+ */
+fn exec_erf(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, special::erf)
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+Accurate far into the tail, where `1 erf -` would round to zero.
+ */
+fn exec_erfc(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, special::erfc)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+ */
+fn exec_beta(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(special::beta(lhs, rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push
+
+`x %` is `x` percent as a fraction, so `50 %` is 0.5.
+ */
+fn exec_percent(state: &mut State) -> Result<(), CalcError> {
+    exec_unary(state, |value| value/100.0)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+`a b addpct` is `a` increased by `b` percent; a negative `b` decreases it.
+ */
+fn exec_addpct(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs*(1.0 + rhs/100.0)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+`a b pctof` is how many percent of `b` the value `a` is.
+ */
+fn exec_pctof(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs/rhs*100.0));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push, uniformly random in `[0, 1)`
+ */
+fn exec_rand(state: &mut State) -> Result<(), CalcError> {
+    let value = state.rng.uniform();
+    state.stack.push(Value::Number(value));
+    Ok(())
+}
+
+/**
+Variables: `b`, then `a`
+
+Stack changes:
+
+- 2 pop
+- 1 push, a uniformly random integer from `a` to `b`, both included
+ */
+fn exec_randint(state: &mut State) -> Result<(), CalcError> {
+    let [lo, hi] = peek_exact::<2>(state, "randint")?;
+    if lo > hi {
+        return Err(CalcError::InvalidArgument(format!("`randint` needs a <= b, got {lo} and {hi}")));
+    }
+    state.stack.truncate(state.stack.len() - 2);
+    let offset = state.rng.below((hi - lo) as u64 + 1);
+    state.stack.push(Value::Number((lo + offset as i64) as f64));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push, a standard normally distributed random number
+ */
+fn exec_randn(state: &mut State) -> Result<(), CalcError> {
+    let value = state.rng.normal();
+    state.stack.push(Value::Number(value));
+    Ok(())
+}
+
+/**
+Variables: `col` then `row`
+
+Stack changes:
+
+- 2 pop
+- 1 push, a `row x col` matrix of uniform random numbers in `[0, 1)`
+
+Draws from the same generator as `rand`, so `seed` makes it reproducible.
+ */
+fn exec_randmat(state: &mut State) -> Result<(), CalcError> {
+    let (row, col) = peek_shape(state, 0)?;
+    check_generated_size(row, col)?;
+    let data = (0..row * col).map(|_| state.rng.uniform()).collect();
+    replace_with_matrix(state, 2, row, col, data);
+    Ok(())
+}
+
+/**
+Variables: `col` then `row`
+
+Stack changes:
+
+- 2 pop
+- 1 push, a `row x col` matrix of standard normal random numbers
+ */
+fn exec_randnmat(state: &mut State) -> Result<(), CalcError> {
+    let (row, col) = peek_shape(state, 0)?;
+    check_generated_size(row, col)?;
+    let data = (0..row * col).map(|_| state.rng.normal()).collect();
+    replace_with_matrix(state, 2, row, col, data);
+    Ok(())
+}
+
+/**
+Variables: `n`
+
+Stack changes:
+
+- 1 pop
+
+Restarts the random number generator from seed `n`, so the numbers that follow
+are the same on every run.
+ */
+fn exec_seed(state: &mut State) -> Result<(), CalcError> {
+    let [seed] = peek_exact::<1>(state, "seed")?;
+    state.stack.pop();
+    state.rng = random::Rng::from_seed(seed as u64);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+sqrt(x² + y²) without overflowing or underflowing in between.
+ */
+fn exec_hypot(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(lhs.hypot(rhs)));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Variables: `y2`, `x2`, `y1`, then `x1`
+
+Stack changes:
+
+- 4 pop
+- 1 push, the distance between the points (`x1`, `y1`) and (`x2`, `y2`)
+ */
+fn exec_dist2d(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let distance = match &state.stack[len - 4..] {
+        [Value::Number(x1), Value::Number(y1), Value::Number(x2), Value::Number(y2)] => {
+            (x2 - x1).hypot(y2 - y1)
+        },
+        _ => return Err(CalcError::TypeMismatch("`dist2d` needs four numbers".to_string())),
+    };
+    state.stack.truncate(len - 4);
+    state.stack.push(Value::Number(distance));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the value as an integer
+
+A fractional part is cut off, or is an error with `--int-policy error`.
+ */
+fn exec_int(state: &mut State) -> Result<(), CalcError> {
+    let integer = match state.stack.last().unwrap() {
+        Value::Number(value) => state.coerce_int("int", *value)?,
+        value => return Err(CalcError::TypeMismatch(format!("Unsupported operation on {}", value))),
+    };
+    state.stack.pop();
+    state.stack.push(Value::Number(integer as f64));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the integer as a big integer, like typing it with an `n` suffix
+ */
+fn exec_big(state: &mut State) -> Result<(), CalcError> {
+    let value = state.stack.last().unwrap();
+    let Some(int) = to_big(value) else {
+        return Err(CalcError::InvalidArgument(format!("`big` needs an integer, got {value}")));
+    };
+    *state.stack.last_mut().unwrap() = Value::BigInt(int);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, a big integer or big float as the nearest number
+ */
+fn exec_float(state: &mut State) -> Result<(), CalcError> {
+    demote_big(state, 1);
+    Ok(())
+}
+
+/**
+Variables: `hi`, then `lo`
+
+Stack changes:
+
+- 2 pop
+- 1 push, the interval from `lo` to `hi`
+
+Arithmetic, powers, `abs`, `sqrt`, `exp`, `ln`, `sin`, `cos` and `tan` on
+intervals round outward, so the result holds every value the operation could
+give for points of the operands. `tan` across a pole, like `/` by an interval
+holding zero, gives the whole real line.
+ */
+fn exec_interval(state: &mut State) -> Result<(), CalcError> {
+    let [lo, hi] = peek_numbers::<2>(state, "interval")?;
+    if lo > hi || lo.is_nan() || hi.is_nan() {
+        return Err(CalcError::InvalidArgument(format!("`interval` needs lo <= hi, got {lo} and {hi}")));
+    }
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Interval(Interval::new(lo, hi)));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 2 push, the lower bound, then the upper bound on top
+ */
+fn exec_bounds(state: &mut State) -> Result<(), CalcError> {
+    let Value::Interval(interval) = *state.stack.last().unwrap() else {
+        let value = state.stack.last().unwrap();
+        return Err(CalcError::TypeMismatch(format!("`bounds` needs an interval, got {value}")));
+    };
+    state.stack.pop();
+    state.stack.push(Value::Number(interval.lo));
+    state.stack.push(Value::Number(interval.hi));
+    Ok(())
+}
+
+/// Reads a unit like `"km"` or `"m/s^2"` from a string.
+fn parse_unit(value: &Value, op: &str) -> Result<Unit, CalcError> {
+    match value {
+        Value::Str(text) => Unit::parse(text).ok_or_else(|| CalcError::InvalidArgument(
+            format!("`{op}` needs a unit like \"km\" or \"m/s^2\", got {text:?}")
+        )),
+        other => Err(CalcError::TypeMismatch(format!("`{op}` needs a unit string, got {other}"))),
+    }
+}
+
+/**
+Stack changes:
+
+- 1 pop, a unit string like `"km"` or `"m/s^2"`
+- 1 pop and 1 push, a number below it measured in the unit, or 1 push of
+  one of the unit on anything else
+
+`5 "km" unit` is the same as the literal `5km`; the string form takes any
+number, like the value of a variable.
+ */
+fn exec_unit(state: &mut State) -> Result<(), CalcError> {
+    let unit = parse_unit(state.stack.last().unwrap(), "unit")?;
+    state.stack.pop();
+    let value = match state.stack.last() {
+        Some(Value::Number(num)) => Some(*num),
+        Some(Value::BigInt(int)) => Some(big_to_f64(int)),
+        Some(Value::BigFloat(x)) => Some(bigfloat::to_f64(x)),
+        _ => None,
+    };
+    match value {
+        Some(value) => *state.stack.last_mut().unwrap() = Value::Quantity(Quantity::new(value, unit)),
+        None => state.stack.push(Value::Quantity(Quantity::new(1.0, unit))),
+    }
+    Ok(())
+}
+
+/**
+Variables: `unit`, then `x`
+
+Stack changes:
+
+- 2 pop
+- 1 push, `x` converted to `unit`
+
+`unit` is a unit string, or a quantity of which only the unit matters, as
+in `1mile "km" to`. Temperatures move to the other scale, so
+`25degC "degF" to` is 77 degF.
+ */
+fn exec_to(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let target = match &state.stack[len - 1] {
+        Value::Quantity(target) => target.unit.clone(),
+        other => parse_unit(other, "to")?,
+    };
+    let Value::Quantity(quantity) = &state.stack[len - 2] else {
+        let (lhs, rhs) = (&state.stack[len - 2], &state.stack[len - 1]);
+        return Err(CalcError::TypeMismatch(format!("`to` needs a quantity and a unit, got {lhs} and {rhs}")));
+    };
+    let Some(result) = quantity.convert(&target) else {
+        return Err(CalcError::UnitMismatch {
+            operator: "to".to_string(),
+            lhs: quantity.unit.to_string(),
+            rhs: target.to_string(),
+        });
+    };
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Quantity(result));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, `x + ε`, the marked variable at `x`
+
+Arithmetic, powers and the elementary functions carry the `ε` part along as
+the derivative with respect to the marked variable, so
+`2 dual dup 3 ^ swap sin +` is `8 + sin 2` together with `12 + cos 2`, in
+radians. `parts` takes the two apart.
+ */
+fn exec_dual(state: &mut State) -> Result<(), CalcError> {
+    let [x] = peek_numbers::<1>(state, "dual")?;
+    *state.stack.last_mut().unwrap() = Value::Dual(Dual::variable(x));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 2 push, the value and then the derivative of a dual number, or 4 push, the
+  `w`, `x`, `y` and `z` of a quaternion
+ */
+fn exec_parts(state: &mut State) -> Result<(), CalcError> {
+    let parts = match *state.stack.last().unwrap() {
+        Value::Dual(dual) => vec![dual.value, dual.deriv],
+        Value::Quat(Quat { w, x, y, z }) => vec![w, x, y, z],
+        ref value => return Err(CalcError::TypeMismatch(
+            format!("`parts` needs a dual number or quaternion, got {value}")
+        )),
+    };
+    state.stack.pop();
+    state.stack.extend(parts.into_iter().map(Value::Number));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop, a vector of the coefficients `c_n`, ..., `c_0`
+- 1 push, the polynomial `c_n x^n + ... + c_0`
+
+Coefficients go highest degree first, so `[1 0 -2 3] poly` is `x^3 - 2x + 3`.
+
+`+`, `-` and `*` combine polynomials with each other and with numbers.
+ */
+fn exec_poly(state: &mut State) -> Result<(), CalcError> {
+    let mat = match state.stack.last().unwrap() {
+        Value::Matrix(mat) if mat.row == 1 || mat.col == 1 => mat,
+        Value::Matrix(mat) => {
+            return Err(CalcError::TypeMismatch(format!("`poly` needs a vector, got a {}x{} matrix", mat.row, mat.col)));
+        },
+        value => return Err(CalcError::TypeMismatch(format!("`poly` needs a vector of coefficients, got {value}"))),
+    };
+    let poly = Poly::from_highest(&mat.data);
+    *state.stack.last_mut().unwrap() = Value::Poly(poly);
+    Ok(())
+}
+
+fn peek_poly<'a>(state: &'a State, op: &str, depth: usize) -> Result<&'a Poly, CalcError> {
+    match &state.stack[state.stack.len() - 1 - depth] {
+        Value::Poly(poly) => Ok(poly),
+        other => Err(CalcError::TypeMismatch(format!("`{op}` needs a polynomial, got {other}"))),
+    }
+}
+
+/**
+Variables: `x`, then `p`
+
+Stack changes:
+
+- 2 pop
+- 1 push, `p(x)`, element by element for a matrix `x`
+ */
+fn exec_polyval(state: &mut State) -> Result<(), CalcError> {
+    let poly = peek_poly(state, "polyval", 1)?;
+    let result = match state.stack.last().unwrap() {
+        Value::Number(x) => Value::Number(poly.eval(*x)),
+        Value::Matrix(mat) => Value::Matrix(mat.map(|x| poly.eval(x))),
+        Value::Dual(x) => Value::Dual(Dual::new(poly.eval(x.value), poly.deriv().eval(x.value) * x.deriv)),
+        other => return Err(CalcError::TypeMismatch(format!("`polyval` needs a number or matrix, got {other}"))),
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(result);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the derivative of the polynomial
+ */
+fn exec_polyderiv(state: &mut State) -> Result<(), CalcError> {
+    let deriv = peek_poly(state, "polyderiv", 0)?.deriv();
+    *state.stack.last_mut().unwrap() = Value::Poly(deriv);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the antiderivative of the polynomial with a constant term of 0
+ */
+fn exec_polyint(state: &mut State) -> Result<(), CalcError> {
+    let integral = peek_poly(state, "polyint", 0)?.integral();
+    *state.stack.last_mut().unwrap() = Value::Poly(integral);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 2 push, a `1 x n` row of the real parts of the `n` roots, then one of
+  their imaginary parts
+
+The roots are the eigenvalues of the companion matrix, ordered by real part
+and then by imaginary part, so complex ones come in conjugate pairs with the
+negative part first.
+ */
+fn exec_roots(state: &mut State) -> Result<(), CalcError> {
+    let poly = peek_poly(state, "roots", 0)?;
+    if poly.degree().is_none_or(|degree| degree == 0) {
+        return Err(CalcError::InvalidArgument(format!("`roots` needs a polynomial of degree 1 or more, got {poly}")));
+    }
+    let roots = poly.roots()?;
+    let (re, im): (Vec<_>, Vec<_>) = roots.into_iter().unzip();
+    let n = re.len();
+    state.stack.pop();
+    state.stack.push(Value::Matrix(Matrix::from(1, n, re.into_boxed_slice())));
+    state.stack.push(Value::Matrix(Matrix::from(1, n, im.into_boxed_slice())));
+    Ok(())
+}
+
+/**
+Variables: `z`, then `y`, `x` and `w`
+
+Stack changes:
+
+- 4 pop
+- 1 push, the quaternion `w + xi + yj + zk`
+
+`+`, `-`, `*` and `/` combine quaternions with each other and with numbers;
+`p q *` is the rotation by `q` followed by `p`, and `/` multiplies by the
+inverse. `abs` is the norm, `normalize` scales to unit norm and `parts` takes
+the four apart again.
+ */
+fn exec_quat(state: &mut State) -> Result<(), CalcError> {
+    let [w, x, y, z] = peek_numbers::<4>(state, "quat")?;
+    state.stack.truncate(state.stack.len() - 4);
+    state.stack.push(Value::Quat(Quat::new(w, x, y, z)));
+    Ok(())
+}
+
+fn peek_quat(state: &State, op: &str) -> Result<Quat, CalcError> {
+    match state.stack.last().unwrap() {
+        Value::Quat(quat) => Ok(*quat),
+        other => Err(CalcError::TypeMismatch(format!("`{op}` needs a quaternion, got {other}"))),
+    }
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the conjugate `w - xi - yj - zk`, the inverse rotation of a unit
+  quaternion
+ */
+fn exec_conj(state: &mut State) -> Result<(), CalcError> {
+    let conj = peek_quat(state, "conj")?.conj();
+    *state.stack.last_mut().unwrap() = Value::Quat(conj);
+    Ok(())
+}
+
+/**
+Variables: `angle`, then `axis`
+
+Stack changes:
+
+- 2 pop
+- 1 push, the unit quaternion rotating by the angle about the axis
+
+The axis is a 3-vector of any nonzero length; the angle is in the current
+angle mode, counterclockwise looking down the axis like `rotx` and co.
+ */
+fn exec_axisangle(state: &mut State) -> Result<(), CalcError> {
+    let axis = peek_vector(state, "axisangle", 1)?;
+    let Ok(axis) = <[f64; 3]>::try_from(&axis.data[..]) else {
+        return Err(CalcError::InvalidArgument(
+            format!("`axisangle` needs a 3-vector axis, got a {}x{} matrix", axis.row, axis.col)
+        ));
+    };
+    let [angle] = peek_numbers::<1>(state, "axisangle")?;
+    let Some(quat) = Quat::from_axis_angle(axis, state.angle.to_radians(angle)) else {
+        return Err(CalcError::InvalidArgument("`axisangle` needs a non-zero axis".to_string()));
+    };
+    state.stack.truncate(state.stack.len() - 2);
+    state.stack.push(Value::Quat(quat));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the 3x3 matrix rotating column vectors like the quaternion
+
+A quaternion that is not a unit one rotates like its normalization.
+ */
+fn exec_rotmat(state: &mut State) -> Result<(), CalcError> {
+    let Some(rotation) = peek_quat(state, "rotmat")?.to_rotation() else {
+        return Err(CalcError::InvalidArgument("`rotmat` needs a non-zero quaternion".to_string()));
+    };
+    replace_with_matrix(state, 1, 3, 3, rotation.to_vec());
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the unit quaternion of a 3x3 rotation matrix, with `w >= 0`
+
+The matrix has to be orthogonal with determinant 1, up to rounding.
+ */
+fn exec_toquat(state: &mut State) -> Result<(), CalcError> {
+    let mat = peek_square(state, "toquat")?;
+    if mat.row != 3 {
+        return Err(CalcError::InvalidArgument(format!("`toquat` needs a 3x3 matrix, got {}x{}", mat.row, mat.col)));
+    }
+    // Orthonormal rows, so that `R R^T = I`
+    let rows = mat.data.chunks(3).collect::<Vec<_>>();
+    let identity = linalg::identity(3);
+    let orthogonal = (0..9).all(|k| (dot(rows[k / 3], rows[k % 3]) - identity[k]).abs() <= 1e-9);
+    if !orthogonal || linalg::lu(3, &mat.data)?.det() < 0.0 {
+        return Err(CalcError::InvalidArgument("`toquat` needs a rotation matrix".to_string()));
+    }
+    let quat = Quat::from_rotation(&mat.data);
+    *state.stack.last_mut().unwrap() = Value::Quat(quat);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the date read from a string like `"2024-06-01"` or
+  `"2024-06-01T12:30"`, or the start of the day of a date
+
+Dates are in UTC on the Gregorian calendar. The same forms can be entered
+without quotes, like `2024-06-01 3d +`.
+ */
+fn exec_date(state: &mut State) -> Result<(), CalcError> {
+    let date = match state.stack.last().unwrap() {
+        Value::Str(text) => DateTime::parse(text).ok_or_else(|| CalcError::InvalidArgument(
+            format!("`date` needs a date like 2024-06-01 or 2024-06-01T12:30:00, got {text:?}")
+        ))?,
+        Value::Date(date) => date.midnight(),
+        other => return Err(CalcError::TypeMismatch(format!("`date` needs a string or a date, got {other}"))),
+    };
+    *state.stack.last_mut().unwrap() = Value::Date(date);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push, the current date and time in UTC
+ */
+fn exec_now(state: &mut State) -> Result<(), CalcError> {
+    state.stack.push(Value::Date(DateTime::now()));
+    Ok(())
+}
+
+fn peek_date(value: &Value, op: &str) -> Result<DateTime, CalcError> {
+    match value {
+        Value::Date(date) => Ok(*date),
+        other => Err(CalcError::TypeMismatch(format!("`{op}` needs a date, got {other}"))),
+    }
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, the name of the day of the week of a date, like `"Saturday"`
+ */
+fn exec_weekday(state: &mut State) -> Result<(), CalcError> {
+    let date = peek_date(state.stack.last().unwrap(), "weekday")?;
+    *state.stack.last_mut().unwrap() = Value::Str(date.weekday().to_string());
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push, the number of calendar days from the lower date to the top one
+
+Times of day are ignored, so the evening before is one day earlier. `-`
+gives the exact duration instead.
+ */
+fn exec_days_between(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let from = peek_date(&state.stack[len - 2], "days_between")?;
+    let to = peek_date(&state.stack[len - 1], "days_between")?;
+    let days = (to.midnight().seconds - from.midnight().seconds) / 86_400.0;
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Number(days));
+    Ok(())
+}
+
+/// Replaces an amount of data on top of the stack with it in `unit` of the
+/// number of bytes.
+fn exec_bytes_in(state: &mut State, op: &str, unit: fn(f64) -> Unit) -> Result<(), CalcError> {
+    let bytes = match state.stack.last().unwrap() {
+        Value::Quantity(quantity) => quantity.value_in(&Unit::parse("B").unwrap()),
+        _ => None,
+    };
+    let Some(bytes) = bytes else {
+        let value = state.stack.last().unwrap();
+        return Err(CalcError::TypeMismatch(format!("`{op}` needs an amount of data like 4KiB, got {value}")));
+    };
+    let unit = unit(bytes);
+    let quantity = Quantity::new(bytes, Unit::parse("B").unwrap()).convert(&unit).unwrap();
+    *state.stack.last_mut().unwrap() = Value::Quantity(quantity);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, an amount of data in bytes, so `1.5KiB tobytes` is 1536 B
+ */
+fn exec_tobytes(state: &mut State) -> Result<(), CalcError> {
+    exec_bytes_in(state, "tobytes", |_| Unit::parse("B").unwrap())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, an amount of data with the largest SI prefix that leaves at
+  least 1, so `1GiB tosibytes` is 1.073741824 GB
+ */
+fn exec_tosibytes(state: &mut State) -> Result<(), CalcError> {
+    exec_bytes_in(state, "tosibytes", |bytes| units::byte_unit(bytes, false))
+}
+
+/**
+Stack changes:
+
+- 1 pop
+- 1 push, an amount of data with the largest binary prefix that leaves at
+  least 1, so `1.5GB toiecbytes` is about 1.397 GiB
+ */
+fn exec_toiecbytes(state: &mut State) -> Result<(), CalcError> {
+    exec_bytes_in(state, "toiecbytes", |bytes| units::byte_unit(bytes, true))
+}
+
+/// Pops two integer operands and pushes `apply(lhs, rhs)`, for `idiv` and
+/// `imod`. Dividing by zero is always an error since integers have no infinity.
+fn exec_integer_op(
+    state: &mut State,
+    operator: &str,
+    apply: fn(i64, i64) -> i64,
+) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    let (lhs, rhs) = match &state.stack[len - 2..] {
+        [Value::Number(lhs), Value::Number(rhs)] => {
+            (state.coerce_int(operator, *lhs)?, state.coerce_int(operator, *rhs)?)
+        },
+        _ => return Err(CalcError::TypeMismatch(format!("`{operator}` needs two numbers"))),
+    };
+    if rhs == 0 {
+        return Err(CalcError::DivisionByZero);
+    }
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Number(apply(lhs, rhs) as f64));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+Integer quotient rounded toward zero: `-7 2 idiv` is -3.
+ */
+fn exec_idiv(state: &mut State) -> Result<(), CalcError> {
+    exec_integer_op(state, "idiv", |lhs, rhs| lhs / rhs)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+
+Integer remainder matching `idiv`, with the sign of the dividend: `-7 2 imod` is -1.
+ */
+fn exec_imod(state: &mut State) -> Result<(), CalcError> {
+    exec_integer_op(state, "imod", |lhs, rhs| lhs % rhs)
+}
+
+/**
+Stack changes:
+
+- 2 pop
+- 1 push
+ */
+fn exec_atan2(state: &mut State) -> Result<(), CalcError> {
+    let val2 = state.stack.pop().unwrap();
+    let val1 = state.stack.pop().unwrap();
+    match (val1, val2) {
+        (Value::Number(lhs), Value::Number(rhs)) => {
+            state.stack.push(Value::Number(state.angle.radians_to_unit(lhs.atan2(rhs))));
+        }
+        (lhs,rhs) => return Err(unsupported_binary(&mut state.stack, lhs, rhs)),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+ */
+fn exec_print(state: &mut State) -> Result<(), CalcError> {
+    for (i, elem) in state.stack.iter().rev().enumerate() {
+        println!("{}: {}", !(i as isize), state.show(elem));
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Prints only the top value.
+ */
+fn exec_print_top(state: &mut State) -> Result<(), CalcError> {
+    println!("{}", state.show(state.stack.last().unwrap()));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Prints the top value in full, even a matrix too large for `pt` to show whole.
+ */
+fn exec_print_full(state: &mut State) -> Result<(), CalcError> {
+    println!("{}", state.show_summary(state.stack.last().unwrap(), None));
+    Ok(())
+}
+
+/// Like `format_number`, but without `digits` very large and very small
+/// numbers keep the shortest exponent form instead of spelling out every zero.
+fn export_number(num: f64, digits: Option<usize>) -> String {
+    let magnitude = num.abs();
+    if digits.is_none() && magnitude != 0.0 && !(1e-5..1e16).contains(&magnitude) {
+        return format!("{num:e}");
+    }
+    format_number(num, digits)
+}
+
+/// A number as LaTeX math, with `1.5 \times 10^{-7}` instead of `1.5e-7`.
+fn latex_number(num: f64, digits: Option<usize>) -> String {
+    if num.is_nan() {
+        return "\\mathrm{NaN}".to_string();
+    }
+    if num.is_infinite() {
+        return if num > 0.0 { "\\infty" } else { "-\\infty" }.to_string();
+    }
+    let formatted = export_number(num, digits);
+    match formatted.split_once('e') {
+        Some((mantissa, exponent)) => format!("{mantissa} \\times 10^{{{}}}", exponent.trim_start_matches('+')),
+        None => formatted,
+    }
+}
+
+/// Text as a LaTeX `\text{...}` box, escaping the characters LaTeX reserves.
+fn latex_text(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '_' | '%' | '&' | '#' | '$' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c => escaped.push(c),
+        }
+    }
+    format!("\\text{{{escaped}}}")
+}
+
+/// A quaternion as `w + xi + yj + zk`, with `number` for the magnitudes.
+fn quat_text(quat: &Quat, number: impl Fn(f64) -> String) -> String {
+    let mut text = number(quat.w);
+    for (part, unit) in [(quat.x, 'i'), (quat.y, 'j'), (quat.z, 'k')] {
+        let sign = if part.is_sign_negative() { '-' } else { '+' };
+        text.push_str(&format!(" {sign} {}{unit}", number(part.abs())));
+    }
+    text
+}
+
+/// A polynomial as LaTeX math, like `x^{3} - 2x + 3`.
+fn latex_poly(poly: &Poly, digits: Option<usize>) -> String {
+    poly.format_with(|coeff| latex_number(coeff, digits), |degree| format!("x^{{{degree}}}"))
+}
+
+/// A number as MATLAB and Octave spell it.
+fn matlab_number(num: f64, digits: Option<usize>) -> String {
+    match num {
+        num if num.is_nan() => "NaN".to_string(),
+        num if num.is_infinite() => if num > 0.0 { "Inf" } else { "-Inf" }.to_string(),
+        num => export_number(num, digits),
+    }
+}
+
+/**
+Stack changes:
+
+- No change
+
+Prints the top value as LaTeX, a matrix as a `bmatrix` environment, with the
+digits set by `prec`.
+ */
+fn exec_tolatex(state: &mut State) -> Result<(), CalcError> {
+    match state.stack.last().unwrap() {
+        Value::Number(num) => println!("{}", latex_number(*num, state.precision)),
+        Value::Matrix(mat) => {
+            let rows = mat.data.chunks(mat.col)
+                .map(|row| row.iter().map(|elem| latex_number(*elem, state.precision)).collect::<Vec<_>>().join(" & "))
+                .collect::<Vec<_>>();
+            println!("\\begin{{bmatrix}}\n{}\n\\end{{bmatrix}}", rows.join(" \\\\\n"));
+        },
+        Value::BigInt(int) => println!("{int}"),
+        Value::BigFloat(x) => println!("{}", bigfloat::format(x, state.precision)),
+        Value::Bool(bool) => println!("\\mathrm{{{bool}}}"),
+        Value::Str(string) => println!("{}", latex_text(string)),
+        Value::Interval(interval) => {
+            let (lo, hi) = (latex_number(interval.lo, state.precision), latex_number(interval.hi, state.precision));
+            println!("[{lo}, {hi}]");
+        },
+        Value::Quantity(quantity) => println!("{}\\,\\mathrm{{{}}}", latex_number(quantity.value, state.precision), quantity.unit),
+        Value::Dual(dual) => {
+            let (value, deriv) = (latex_number(dual.value, state.precision), latex_number(dual.deriv, state.precision));
+            println!("{value} + {deriv} \\varepsilon");
+        },
+        Value::Poly(poly) => println!("{}", latex_poly(poly, state.precision)),
+        Value::Quat(quat) => println!("{}", quat_text(quat, |c| latex_number(c, state.precision))),
+        Value::Date(date) => println!("{}", latex_text(&date.to_string())),
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Prints the top value as a MATLAB or Octave literal such as `[1 2; 3 4]`, with
+the digits set by `prec`.
+ */
+fn exec_tomatlab(state: &mut State) -> Result<(), CalcError> {
+    match state.stack.last().unwrap() {
+        Value::Number(num) => println!("{}", matlab_number(*num, state.precision)),
+        Value::Matrix(mat) => {
+            let rows = mat.data.chunks(mat.col)
+                .map(|row| row.iter().map(|elem| matlab_number(*elem, state.precision)).collect::<Vec<_>>().join(" "))
+                .collect::<Vec<_>>();
+            println!("[{}]", rows.join("; "));
+        },
+        Value::BigInt(int) => println!("{int}"),
+        Value::BigFloat(x) => println!("{}", bigfloat::format(x, state.precision)),
+        Value::Bool(bool) => println!("{bool}"),
+        Value::Str(string) => println!("'{}'", string.replace('\'', "''")),
+        Value::Interval(interval) => {
+            let (lo, hi) = (matlab_number(interval.lo, state.precision), matlab_number(interval.hi, state.precision));
+            println!("infsup({lo}, {hi})");
+        },
+        Value::Quantity(quantity) => println!("{} % {}", matlab_number(quantity.value, state.precision), quantity.unit),
+        Value::Dual(dual) => {
+            let (value, deriv) = (matlab_number(dual.value, state.precision), matlab_number(dual.deriv, state.precision));
+            println!("[{value} {deriv}] % value and derivative");
+        },
+        Value::Poly(poly) => {
+            let coeffs = poly.highest_first().iter().map(|coeff| matlab_number(*coeff, state.precision)).collect::<Vec<_>>();
+            println!("[{}]", coeffs.join(" "));
+        },
+        Value::Quat(quat) => {
+            let parts = [quat.w, quat.x, quat.y, quat.z].map(|c| matlab_number(c, state.precision));
+            println!("quaternion({})", parts.join(", "));
+        },
+        Value::Date(date) => {
+            let (year, month, day, hour, minute, second) = date.fields();
+            println!("datetime({year}, {month}, {day}, {hour}, {minute}, {second}, 'TimeZone', 'UTC')");
+        },
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+
+Prints the top value before popping it.
+ */
+fn exec_print_pop(state: &mut State) -> Result<(), CalcError> {
+    let top = state.stack.pop().unwrap();
+    println!("{}", state.show(&top));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push, a copy of the top value
+ */
+fn exec_dup(state: &mut State) -> Result<(), CalcError> {
+    let top = state.stack.last().unwrap().clone();
+    state.stack.push(top);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- The top two values trade places
+ */
+fn exec_swap(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    state.stack.swap(len - 1, len - 2);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+ */
+fn exec_drop(state: &mut State) -> Result<(), CalcError> {
+    state.stack.pop();
+    Ok(())
+}
+
+/**
+Variables: `n`
+
+Stack changes:
+
+- `n` + 1 pop
+ */
+fn exec_dropn(state: &mut State) -> Result<(), CalcError> {
+    let count = take_count(state, "dropn", |n| n)?;
+    state.stack.truncate(state.stack.len() - count);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- Every value is popped
+ */
+fn exec_clear(state: &mut State) -> Result<(), CalcError> {
+    state.stack.clear();
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- The third value moves to the top: `a b c` becomes `b c a`
+ */
+fn exec_rot(state: &mut State) -> Result<(), CalcError> {
+    let third = state.stack.remove(state.stack.len() - 3);
+    state.stack.push(third);
+    Ok(())
+}
+
+/**
+Variables: `n`
+
+Stack changes:
+
+- 1 pop
+- The value `n` below the top moves to the top, so `1 roll` is `swap` and
+  `2 roll` is `rot`
+ */
+fn exec_roll(state: &mut State) -> Result<(), CalcError> {
+    let depth = take_count(state, "roll", |n| n.saturating_add(1))?;
+    let value = state.stack.remove(state.stack.len() - 1 - depth);
+    state.stack.push(value);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push, a copy of the second value: `a b` becomes `a b a`
+ */
+fn exec_over(state: &mut State) -> Result<(), CalcError> {
+    let second = state.stack[state.stack.len() - 2].clone();
+    state.stack.push(second);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- The second value is removed: `a b` becomes `b`
+ */
+fn exec_nip(state: &mut State) -> Result<(), CalcError> {
+    state.stack.remove(state.stack.len() - 2);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push, a copy of the top value under the second: `a b` becomes `b a b`
+ */
+fn exec_tuck(state: &mut State) -> Result<(), CalcError> {
+    let top = state.stack.last().unwrap().clone();
+    state.stack.insert(state.stack.len() - 2, top);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 push, the number of values on the stack before the push
+ */
+fn exec_depth(state: &mut State) -> Result<(), CalcError> {
+    state.stack.push(Value::Number(state.stack.len() as f64));
+    Ok(())
+}
+
+/**
+Variables: `n`
+
+Stack changes:
+
+- 1 pop
+- 1 push, a copy of the value `n` below the top
+
+`n` counts from 0 like Forth, so `0 pick` is `dup` and `1 pick` is `over`.
+Negative `n` uses the labels printed by `p` instead, so `-1 pick` is also `dup`.
+ */
+fn exec_pick(state: &mut State) -> Result<(), CalcError> {
+    let depth = match state.stack.last().unwrap() {
+        Value::Number(index) if *index < 0.0 => to_usize(-index - 1.0),
+        Value::Number(index) => to_usize(*index),
+        other => return Err(CalcError::TypeMismatch(
+            format!("`pick` needs a number as its index, got {other}")
+        )),
+    };
+    let Some(depth) = depth else {
+        return Err(CalcError::InvalidArgument("`pick` needs an integer index".to_string()));
+    };
+    let below = state.stack.len() - 1;
+    if below <= depth {
+        return Err(CalcError::StackUnderflow {
+            operator: "pick".to_string(),
+            needed: depth.saturating_add(2),
+            found: state.stack.len(),
+        });
+    }
+    state.stack.pop();
+    let value = state.stack[below - 1 - depth].clone();
+    state.stack.push(value);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 2 push, copies of the top pair: `a b` becomes `a b a b`
+ */
+fn exec_dup2(state: &mut State) -> Result<(), CalcError> {
+    let pair = state.stack[state.stack.len() - 2..].to_vec();
+    state.stack.extend(pair);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- The top two pairs trade places: `a b c d` becomes `c d a b`
+ */
+fn exec_swap2(state: &mut State) -> Result<(), CalcError> {
+    let len = state.stack.len();
+    state.stack[len - 4..].rotate_left(2);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- The whole stack is reversed, the bottom value ends up on top
+ */
+fn exec_rev(state: &mut State) -> Result<(), CalcError> {
+    state.stack.reverse();
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- The stack returns to how it was before the last REPL line that changed it
+ */
+fn exec_undo(state: &mut State) -> Result<(), CalcError> {
+    if state.history_pos == 0 {
+        return Err(CalcError::NothingToUndo);
+    }
+    state.restore_history(state.history_pos - 1);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- The stack returns to how it was before the last `undo`
+ */
+fn exec_redo(state: &mut State) -> Result<(), CalcError> {
+    if state.history_pos + 1 >= state.history.len() {
+        return Err(CalcError::NothingToRedo);
+    }
+    state.restore_history(state.history_pos + 1);
+    Ok(())
+}
+
+/**
+Variables: `n`, then the value to store
+
+Stack changes:
+
+- 2 pop
+
+The value is kept in register `n` until it is overwritten.
+ */
+fn exec_sto(state: &mut State) -> Result<(), CalcError> {
+    let register = take_count(state, "sto", |_| 1)?;
+    let value = state.stack.pop().unwrap();
+    state.registers.insert(register, value);
+    Ok(())
+}
+
+/**
+Variables: `n`
+
+Stack changes:
+
+- 1 pop
+- 1 push, a copy of register `n`
+ */
+fn exec_rcl(state: &mut State) -> Result<(), CalcError> {
+    let register = take_count(state, "rcl", |_| 0)?;
+    match state.registers.get(&register) {
+        Some(value) => state.stack.push(value.clone()),
+        None => {
+            state.stack.push(Value::Number(register as f64));
+            return Err(CalcError::EmptyRegister(register));
+        },
+    }
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Trigonometric operators work in degrees from now on.
+ */
+fn exec_deg(state: &mut State) -> Result<(), CalcError> {
+    state.angle = AngleMode::Degrees;
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Trigonometric operators work in radians from now on, the default.
+ */
+fn exec_rad(state: &mut State) -> Result<(), CalcError> {
+    state.angle = AngleMode::Radians;
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Trigonometric operators work in gradians (400 to a full turn) from now on.
+ */
+fn exec_grad(state: &mut State) -> Result<(), CalcError> {
+    state.angle = AngleMode::Gradians;
+    Ok(())
+}
+
+/**
+Variables: `n`
+
+Stack changes:
+
+- 1 pop
+
+Prints values with `n` significant digits from now on, or in full for `0 prec`.
+Only the display changes, calculations keep every digit.
+ */
+fn exec_prec(state: &mut State) -> Result<(), CalcError> {
+    let digits = take_count(state, "prec", |_| 0)?;
+    state.precision = (digits > 0).then_some(digits);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- 1 pop
+
+Sets the precision in bits of the numbers entered and computed from now on,
+or goes back to `f64` with 0. Big floats already on the stack keep their
+precision.
+ */
+fn exec_setprec(state: &mut State) -> Result<(), CalcError> {
+    let bits = take_count(state, "setprec", |_| 0)?;
+    state.bits = (bits > 0).then_some(bits);
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Toggles whether printed matrix rows are enclosed in brackets.
+ */
+fn exec_brackets(state: &mut State) -> Result<(), CalcError> {
+    state.brackets = !state.brackets;
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Toggles whether NaN and infinite results are errors.
+ */
+fn exec_fptrap(state: &mut State) -> Result<(), CalcError> {
+    state.strict_fp = !state.strict_fp;
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- No change
+
+Toggles whether dividing by zero is an error.
+ */
+fn exec_divtrap(state: &mut State) -> Result<(), CalcError> {
+    state.div_trap = !state.div_trap;
+    Ok(())
+}
+
+fn is_finite_value(value: &Value) -> bool {
+    match value {
+        Value::Number(num) => num.is_finite(),
+        Value::Matrix(mat) => mat.data.iter().all(|elem| elem.is_finite()),
+        Value::BigInt(_) => true,
+        Value::BigFloat(x) => bigfloat::is_finite(x),
+        Value::Bool(_) | Value::Str(_) => true,
+        Value::Interval(interval) => interval.lo.is_finite() && interval.hi.is_finite(),
+        Value::Quantity(quantity) => quantity.value.is_finite(),
+        Value::Dual(dual) => dual.value.is_finite() && dual.deriv.is_finite(),
+        Value::Poly(poly) => poly.is_finite(),
+        Value::Quat(quat) => quat.is_finite(),
+        Value::Date(date) => date.seconds.is_finite(),
+    }
+}
+
+/// Named values that push themselves. Physical constants are in SI units (CODATA
+/// 2018) and live under `phys.` so they cannot shadow a short operator name.
+const CONSTANTS: phf::Map<&'static str, f64> = phf::phf_map!{
+    "e" => std::f64::consts::E,
+    "tau" => std::f64::consts::TAU,
+    "phi" => 1.618_033_988_749_895,
+    "phys.c" => 299_792_458.0,
+    "phys.G" => 6.674_30e-11,
+    "phys.h" => 6.626_070_15e-34,
+    "phys.hbar" => 1.054_571_817e-34,
+    "phys.k_B" => 1.380_649e-23,
+    "phys.N_A" => 6.022_140_76e23,
+    "phys.R" => 8.314_462_618,
+    "phys.e" => 1.602_176_634e-19,
+    "phys.m_e" => 9.109_383_701_5e-31,
+    "phys.m_p" => 1.672_621_923_69e-27,
+    "phys.m_n" => 1.674_927_498_04e-27,
+    "phys.eps0" => 8.854_187_812_8e-12,
+    "phys.mu0" => 1.256_637_062_12e-6,
+    "phys.alpha" => 7.297_352_569_3e-3,
+    "phys.a0" => 5.291_772_109_03e-11,
+    "phys.sigma" => 5.670_374_419e-8,
+    "phys.g" => 9.806_65,
+    "phys.atm" => 101_325.0,
+};
+
+type NamedHandler = fn(&mut State, &str) -> Result<(), CalcError>;
+
+/// Words that take the token following them as a name instead of reading the
+/// stack, like `stack foo`.
+const NAMED_HANDLERS: phf::Map<&'static str, NamedHandler> = phf::phf_map!{
+    "stack" => exec_stack,
+    "readcsv" => exec_readcsv,
+    "writecsv" => exec_writecsv,
+    "readnpy" => exec_readnpy,
+    "writenpy" => exec_writenpy,
+};
+
+/**
+Stack changes:
+
+- The stack called `name` becomes the active one, the current one is kept
+  under its own name. The initial stack is called `main`.
+ */
+fn exec_stack(state: &mut State, name: &str) -> Result<(), CalcError> {
+    state.switch_stack(name);
+    Ok(())
+}
+
+/// Levenshtein distance, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut prev = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// The registered operators closest to a misspelled one, at most three of them.
+fn suggest_operators(identifier: &str) -> Vec<&'static str> {
+    // Never suggest a name that shares nothing with what was typed
+    let length = identifier.chars().count();
+    let limit = (length / 3).clamp(1, 2).min(length.saturating_sub(1));
+    let mut candidates = HANDLERS.keys().chain(NAMED_HANDLERS.keys()).chain(CONSTANTS.keys())
+        .map(|name| (edit_distance(identifier, name), *name))
+        .filter(|(distance, _)| *distance <= limit)
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+/// The operators that only rearrange, copy or store values already on the
+/// stack. A non-finite value they move was either checked when it got there
+/// or arrived before `fptrap`, so they are never blamed for one.
+const STACK_WORDS: [&str; 20] = [
+    "dup", "swap", "x", "dup2", "swap2", "drop", "dropn", "clear", "rot", "roll",
+    "over", "nip", "tuck", "pick", "rev", "sto", "rcl", "p", "pt", "full",
+];
+
+/// Pushes a literal or constant, which with `--strict-fp` must be finite too.
+fn push_literal(state: &mut State, text: &str, value: Value) -> Result<(), CalcError> {
+    if state.strict_fp && !is_finite_value(&value) {
+        return Err(CalcError::NonFinite(text.to_string()));
+    }
+    state.stack.push(value);
+    Ok(())
+}
+
+fn exec_identifier(state: &mut State, identifier: &str) -> Result<(), CalcError> {
+    if let Some(value) = CONSTANTS.get(identifier) {
+        // Only the mathematical constants have more digits than an `f64`
+        let value = match state.bits {
+            Some(bits) => bigfloat_value(bigfloat::constant(identifier, bits)
+                .unwrap_or_else(|| bigfloat::from_f64(*value, bits))),
+            None => Value::Number(*value),
+        };
+        return push_literal(state, identifier, value);
+    }
+    let (arity, fun) = match HANDLERS.get(identifier) {
+        Some(handler) => *handler,
+        None => return Err(CalcError::UnknownOperator {
+            name: identifier.to_string(),
+            suggestions: suggest_operators(identifier),
+        }),
+    };
+    if state.stack.len() < arity {
+        return Err(CalcError::StackUnderflow {
+            operator: identifier.to_string(),
+            needed: arity,
+            found: state.stack.len(),
+        });
+    }
+    if !state.strict_fp || STACK_WORDS.contains(&identifier) {
+        return fun(state);
+    }
+
+    // Keep the operands around so a non-finite result can be undone. Only
+    // what was pushed in their place is new; everything below was checked
+    // on its way onto the stack.
+    let watermark = state.stack.len() - arity;
+    let operands = state.stack[watermark..].to_vec();
+    fun(state)?;
+    let produced = watermark.min(state.stack.len());
+    if !state.stack[produced..].iter().all(is_finite_value) {
+        state.stack.truncate(produced);
+        state.stack.extend(operands);
+        return Err(CalcError::NonFinite(identifier.to_string()));
+    }
+    Ok(())
+}
+
+/// A whitespace-separated word, a quoted string, or one of the matrix literal
+/// delimiters `[`, `;` and `]`, together with its byte range in the source.
+struct Token<'src> {
+    text: &'src str,
+    span: std::ops::Range<usize>,
+}
+
+fn tokenize(src: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut chars = src.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '"' && start.is_none() {
+            // Runs to the closing quote, or to the end for parse_string_literal to reject
+            let mut end = src.len();
+            let mut escaped = false;
+            for (j, c) in chars.by_ref() {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    end = j + 1;
+                    break;
+                }
+            }
+            tokens.push(Token { text: &src[i..end], span: i..end });
+            continue;
+        }
+        let delimiter = matches!(c, '[' | ';' | ']');
+        if c.is_whitespace() || delimiter {
+            if let Some(begin) = start.take() {
+                tokens.push(Token { text: &src[begin..i], span: begin..i });
+            }
+            if delimiter {
+                tokens.push(Token { text: &src[i..i + 1], span: i..i + 1 });
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push(Token { text: &src[begin..], span: begin..src.len() });
+    }
+    tokens
+}
+
+/// Unquotes a `"..."` token. Besides `\"` and `\\`, the escapes are `\n`,
+/// `\t`, `\r`, `\0` and `\u{...}`, which covers everything `{:?}` writes.
+fn parse_string_literal(text: &str) -> Result<String, String> {
+    let mut chars = text[1..].chars();
+    let mut string = String::new();
+    while let Some(c) = chars.next() {
+        let escape = match c {
+            '"' if chars.as_str().is_empty() => return Ok(string),
+            '\\' => chars.next(),
+            c => {
+                string.push(c);
+                continue;
+            },
+        };
+        let unescaped = match escape {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ ('"' | '\\' | '\'')) => c,
+            Some('u') => {
+                let rest = chars.as_str();
+                let code = rest.strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32));
+                let Some(code) = code else {
+                    return Err("Bad `\\u{...}` escape in string literal".to_string());
+                };
+                chars = rest[rest.find('}').unwrap() + 1..].chars();
+                code
+            },
+            Some(other) => return Err(format!("Unknown escape `\\{other}` in string literal")),
+            None => break,
+        };
+        string.push(unescaped);
+    }
+    Err("Unclosed string literal".to_string())
+}
+
+/// Reads the rest of a `[1 2; 3 4]` literal after its opening bracket. Rows
+/// are separated by `;` and must all have the same length; elements are
+/// numbers or named constants.
+fn parse_matrix_literal<'src>(
+    tokens: &mut impl Iterator<Item = Token<'src>>,
+    open: std::ops::Range<usize>,
+) -> Result<Matrix, SourceError> {
+    let syntax = |msg: String, span| SourceError { error: CalcError::Syntax(msg), span };
+    let mut data = Vec::new();
+    let mut col = None;
+    let mut row_len = 0;
+    loop {
+        let Some(tok) = tokens.next() else {
+            return Err(syntax("Unclosed `[` in matrix literal".to_string(), open));
+        };
+        match tok.text {
+            ";" | "]" => {
+                let closing = tok.text == "]";
+                // A trailing `;` before `]` does not start another row
+                if row_len == 0 && !(closing && col.is_some()) {
+                    return Err(syntax("Empty row in matrix literal".to_string(), tok.span));
+                }
+                if row_len > 0 {
+                    match col {
+                        Some(len) if len != row_len => return Err(syntax(
+                            format!("Matrix literal row has {row_len} elements, expected {len}"), tok.span
+                        )),
+                        _ => col = Some(row_len),
+                    }
+                }
+                row_len = 0;
+                if closing {
+                    let col = col.unwrap();
+                    return Ok(Matrix::from(data.len() / col, col, data.into_boxed_slice()));
+                }
+            },
+            "[" => return Err(syntax("Matrix literals cannot be nested".to_string(), tok.span)),
+            text => {
+                let elem = text.parse::<f64>().ok()
+                    .or_else(|| CONSTANTS.get(text).copied())
+                    .or_else(|| (text == "pi").then_some(std::f64::consts::PI));
+                let Some(elem) = elem else {
+                    return Err(syntax(format!("Matrix literal elements must be numbers, got `{text}`"), tok.span));
+                };
+                data.push(elem);
+                row_len += 1;
+            },
+        }
+    }
+}
+
+/// A `CalcError` tagged with the byte range of the token that raised it.
+#[derive(Debug)]
+struct SourceError {
+    error: CalcError,
+    span: std::ops::Range<usize>,
+}
+
+impl SourceError {
+    /// Prints the error to stderr followed by the offending source line with the
+    /// token underlined, e.g.
+    ///
+    /// ```text
+    /// Error: `+` needs 2 operands, stack has 1
+    ///  --> <string>:1:3
+    ///   |
+    /// 1 | 3 +
+    ///   |   ^
+    /// ```
+    fn report(&self, origin: &str, source: &str) {
+        let line_start = source[..self.span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[self.span.start..].find('\n').map_or(source.len(), |i| self.span.start + i);
+        let line_number = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..self.span.start].chars().count();
+        let width = source[self.span.clone()].chars().count().max(1);
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        eprintln!("Error: {}", self.error);
+        eprintln!("{gutter}--> {origin}:{line_number}:{}", column + 1);
+        eprintln!("{gutter} |");
+        eprintln!("{line_number} | {}", &source[line_start..line_end]);
+        eprintln!("{gutter} | {}{}", " ".repeat(column), "^".repeat(width));
+    }
+}
+
+/// Stops at the first failing token; everything evaluated before it stays on the stack.
+///
+/// Anything that evaluates source on behalf of another evaluation must come
+/// through here so runaway nesting hits `max_depth` instead of the native stack.
+fn exec(state: &mut State, expr: &str) -> Result<(), SourceError> {
+    if state.depth >= state.max_depth {
+        return Err(SourceError {
+            error: CalcError::DepthExceeded(state.max_depth),
+            span: 0..0,
+        });
+    }
+    state.depth += 1;
+    let result = exec_tokens(state, expr);
+    state.depth -= 1;
+    result
+}
+
+/// A big integer literal is an integer with an `n` suffix, like `123n`.
+fn parse_big_literal(text: &str) -> Option<BigInt> {
+    let digits = text.strip_suffix('n')?;
+    let unsigned = digits.strip_prefix(['-', '+']).unwrap_or(digits);
+    if unsigned.is_empty() || !unsigned.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Durations like `3d` or `90min`, for adding to dates: a decimal number
+/// with one of these suffixes, for the time unit it stands for.
+const DURATION_SUFFIXES: [(&str, &str); 6] = [("ms", "ms"), ("min", "minute"), ("s", "s"), ("h", "h"), ("d", "day"), ("w", "week")];
+
+fn parse_duration_literal(text: &str) -> Option<Quantity> {
+    let (number, unit) = DURATION_SUFFIXES.iter().find_map(|(suffix, unit)| Some((text.strip_suffix(suffix)?, unit)))?;
+    let unsigned = number.strip_prefix(['-', '+']).unwrap_or(number);
+    if !unsigned.bytes().any(|byte| byte.is_ascii_digit()) || !unsigned.bytes().all(|byte| byte.is_ascii_digit() || byte == b'.') {
+        return None;
+    }
+    Some(Quantity::new(number.parse().ok()?, Unit::parse(unit)?))
+}
+
+/// Quantities like `5km`, `9.81m/s^2` or `4KiB`: a decimal number run
+/// together with a unit. A unit on its own is not a literal, so a mistyped
+/// operator is never taken for one.
+fn parse_quantity_literal(text: &str) -> Option<Quantity> {
+    let split = text.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, symbol) = text.split_at(split);
+    let unsigned = number.strip_prefix(['-', '+']).unwrap_or(number);
+    if !unsigned.bytes().any(|byte| byte.is_ascii_digit()) || !unsigned.bytes().all(|byte| byte.is_ascii_digit() || byte == b'.') {
+        return None;
+    }
+    Some(Quantity::new(number.parse().ok()?, Unit::parse(symbol)?))
+}
+
+/// A number, entered at the `--bits` precision if one is set, a big integer,
+/// a duration, a quantity or a date.
+fn parse_number_literal(state: &State, text: &str) -> Option<Value> {
+    match (text.parse::<f64>(), state.bits) {
+        (Ok(num), Some(bits)) => Some(bigfloat_value(bigfloat::parse(text, bits, num))),
+        (Ok(num), None) => Some(Value::Number(num)),
+        (Err(_), _) => parse_big_literal(text).map(Value::BigInt)
+            .or_else(|| parse_duration_literal(text).map(Value::Quantity))
+            .or_else(|| parse_quantity_literal(text).map(Value::Quantity))
+            .or_else(|| DateTime::parse(text).map(Value::Date)),
+    }
+}
+
+fn exec_tokens(state: &mut State, expr: &str) -> Result<(), SourceError> {
+    let mut tokens = tokenize(expr).into_iter();
+    while let Some(tok) = tokens.next() {
+        if let Some(fun) = NAMED_HANDLERS.get(tok.text) {
+            let Some(name) = tokens.next() else {
+                let error = CalcError::MissingName(tok.text.to_string());
+                return Err(SourceError { error, span: tok.span });
+            };
+            let span = tok.span.start..name.span.end;
+            let name = match name.text.starts_with('"') {
+                true => parse_string_literal(name.text)
+                    .map_err(|msg| SourceError { error: CalcError::Syntax(msg), span: name.span })?,
+                false => name.text.to_string(),
+            };
+            check_interrupt().and_then(|()| fun(state, &name))
+                .map_err(|error| SourceError { error, span })?;
+            continue;
+        }
+        match tok.text {
+            "[" => {
+                let mat = parse_matrix_literal(&mut tokens, tok.span.clone())?;
+                check_interrupt().and_then(|()| push_literal(state, "[", Value::Matrix(mat)))
+                    .map_err(|error| SourceError { error, span: tok.span })?;
+                continue;
+            },
+            ";" | "]" => {
+                let error = CalcError::Syntax(format!("Unexpected `{}` outside a matrix literal", tok.text));
+                return Err(SourceError { error, span: tok.span });
+            },
+            text if text.starts_with('"') => {
+                let string = parse_string_literal(text)
+                    .map_err(|msg| SourceError { error: CalcError::Syntax(msg), span: tok.span.clone() })?;
+                check_interrupt().map_err(|error| SourceError { error, span: tok.span })?;
+                state.stack.push(Value::Str(string));
+                continue;
+            },
+            _ => {},
+        }
+        let literal = parse_number_literal(state, tok.text);
+        let result = check_interrupt().and_then(|()| match literal {
+            Some(value) => push_literal(state, tok.text, value),
+            None => match exec_identifier(state, tok.text) {
+                Err(problem @ CalcError::UnknownOperator { .. })
+                    if state.strictness == Strictness::Lenient => state.warn(problem),
+                result => result,
+            },
+        });
+        result.map_err(|error| SourceError { error, span: tok.span })?;
+        // println!("\"{tok}\": {:?}", stack);
+    }
+    Ok(())
+}
+
+/// Runs a whole non-interactive program and returns the process exit code.
+fn run_source(state: &mut State, origin: &str, source: &str) -> i32 {
+    match exec(state, source) {
+        Ok(()) => 0,
+        Err(err) => {
+            err.report(origin, source);
+            err.error.exit_code()
+        },
+    }
+}
+
+fn exec_file(state: &mut State, path: &str) -> i32 {
+    match std::fs::read_to_string(path) {
+        Ok(source) => run_source(state, path, &source),
+        Err(err) => {
+            eprintln!("Error: cannot read {path}: {err}");
+            EXIT_FILE_ERROR
+        },
+    }
+}
+
+/// Runs one REPL line as a single undo step. A failing line is rolled back,
+/// registers and settings included, unless `keep_partial` is set.
+fn exec_line(state: &mut State, line: &str) {
+    let history_pos = state.history_pos;
+    let checkpoint = state.checkpoint();
+    if let Err(err) = exec(state, line) {
+        err.report("<input>", line);
+        if !state.keep_partial {
+            state.restore_history(history_pos);
+            state.restore_checkpoint(checkpoint);
+            return;
+        }
+    }
+    state.record_history();
+}
+
+/// A session file is a pc program that pushes the saved stack back, one value
+/// per line, bottom first. `f64` formatting round-trips, so nothing is lost.
+fn session_source(stack: &[Value]) -> String {
+    let mut source = String::new();
+    for value in stack {
+        match value {
+            Value::Number(num) => source.push_str(&format!("{num}\n")),
+            Value::BigInt(int) => source.push_str(&format!("{int}n\n")),
+            Value::Bool(bool) => source.push_str(&format!("{bool}\n")),
+            Value::Str(string) => source.push_str(&format!("{string:?}\n")),
+            Value::Interval(interval) => source.push_str(&format!("{} {} interval\n", interval.lo, interval.hi)),
+            Value::Quantity(quantity) => source.push_str(&format!("{} {:?} unit\n", quantity.value, quantity.unit.to_string())),
+            // Exact: `x dual x -` is 0 + 1ε, and scaling and shifting it is too
+            Value::Dual(dual) => {
+                let Dual { value, deriv } = dual;
+                source.push_str(&format!("{value} dual {value} - {deriv} * {value} +\n"));
+            },
+            Value::Poly(poly) => {
+                let coeffs = poly.highest_first();
+                for coeff in &coeffs {
+                    source.push_str(&format!("{coeff} "));
+                }
+                source.push_str(&format!("1 {} matrix poly\n", coeffs.len()));
+            },
+            Value::Quat(Quat { w, x, y, z }) => source.push_str(&format!("{w} {x} {y} {z} quat\n")),
+            // To the microsecond, as dates are shown
+            Value::Date(date) => source.push_str(&format!("{date}\n")),
+            Value::BigFloat(x) => {
+                let bits = bigfloat::precision(x);
+                source.push_str(&format!("{bits} setprec {} 0 setprec\n", bigfloat::format_exact(x)));
+            },
+            Value::Matrix(mat) => {
+                for elem in mat.data.iter() {
+                    source.push_str(&format!("{elem} "));
+                }
+                source.push_str(&format!("{} {} matrix\n", mat.row, mat.col));
+            },
+        }
+    }
+    source
+}
+
+fn save_session(state: &State, path: &str) -> Result<(), CalcError> {
+    std::fs::write(path, session_source(&state.stack))
+        .map_err(|err| CalcError::Io(format!("Cannot write {path}: {err}")))
+}
+
+/// Replaces the stack with the one saved in `path`.
+fn load_session(state: &mut State, path: &str) -> Result<(), CalcError> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| CalcError::Io(format!("Cannot read {path}: {err}")))?;
+    let mut loaded = State::new();
+    if let Err(err) = exec(&mut loaded, &source) {
+        err.report(path, &source);
+        return Err(CalcError::Io(format!("{path} is not a valid session file")));
+    }
+    state.stack = loaded.stack;
+    Ok(())
+}
+
+/// REPL commands are lines starting with `:`, outside the operator namespace.
+fn exec_command(state: &mut State, command: &str) -> Result<(), CalcError> {
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, ""),
+    };
+    match (name, argument) {
+        ("undo", "") => exec_undo(state),
+        ("redo", "") => exec_redo(state),
+        ("save", path) if !path.is_empty() => save_session(state, path),
+        ("load", path) if !path.is_empty() => {
+            load_session(state, path)?;
+            state.record_history();
+            Ok(())
+        },
+        _ => Err(CalcError::UnknownCommand(command.to_string())),
+    }
+}
+
+use rustyline::error::ReadlineError;
+/// With a `session` file, the stack is loaded from it on start (if it exists)
+/// and written back when the REPL exits.
+fn interactive(state: &mut State, session: Option<&str>) -> Result<bool, ReadlineError> {
+    use rustyline::{DefaultEditor};
+    
+    let mut rl = DefaultEditor::new().unwrap();
+    if let Some(path) = session.filter(|path| std::path::Path::new(path).exists()) {
+        if let Err(err) = load_session(state, path) {
+            eprintln!("Error: {err}");
+        }
+        state.record_history();
+    }
+    // readline sees Ctrl-C as a key press; the handler only fires while evaluating
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::Relaxed))
+        .expect("Cannot install the Ctrl-C handler");
+
+    loop {
+        let prompt = match state.stack_name.as_str() {
+            MAIN_STACK => format!("{}> ", state.angle.name()),
+            name => format!("{name} {}> ", state.angle.name()),
+        };
+        let line = rl.readline(&prompt);
+        match line {
+            Ok(line_string) => {
+                rl.add_history_entry(&line_string)?;
+                INTERRUPTED.store(false, Ordering::Relaxed);
+                match line_string.trim().strip_prefix(':') {
+                    Some(command) => {
+                        if let Err(err) = exec_command(state, command) {
+                            eprintln!("Error: {err}");
+                        }
+                    },
+                    None => exec_line(state, &line_string),
+                }
+            },
+            // Ctrl-C drops the current line, Ctrl-D ends the session
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        }
+    };
+    if let Some(path) = session {
+        if let Err(err) = save_session(state, path) {
+            eprintln!("Error: {err}");
+        }
+    }
+    Ok(true)
+}
+
+/// Borrows the matrix on top of the stack for a named word, which unlike the
+/// operators in `HANDLERS` gets no arity check beforehand.
+fn top_matrix<'a>(state: &'a State, op: &str) -> Result<&'a Matrix, CalcError> {
+    match state.stack.last() {
+        Some(Value::Matrix(mat)) => Ok(mat),
+        Some(value) => Err(CalcError::TypeMismatch(format!("`{op}` needs a matrix, got {}", value))),
+        None => Err(CalcError::StackUnderflow { operator: op.to_string(), needed: 1, found: 0 }),
+    }
+}
+
+/// Reads the delimited file at `path` as a matrix.
+fn read_csv(path: &str, skip: Option<usize>) -> Result<Matrix, CalcError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| CalcError::Io(format!("Cannot read {path}: {err}")))?;
+    let (row, col, data) = delimited::parse(&text, skip)
+        .map_err(|err| CalcError::Io(format!("Cannot load a matrix from {path}: {err}")))?;
+    Ok(Matrix::from(row, col, data.into_boxed_slice()))
+}
+
+/**
+Stack changes:
+
+- 1 push, the numeric CSV or TSV file `name` as a matrix
+
+The delimiter is detected from the first line: comma, tab, semicolon, or else
+whitespace. A non-numeric first line is skipped as a header; `--csv-skip N`
+drops exactly `N` lines instead.
+ */
+fn exec_readcsv(state: &mut State, name: &str) -> Result<(), CalcError> {
+    let mat = read_csv(name, state.csv_skip)?;
+    state.stack.push(Value::Matrix(mat));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- None, the matrix on top is written to the file `name`
+
+Fields are separated by tabs if `name` ends in `.tsv` and by commas
+otherwise. Numbers are written with the digits set by `prec`, all of them by
+default.
+ */
+fn exec_writecsv(state: &mut State, name: &str) -> Result<(), CalcError> {
+    let mat = top_matrix(state, "writecsv")?;
+    let delimiter = if name.ends_with(".tsv") { '\t' } else { ',' };
+    let text = delimited::format(mat.col, &mat.data, delimiter, |elem| format_number(elem, state.precision));
+    std::fs::write(name, text).map_err(|err| CalcError::Io(format!("Cannot write {name}: {err}")))
+}
+
+/**
+Stack changes:
+
+- 1 push, the NumPy array in the `.npy` file `name` as a matrix
+
+The array must hold little-endian `f64`s in one or two dimensions; a 1-D
+array becomes a single row.
+ */
+fn exec_readnpy(state: &mut State, name: &str) -> Result<(), CalcError> {
+    let bytes = std::fs::read(name).map_err(|err| CalcError::Io(format!("Cannot read {name}: {err}")))?;
+    let (row, col, data) = npy::decode(&bytes)
+        .map_err(|err| CalcError::Io(format!("Cannot load a matrix from {name}: {err}")))?;
+    state.stack.push(Value::Matrix(Matrix::from(row, col, data.into_boxed_slice())));
+    Ok(())
+}
+
+/**
+Stack changes:
+
+- None, the matrix on top is written to the file `name` as a 2-D `.npy` array
+ */
+fn exec_writenpy(state: &mut State, name: &str) -> Result<(), CalcError> {
+    let mat = top_matrix(state, "writenpy")?;
+    std::fs::write(name, npy::encode(mat.row, mat.col, &mat.data))
+        .map_err(|err| CalcError::Io(format!("Cannot write {name}: {err}")))
 }
 
 fn main() {
@@ -480,15 +6187,668 @@ fn main() {
         .author("Erencan Ceyhan")
         .about("A postfix calculator written in Rust")
         .arg(arg!([filename] "file"))
-        .arg(arg!(-s --string <STRING>))
+        .arg(arg!(-s --string <STRING>).allow_hyphen_values(true))
         .arg(arg!(-i --interactive))
+        .arg(arg!(--"strict-fp" "Treat NaN and infinite results as errors"))
+        .arg(arg!(--"strict-div" "Treat division by zero as an error"))
+        .arg(arg!(--strict "Treat every warning, including NaN results, as an error")
+            .conflicts_with("lenient"))
+        .arg(arg!(--lenient "Skip unknown tokens with a warning instead of failing"))
+        .arg(arg!(--"keep-partial" "Keep the partial results of a failing REPL line"))
+        .arg(arg!(--angle <MODE> "Angle unit for trigonometric operators")
+            .value_parser(["rad", "deg", "grad"]))
+        .arg(arg!(--precision <N> "Significant digits to print, 0 for all of them")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(arg!(--bits <N> "Enter numbers as arbitrary-precision floats with N bits")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(arg!(--brackets "Print matrix rows between brackets"))
+        .arg(arg!(--"print-threshold" <N> "Print only the corners of matrices with more elements, 0 for never")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(arg!(--"int-policy" <POLICY> "What integer operators do with fractional operands")
+            .value_parser(["truncate", "error"]))
+        .arg(arg!(--seed <N> "Seed for the random number operators")
+            .value_parser(clap::value_parser!(u64)))
+        .arg(arg!(--session <FILE> "Load the REPL stack from FILE and save it back on exit"))
+        .arg(arg!(--"max-depth" <N> "Maximum nesting of `eval` calls")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(arg!(--"load-matrix" <FILE> "Push the matrix in a CSV or TSV file before running"))
+        .arg(arg!(--"csv-skip" <N> "Header lines to skip when reading CSV files")
+            .value_parser(clap::value_parser!(usize)))
+        .after_help("Exit codes: 0 success, 1 runtime error, 2 usage error, 3 parse error, 4 unreadable file")
         .get_matches();
 
+    let mut state = State::new();
+    if matches.get_flag("strict") {
+        state.strictness = Strictness::Strict;
+    } else if matches.get_flag("lenient") {
+        state.strictness = Strictness::Lenient;
+    }
+    state.strict_fp = matches.get_flag("strict-fp") || state.strictness == Strictness::Strict;
+    state.div_trap = matches.get_flag("strict-div");
+    state.keep_partial = matches.get_flag("keep-partial");
+    if matches.get_one::<String>("int-policy").is_some_and(|policy| policy == "error") {
+        state.int_policy = IntPolicy::Error;
+    }
+    if let Some(seed) = matches.get_one::<u64>("seed") {
+        state.rng = random::Rng::from_seed(*seed);
+    }
+    state.precision = matches.get_one::<usize>("precision").copied().filter(|digits| *digits > 0);
+    state.bits = matches.get_one::<usize>("bits").copied().filter(|bits| *bits > 0);
+    state.brackets = matches.get_flag("brackets");
+    if let Some(threshold) = matches.get_one::<usize>("print-threshold") {
+        state.print_threshold = Some(*threshold).filter(|threshold| *threshold > 0);
+    }
+    state.angle = match matches.get_one::<String>("angle").map(String::as_str) {
+        Some("deg") => AngleMode::Degrees,
+        Some("grad") => AngleMode::Gradians,
+        _ => AngleMode::Radians,
+    };
+    if let Some(max_depth) = matches.get_one::<usize>("max-depth") {
+        state.max_depth = *max_depth;
+    }
+
+    state.csv_skip = matches.get_one::<usize>("csv-skip").copied();
+    if let Some(path) = matches.get_one::<String>("load-matrix") {
+        match read_csv(path, state.csv_skip) {
+            Ok(mat) => {
+                state.stack.push(Value::Matrix(mat));
+                state.record_history();
+            },
+            Err(err) => {
+                eprintln!("Error: {err}");
+                std::process::exit(err.exit_code());
+            },
+        }
+    }
+
+    let session = matches.get_one::<String>("session").map(String::as_str);
+
     if matches.get_flag("interactive") {
-        interactive().unwrap();
+        interactive(&mut state, session).unwrap();
     } else if let Some(expression_string) = matches.get_one::<String>("string") {
-        exec_expression(&expression_string);
+        std::process::exit(run_source(&mut state, "<string>", expression_string));
+    } else if let Some(filename) = matches.get_one::<String>("filename") {
+        std::process::exit(exec_file(&mut state, filename));
     } else {
-        interactive().unwrap();
+        interactive(&mut state, session).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates `source` on a fresh state and returns the stack bottom first,
+    /// as each value prints.
+    fn eval(source: &str) -> Result<Vec<String>, CalcError> {
+        let mut state = State::new();
+        exec(&mut state, source).map_err(|err| err.error)?;
+        Ok(stack(&state))
+    }
+
+    fn stack(state: &State) -> Vec<String> {
+        state.stack.iter().map(Value::to_string).collect()
+    }
+
+    /// The shape and elements of the single matrix `source` leaves.
+    fn eval_matrix(source: &str) -> (usize, usize, Vec<f64>) {
+        let mut state = State::new();
+        exec(&mut state, source).unwrap();
+        let [Value::Matrix(mat)] = state.stack.as_slice() else {
+            panic!("`{source}` did not leave a single matrix");
+        };
+        (mat.row, mat.col, mat.data.to_vec())
+    }
+
+    /// Checks that `source` leaves just a number within rounding of `expected`.
+    fn assert_near(source: &str, expected: f64) {
+        let mut state = State::new();
+        exec(&mut state, source).unwrap();
+        let [Value::Number(actual)] = state.stack.as_slice() else {
+            panic!("`{source}` did not leave a single number");
+        };
+        let tolerance = 1e-12 * expected.abs().max(1.0);
+        assert!((actual - expected).abs() <= tolerance, "`{source}` gave {actual}, expected {expected}");
+    }
+
+    #[test]
+    fn dup() {
+        assert_eq!(eval("1 2 dup").unwrap(), ["1", "2", "2"]);
+        assert!(matches!(eval("dup"), Err(CalcError::StackUnderflow { needed: 1, found: 0, .. })));
+    }
+
+    #[test]
+    fn swap() {
+        assert_eq!(eval("1 2 3 swap").unwrap(), ["1", "3", "2"]);
+        assert_eq!(eval("1 2 x").unwrap(), ["2", "1"]);
+        assert!(matches!(eval("1 swap"), Err(CalcError::StackUnderflow { needed: 2, found: 1, .. })));
+    }
+
+    #[test]
+    fn drop_and_dropn() {
+        assert_eq!(eval("1 2 drop").unwrap(), ["1"]);
+        assert_eq!(eval("1 2 3 4 2 dropn").unwrap(), ["1", "2"]);
+        assert_eq!(eval("1 2 0 dropn").unwrap(), ["1", "2"]);
+        assert!(matches!(eval("1 2 dropn"), Err(CalcError::StackUnderflow { needed: 3, found: 2, .. })));
+        assert!(matches!(eval("1 -1 dropn"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn clear() {
+        assert!(eval("1 2 3 clear").unwrap().is_empty());
+        assert!(eval("clear").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rot_and_roll() {
+        assert_eq!(eval("1 2 3 rot").unwrap(), ["2", "3", "1"]);
+        assert_eq!(eval("1 2 3 2 roll").unwrap(), ["2", "3", "1"]);
+        assert_eq!(eval("1 2 3 1 roll").unwrap(), ["1", "3", "2"]);
+        assert_eq!(eval("1 2 0 roll").unwrap(), ["1", "2"]);
+        assert!(matches!(eval("1 2 2 roll"), Err(CalcError::StackUnderflow { needed: 4, found: 3, .. })));
+    }
+
+    #[test]
+    fn over_nip_tuck() {
+        assert_eq!(eval("1 2 over").unwrap(), ["1", "2", "1"]);
+        assert_eq!(eval("1 2 nip").unwrap(), ["2"]);
+        assert_eq!(eval("1 2 tuck").unwrap(), ["2", "1", "2"]);
+        assert!(matches!(eval("1 tuck"), Err(CalcError::StackUnderflow { needed: 2, .. })));
+    }
+
+    #[test]
+    fn depth() {
+        assert_eq!(eval("depth").unwrap(), ["0"]);
+        assert_eq!(eval("5 6 depth").unwrap(), ["5", "6", "2"]);
+    }
+
+    #[test]
+    fn pick() {
+        assert_eq!(eval("1 2 3 0 pick").unwrap(), ["1", "2", "3", "3"]);
+        assert_eq!(eval("1 2 3 2 pick").unwrap(), ["1", "2", "3", "1"]);
+        assert_eq!(eval("1 2 3 -2 pick").unwrap(), ["1", "2", "3", "2"]);
+        assert!(matches!(eval("1 2 2 pick"), Err(CalcError::StackUnderflow { needed: 4, found: 3, .. })));
+        assert!(matches!(eval("1 0.5 pick"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn pair_operators() {
+        assert_eq!(eval("1 2 dup2").unwrap(), ["1", "2", "1", "2"]);
+        assert_eq!(eval("1 2 3 4 swap2").unwrap(), ["3", "4", "1", "2"]);
+        assert!(matches!(eval("1 2 3 swap2"), Err(CalcError::StackUnderflow { needed: 4, found: 3, .. })));
+    }
+
+    #[test]
+    fn rev() {
+        assert_eq!(eval("1 2 3 rev").unwrap(), ["3", "2", "1"]);
+        assert!(eval("rev").unwrap().is_empty());
+    }
+
+    #[test]
+    fn undo_and_redo_whole_lines() {
+        let mut state = State::new();
+        exec_line(&mut state, "1 2");
+        exec_line(&mut state, "+ dup");
+        exec_command(&mut state, "undo").unwrap();
+        assert_eq!(stack(&state), ["1", "2"]);
+        exec_command(&mut state, "redo").unwrap();
+        assert_eq!(stack(&state), ["3", "3"]);
+        assert!(matches!(exec_command(&mut state, "redo"), Err(CalcError::NothingToRedo)));
+        exec_command(&mut state, "undo").unwrap();
+        exec_command(&mut state, "undo").unwrap();
+        assert!(state.stack.is_empty());
+        assert!(matches!(exec_command(&mut state, "undo"), Err(CalcError::NothingToUndo)));
+        assert!(matches!(exec_command(&mut state, "und"), Err(CalcError::UnknownCommand(_))));
+    }
+
+    #[test]
+    fn undo_forgets_redo_steps_after_a_new_line() {
+        let mut state = State::new();
+        exec_line(&mut state, "1");
+        exec_line(&mut state, "2");
+        exec_command(&mut state, "undo").unwrap();
+        exec_line(&mut state, "3");
+        assert!(matches!(exec_command(&mut state, "redo"), Err(CalcError::NothingToRedo)));
+        assert_eq!(stack(&state), ["1", "3"]);
+    }
+
+    #[test]
+    fn session_source_round_trips() {
+        let mut state = State::new();
+        exec(&mut state, "0.1 3 / -1e300 5e-324 1 2 3 4 2 2 matrix").unwrap();
+        let mut loaded = State::new();
+        exec(&mut loaded, &session_source(&state.stack)).unwrap();
+        assert!(loaded.stack == state.stack);
+    }
+
+    #[test]
+    fn save_and_load_commands() {
+        let path = std::env::temp_dir().join(format!("pc-test-session-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut state = State::new();
+        exec(&mut state, "1 2").unwrap();
+        exec_command(&mut state, &format!("save {path}")).unwrap();
+        let mut other = State::new();
+        exec_line(&mut other, "7");
+        exec_command(&mut other, &format!("load {path}")).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(stack(&other), ["1", "2"]);
+        exec_command(&mut other, "undo").unwrap();
+        assert_eq!(stack(&other), ["7"]);
+        assert!(matches!(exec_command(&mut other, &format!("load {path}")), Err(CalcError::Io(_))));
+        assert!(matches!(exec_command(&mut other, "save"), Err(CalcError::UnknownCommand(_))));
+    }
+
+    #[test]
+    fn named_stacks() {
+        assert_eq!(eval("1 2 stack aux 3 stack main").unwrap(), ["1", "2"]);
+        assert_eq!(eval("1 stack aux 3 stack main stack aux 4").unwrap(), ["3", "4"]);
+        assert!(matches!(eval("1 stack"), Err(CalcError::MissingName(_))));
+    }
+
+    #[test]
+    fn undo_switches_back_to_the_stack_it_came_from() {
+        let mut state = State::new();
+        exec_line(&mut state, "1");
+        exec_line(&mut state, "stack aux 2");
+        exec_command(&mut state, "undo").unwrap();
+        assert_eq!((state.stack_name.as_str(), stack(&state)), ("main", vec!["1".to_string()]));
+        exec_command(&mut state, "redo").unwrap();
+        assert_eq!((state.stack_name.as_str(), stack(&state)), ("aux", vec!["2".to_string()]));
+    }
+
+    #[test]
+    fn print_top_keeps_or_pops() {
+        assert_eq!(eval("1 2 pt").unwrap(), ["1", "2"]);
+        assert_eq!(eval("1 2 .").unwrap(), ["1", "2"]);
+        assert_eq!(eval("1 2 .,").unwrap(), ["1"]);
+        assert!(matches!(eval("."), Err(CalcError::StackUnderflow { needed: 1, found: 0, .. })));
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(eval("5 1 sto 1 rcl 1 rcl").unwrap(), ["5", "5"]);
+        assert_eq!(eval("5 1 sto 6 1 sto 1 rcl").unwrap(), ["6"]);
+        assert_eq!(eval("5 1 sto stack aux 1 rcl").unwrap(), ["5"]);
+        assert!(matches!(eval("2 rcl"), Err(CalcError::EmptyRegister(2))));
+        assert!(matches!(eval("1 sto"), Err(CalcError::StackUnderflow { needed: 2, .. })));
+    }
+
+    #[test]
+    fn logarithms() {
+        assert_near("1 ln", 0.0);
+        assert_near("2.718281828459045 ln", 1.0);
+        assert_near("1000 log", 3.0);
+        assert_near("8 log2", 3.0);
+        assert_near("81 3 logb", 4.0);
+        assert_eq!(eval("0 ln").unwrap(), ["-inf"]);
+        assert_eq!(eval_matrix("1 100 1 2 matrix log"), (1, 2, vec![0.0, 2.0]));
+    }
+
+    #[test]
+    fn abs_sign_neg() {
+        assert_eq!(eval("-2.5 abs 3 neg -4 sign 0 sign").unwrap(), ["2.5", "-3", "-1", "0"]);
+        assert_eq!(eval("-0 sign").unwrap(), ["-0"]);
+        assert_eq!(eval("0 neg").unwrap(), ["-0"]);
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(eval("-2.5 floor -2.5 ceil -2.5 round -2.5 trunc -2.5 frac").unwrap(), ["-3", "-2", "-3", "-2", "-0.5"]);
+        assert_eq!(eval("3.14159 2 roundto 1234 -2 roundto").unwrap(), ["3.14", "1200"]);
+        assert!(matches!(eval("1 0.5 roundto"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn euclidean_division() {
+        assert_eq!(eval("-7 3 mod -7 3 rem -7 3 //").unwrap(), ["2", "-1", "-3"]);
+        assert_eq!(eval("7 -3 mod 7 -3 //").unwrap(), ["1", "-2"]);
+        assert_eq!(eval("1 0 mod").unwrap(), ["NaN"]);
+        assert!(matches!(eval("divtrap 1 0 //"), Err(CalcError::DivisionByZero)));
+    }
+
+    #[test]
+    fn min_max_clamp() {
+        assert_eq!(eval("1 2 min 1 2 max").unwrap(), ["1", "2"]);
+        assert_eq!(eval("5 0 3 clamp -5 0 3 clamp 2 0 3 clamp").unwrap(), ["3", "0", "2"]);
+        assert!(matches!(eval("1 3 0 clamp"), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval("1 nan 0 clamp"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn hyperbolic() {
+        assert_near("1 sinh", 1.1752011936438014);
+        assert_near("1 cosh", 1.5430806348152437);
+        assert_near("1 tanh", 0.7615941559557649);
+        assert_near("1 coth", 1.3130352854993312);
+        assert_near("1 sinh asinh", 1.0);
+        assert_near("2 acosh cosh", 2.0);
+        assert_near("0.5 atanh tanh", 0.5);
+        assert_eq!(eval("0.5 acosh").unwrap(), ["NaN"]);
+    }
+
+    #[test]
+    fn factorial_and_gamma() {
+        assert_eq!(eval("0 ! 5 ! 20 fact 171 !").unwrap(), ["1", "120", "2432902008176640000", "inf"]);
+        assert!(matches!(eval("2.5 !"), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval("-1 fact"), Err(CalcError::InvalidArgument(_))));
+        assert_near("0.5 gamma", std::f64::consts::PI.sqrt());
+        assert_near("-0.5 gamma", -2.0 * std::f64::consts::PI.sqrt());
+        assert_near("6 gamma", 120.0);
+        assert_near("200 lgamma", 857.9336698258574);
+        assert_eq!(eval("0 gamma -0 gamma -2 gamma").unwrap(), ["inf", "-inf", "NaN"]);
+    }
+
+    #[test]
+    fn combinatorics() {
+        assert_eq!(eval("5 2 ncr 5 2 npr 3 5 ncr 10 0 npr").unwrap(), ["10", "20", "0", "1"]);
+        assert_near("60 30 ncr", 118264581564861424.0);
+        assert_near("1000 500 ncr log", 299.43182715186373);
+        assert!(matches!(eval("5 -2 ncr"), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval("5.5 2 npr"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn gcd_and_lcm() {
+        assert_eq!(eval("12 18 gcd -12 18 gcd 0 5 gcd 4 6 lcm 0 6 lcm").unwrap(), ["6", "6", "5", "12", "0"]);
+        assert!(matches!(eval("1.5 3 gcd"), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval("1e17 3 lcm"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn primes_and_factors() {
+        assert_eq!(eval("1 isprime 2 isprime 91 isprime 9007199254740881 isprime -7 isprime").unwrap(), ["0", "1", "0", "1", "0"]);
+        assert_eq!(eval_matrix("360 factor"), (1, 6, vec![2.0, 2.0, 2.0, 3.0, 3.0, 5.0]));
+        assert_eq!(eval_matrix("9007199254740881 factor"), (1, 1, vec![9007199254740881.0]));
+        assert!(matches!(eval("1 factor"), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval("2.5 isprime"), Err(CalcError::InvalidArgument(_))));
+        assert_eq!(eval("2n 89 ^ 1 - isprime 2n 89 ^ 1 + isprime").unwrap(), ["1", "0"]);
+        assert_eq!(eval_matrix("2n 64 ^ 1 - factor"), (1, 7, vec![3.0, 5.0, 17.0, 257.0, 641.0, 65537.0, 6700417.0]));
+        assert!(matches!(eval("2n 64 ^ 1 + factor"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn modular_arithmetic() {
+        assert_eq!(eval("3 200 1000003 modpow -2 3 7 modpow 5 0 1 modpow").unwrap(), ["333986", "6", "0"]);
+        assert_eq!(eval("2 1e15 9007199254740881 modpow").unwrap(), ["7887575402196919"]);
+        assert_eq!(eval("3 7 modinv -3 7 modinv").unwrap(), ["5", "2"]);
+        assert!(matches!(eval("4 8 modinv"), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval("2 -1 7 modpow"), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval("2 3 0 modpow"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn error_function_and_beta() {
+        assert_near("0.5 erf", 0.5204998778130465);
+        assert_near("3 erf", 0.9999779095030014);
+        assert_near("-3 erf", -0.9999779095030014);
+        assert_near("10 erfc 1e45 *", 2.088487583762545);
+        assert_near("-1 erfc", 1.842700792949715);
+        assert_near("2.5 1.5 beta", 0.1963495408493621);
+        assert_near("100 100 beta 1e61 *", 2.2087606931994364);
+    }
+
+    #[test]
+    fn angle_modes() {
+        assert_near("deg 30 sin", 0.5);
+        assert_near("deg 1 atan", 45.0);
+        assert_near("deg 1 1 atan2", 45.0);
+        assert_near("grad 100 cos", 0.0);
+        assert_near("grad 0 acos", 100.0);
+        assert_near("deg rad 1 atan", std::f64::consts::FRAC_PI_4);
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(eval("50 % 25 p% 200 -25 addpct 30 120 pctof").unwrap(), ["0.5", "0.25", "150", "25"]);
+        assert_near("200 10 addpct", 220.0);
+    }
+
+    #[test]
+    fn display_precision() {
+        assert_eq!(format_number(std::f64::consts::PI, Some(3)), "3.14");
+        assert_eq!(format_number(1234567.0, Some(3)), "1.23e6");
+        assert_eq!(format_number(0.0000012345, Some(2)), "1.2e-6");
+        assert_eq!(format_number(2.5, Some(6)), "2.5");
+        assert_eq!(format_number(1.0 / 3.0, None), "0.3333333333333333");
+        let mut state = State::new();
+        exec(&mut state, "2 3 / 4 prec").unwrap();
+        assert_eq!(state.show(&state.stack[0]), "0.6667");
+        exec(&mut state, "0 prec").unwrap();
+        assert_eq!(state.show(&state.stack[0]), "0.6666666666666666");
+    }
+
+    #[test]
+    fn seeded_random_numbers_repeat() {
+        let draws = "rand randn 1 6 randint";
+        let first = eval(&format!("42 seed {draws}")).unwrap();
+        assert_eq!(first.len(), 3);
+        assert_eq!(eval(&format!("42 seed {draws}")).unwrap(), first);
+        assert_ne!(eval(&format!("43 seed {draws}")).unwrap(), first);
+        let mut state = State::new();
+        exec(&mut state, "7 seed").unwrap();
+        for _ in 0..200 {
+            exec(&mut state, "rand 3 5 randint").unwrap();
+            let [Value::Number(int), Value::Number(uniform)] = [state.stack.pop().unwrap(), state.stack.pop().unwrap()] else {
+                panic!("expected two numbers");
+            };
+            assert!((0.0..1.0).contains(&uniform) && [3.0, 4.0, 5.0].contains(&int));
+        }
+        assert!(matches!(eval("5 3 randint"), Err(CalcError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(eval("e tau phys.c").unwrap(), ["2.718281828459045", "6.283185307179586", "299792458"]);
+        assert_near("phi dup * phi - 1 -", 0.0);
+        assert!(matches!(eval("phys.q"), Err(CalcError::UnknownOperator { .. })));
+    }
+
+    #[test]
+    fn hypot_and_distance() {
+        assert_eq!(eval("3 4 hypot 1 1 4 5 dist2d").unwrap(), ["5", "5"]);
+        assert_near("1e200 1e200 hypot 1e-200 *", std::f64::consts::SQRT_2);
+        assert!(matches!(eval("1 2 3 dist2d"), Err(CalcError::StackUnderflow { needed: 4, .. })));
+    }
+
+    #[test]
+    fn exponentials() {
+        assert_eq!(eval("10 exp2 3 exp10 -2 exp10").unwrap(), ["1024", "1000", "0.01"]);
+        assert_near("1e-10 expm1 1e10 *", 1.00000000005);
+        assert_near("1e-10 ln1p 1e10 *", 0.99999999995);
+        assert_near("1 expm1 ln1p", 1.0);
+    }
+
+
+    #[test]
+    fn integer_operators() {
+        assert_eq!(eval("3.7 int -3.7 int").unwrap(), ["3", "-3"]);
+        assert_eq!(eval("-7 2 idiv -7 2 imod").unwrap(), ["-3", "-1"]);
+        assert!(matches!(eval("7 0 idiv"), Err(CalcError::DivisionByZero)));
+        assert!(matches!(eval("1e300 int"), Err(CalcError::InvalidArgument(_))));
+
+        let mut state = State::new();
+        state.int_policy = IntPolicy::Error;
+        assert!(matches!(exec(&mut state, "7.5 2 idiv").map_err(|err| err.error), Err(CalcError::InvalidArgument(_))));
+        assert_eq!(stack(&state), ["7.5", "2"]);
+    }
+
+
+    #[test]
+    fn matrix_product() {
+        assert_eq!(eval_matrix("1 2 3 4 2 2 matrix 5 6 2 1 matrix *"), (2, 1, vec![17.0, 39.0]));
+        assert!(matches!(
+            eval("1 2 3 4 2 2 matrix 1 2 3 1 3 matrix *"),
+            Err(CalcError::InnerDimensionMismatch { lhs: (2, 2), rhs: (1, 3) })
+        ));
+    }
+
+
+    #[test]
+    fn scalar_broadcasting() {
+        assert_eq!(eval_matrix("10 1 2 1 2 matrix -"), (1, 2, vec![9.0, 8.0]));
+        assert_eq!(eval_matrix("1 2 1 2 matrix 4 /"), (1, 2, vec![0.25, 0.5]));
+        assert_eq!(eval_matrix("2 1 2 1 2 matrix * 1 +"), (1, 2, vec![3.0, 5.0]));
+    }
+
+
+    #[test]
+    fn element_wise_operators() {
+        assert_eq!(eval_matrix("1 2 3 4 2 2 matrix 5 6 7 8 2 2 matrix .*"), (2, 2, vec![5.0, 12.0, 21.0, 32.0]));
+        assert_eq!(eval_matrix("1 2 1 2 matrix 2 .^"), (1, 2, vec![1.0, 4.0]));
+        assert!(matches!(
+            eval("1 2 1 2 matrix 1 2 2 1 matrix ./"),
+            Err(CalcError::DimensionMismatch { lhs: (1, 2), rhs: (2, 1) })
+        ));
+        let mut state = State::new();
+        state.div_trap = true;
+        assert!(matches!(exec(&mut state, "1 2 1 2 matrix 0 ./").map_err(|err| err.error), Err(CalcError::DivisionByZero)));
+    }
+
+
+    #[test]
+    fn matrix_columns_line_up() {
+        let mut state = State::new();
+        exec(&mut state, "[1 10; 100 2]").unwrap();
+        let mat = state.stack.pop().unwrap();
+        assert_eq!(state.show(&mat), "\n      1  10 \n    100   2 \n");
+        exec(&mut state, "brackets").unwrap();
+        assert_eq!(state.show(&mat), "\n    [   1  10 ]\n    [ 100   2 ]\n");
+    }
+
+
+    #[test]
+    fn large_matrices_print_their_corners() {
+        let mut state = State::new();
+        state.print_threshold = Some(4);
+        exec(&mut state, "1 2 3 4 5 6 7 8 1 8 matrix").unwrap();
+        let mat = state.stack.pop().unwrap();
+        assert_eq!(state.show(&mat), "\n    1  2  3  ...  6  7  8 \n");
+        assert_eq!(state.show_summary(&mat, None), "\n    1  2  3  4  5  6  7  8 \n");
+    }
+
+
+    #[test]
+    fn export_formats() {
+        assert_eq!(latex_number(1.5e-7, None), "1.5 \\times 10^{-7}");
+        assert_eq!(latex_number(f64::NEG_INFINITY, None), "-\\infty");
+        assert_eq!(latex_number(0.25, None), "0.25");
+        assert_eq!(latex_text("50% of {a_b} & \\x"), "\\text{50\\% of \\{a\\_b\\} \\& \\textbackslash{}x}");
+        assert_eq!(matlab_number(f64::NAN, None), "NaN");
+        assert_eq!(matlab_number(1e20, None), "1e20");
+        assert_eq!(matlab_number(2.0 / 3.0, Some(3)), "0.667");
+    }
+
+
+    #[test]
+    fn big_integers() {
+        assert_eq!(eval("2n 100 ^ 100n 50n ncr").unwrap(), ["1267650600228229401496703205376", "100891344545564193334812497256"]);
+        assert_eq!(eval("-7n 2n // -7n 2n mod 12n 18n gcd").unwrap(), ["-4", "1", "6"]);
+        assert_eq!(eval("2n 64 ^ 1 -").unwrap(), ["18446744073709551615"]);
+        assert!(matches!(eval("7n 0n mod"), Err(CalcError::DivisionByZero)));
+        assert!(matches!(eval("1.5 big"), Err(CalcError::InvalidArgument(_))));
+        assert_eq!(eval("10n 400 ^ float -10n 401 ^ float").unwrap(), ["inf", "-inf"]);
+    }
+
+
+    #[test]
+    fn big_float_mode() {
+        let mut state = State::new();
+        state.bits = Some(128);
+        exec(&mut state, "2 sqrt 1 3 /").unwrap();
+        assert_eq!(stack(&state), ["1.414213562373095048801688724209698079", "0.3333333333333333333333333333333333333"]);
+        exec(&mut state, "clear 200 setprec 2 sqrt").unwrap();
+        assert!(stack(&state)[0].starts_with("1.41421356237309504880168872420969807856967187537694807317"));
+        assert_eq!(eval("2 sqrt").unwrap(), ["1.4142135623730951"]);
+        assert!(matches!(eval("1.5 setprec"), Err(CalcError::InvalidArgument(_))));
+    }
+
+
+    #[test]
+    fn comparisons_and_logic() {
+        assert_eq!(eval("1 2 < 2 1 >= 1 1 != 3 3 ==").unwrap(), ["true", "true", "false", "true"]);
+        assert_eq!(eval("true false and true false or false not").unwrap(), ["false", "true", "true"]);
+        assert!(matches!(eval("true 1 +"), Err(CalcError::TypeMismatch(_))));
+    }
+
+
+    #[test]
+    fn strings() {
+        assert_eq!(eval(r#""ab" "cd" concat "héllo" len "Straße" upper"#).unwrap(), [r#""abcd""#, "5", r#""STRASSE""#]);
+        assert_eq!(eval(r#""42" tonum 3 tostr"#).unwrap(), ["42", r#""3""#]);
+        assert!(matches!(eval(r#""abc" tonum"#), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval(r#""ab" "cd" +"#), Err(CalcError::TypeMismatch(_))));
+    }
+
+
+    #[test]
+    fn intervals() {
+        assert_eq!(eval("1 2 interval 3 4 interval + 1 2 interval -1 1 interval *").unwrap(), ["[4, 6]", "[-2, 2]"]);
+        assert_eq!(eval("0.1 0.1 interval 0.2 +").unwrap(), ["[0.3, 0.30000000000000004]"]);
+        assert_eq!(eval("1 2 interval 0 1 interval /").unwrap(), ["[-inf, inf]"]);
+        assert_eq!(eval("1 2 interval bounds").unwrap(), ["1", "2"]);
+        assert!(matches!(eval("2 1 interval"), Err(CalcError::InvalidArgument(_))));
+        assert_eq!(eval("0 1 interval tan 1 2 interval tan").unwrap(), ["[0, 1.5574077246549027]", "[-inf, inf]"]);
+    }
+
+
+    #[test]
+    fn units_come_from_literals_and_the_unit_word() {
+        assert_eq!(eval(r#"1km 300m + 2 "km" unit 1km "m" to"#).unwrap(), ["1.3 km", "2 km", "1000 m"]);
+        assert!(matches!(eval("m"), Err(CalcError::UnknownOperator { .. })));
+        assert!(matches!(eval(r#"2 "xyz" unit"#), Err(CalcError::InvalidArgument(_))));
+        assert!(matches!(eval("1km 1s +"), Err(CalcError::UnitMismatch { .. })));
+    }
+
+
+    #[test]
+    fn polynomials_come_from_vectors() {
+        assert_eq!(eval("1 [1 -3 2] poly").unwrap(), ["1", "x^2 - 3x + 2"]);
+        assert!(matches!(eval("1 -3 2 poly"), Err(CalcError::TypeMismatch(_))));
+        assert!(matches!(eval("[1 2; 3 4] poly"), Err(CalcError::TypeMismatch(_))));
+    }
+
+
+    #[test]
+    fn strict_fp_blames_the_producer() {
+        let blamed = |source: &str| match eval(source) {
+            Err(CalcError::NonFinite(operator)) => operator,
+            other => panic!("{source}: {other:?}"),
+        };
+        assert_eq!(blamed("fptrap inf p"), "inf");
+        assert_eq!(blamed("fptrap 1e999"), "1e999");
+        assert_eq!(blamed("fptrap 2 1 0 / swap"), "/");
+        assert_eq!(eval("inf fptrap 2 swap drop clear 1 2 +").unwrap(), ["3"]);
+
+        let mut state = State::new();
+        state.strict_fp = true;
+        let err = exec(&mut state, "1 0 /").unwrap_err();
+        assert!(matches!(err.error, CalcError::NonFinite(_)));
+        assert_eq!(stack(&state), ["1", "0"]);
+    }
+
+
+    #[test]
+    fn failing_line_rolls_back_registers_and_settings() {
+        let mut state = State::new();
+        exec_line(&mut state, "5 1 sto 7 seed");
+        exec_line(&mut state, "9 1 sto deg fptrap 64 setprec rand nosuchop");
+        assert!(!state.strict_fp && state.bits.is_none() && state.angle == AngleMode::Radians);
+        exec_line(&mut state, "rand 1 rcl");
+        let rand = eval("7 seed rand").unwrap().remove(0);
+        assert_eq!(stack(&state), [rand, "5".to_string()]);
+    }
+
+
+    #[test]
+    fn eval_nests_up_to_max_depth() {
+        assert_eq!(eval("2 \"3 *\" eval").unwrap(), ["6"]);
+        assert!(matches!(eval("\"dup eval\" dup eval"), Err(CalcError::DepthExceeded(DEFAULT_MAX_DEPTH))));
+
+        let mut state = State::new();
+        state.max_depth = 3;
+        exec(&mut state, "\"\\\"1\\\" eval\" eval").unwrap();
+        let err = exec(&mut state, "\"\\\"\\\\\\\"1\\\\\\\" eval\\\" eval\" eval").unwrap_err();
+        assert!(matches!(err.error, CalcError::DepthExceeded(3)));
     }
 }
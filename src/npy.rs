@@ -0,0 +1,141 @@
+//! NumPy's `.npy` format for little-endian `f64` arrays of one or two
+//! dimensions.
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Looks up `key` in the Python dict literal of an `.npy` header and returns
+/// the text of its value, up to the next top-level comma or closing brace.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let rest = &header[header.find(&format!("'{key}'"))? + key.len() + 2..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let end = match rest.strip_prefix('(') {
+        Some(tuple) => tuple.find(')')? + 2,
+        None => rest.find([',', '}'])?,
+    };
+    Some(rest[..end].trim())
+}
+
+/// Decodes an `.npy` file into `(rows, cols, data)` in row-major order. A
+/// one-dimensional array becomes a single row.
+pub fn decode(bytes: &[u8]) -> Result<(usize, usize, Vec<f64>), String> {
+    let rest = bytes.strip_prefix(MAGIC).ok_or("not an .npy file")?;
+    let (header_len, rest) = match rest {
+        [1, _, lo, hi, rest @ ..] => (u16::from_le_bytes([*lo, *hi]) as usize, rest),
+        [2 | 3, _, b0, b1, b2, b3, rest @ ..] => (u32::from_le_bytes([*b0, *b1, *b2, *b3]) as usize, rest),
+        _ => return Err("unsupported .npy version".to_string()),
+    };
+    if rest.len() < header_len {
+        return Err("truncated header".to_string());
+    }
+    let header = std::str::from_utf8(&rest[..header_len]).map_err(|_| "header is not text")?;
+    let data = &rest[header_len..];
+
+    match header_value(header, "descr") {
+        Some("'<f8'") | Some("'<d'") => {},
+        Some(descr) => return Err(format!("element type {descr} is not a little-endian f64")),
+        None => return Err("header has no element type".to_string()),
+    }
+    let fortran = match header_value(header, "fortran_order") {
+        Some("False") => false,
+        Some("True") => true,
+        _ => return Err("header has no memory order".to_string()),
+    };
+    let shape = header_value(header, "shape").ok_or("header has no shape")?;
+    let dims = shape.trim_matches(['(', ')']).split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse::<usize>().map_err(|_| format!("bad shape {shape}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (rows, cols) = match dims[..] {
+        [len] => (1, len),
+        [rows, cols] => (rows, cols),
+        _ => return Err(format!("only 1-D and 2-D arrays are supported, got shape {shape}")),
+    };
+    if rows == 0 || cols == 0 {
+        return Err(format!("empty arrays are not supported, got shape {shape}"));
+    }
+    let count = rows.checked_mul(cols).ok_or("shape is too large")?;
+    if data.len() != count.saturating_mul(8) {
+        return Err(format!("expected {count} elements, found {} bytes of data", data.len()));
+    }
+    let elems = data.chunks_exact(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    if fortran {
+        // Column-major storage: element (i, j) is at j * rows + i
+        let data = (0..count).map(|index| elems[(index % cols) * rows + index / cols]).collect();
+        return Ok((rows, cols, data));
+    }
+    Ok((rows, cols, elems))
+}
+
+/// Encodes a row-major `rows x cols` matrix as a version 1.0 `.npy` file.
+pub fn encode(rows: usize, cols: usize, data: &[f64]) -> Vec<u8> {
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({rows}, {cols}), }}");
+    // Pad with spaces and a newline so the data starts 64-byte aligned
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 4 + header.len() + data.len() * 8);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for elem in data {
+        bytes.extend_from_slice(&elem.to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A version 1.0 file with the given header and data, unpadded.
+    fn file(header: &str, data: &[f64]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        for elem in data {
+            bytes.extend_from_slice(&elem.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        let data = [1.0, -2.5, 3.0, f64::INFINITY, 0.0, 6.0];
+        let bytes = encode(2, 3, &data);
+        assert_eq!((bytes.len() - data.len() * 8) % 64, 0);
+        assert_eq!(decode(&bytes).unwrap(), (2, 3, data.to_vec()));
+    }
+
+    #[test]
+    fn reads_vectors_and_fortran_order() {
+        let vector = file("{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }\n", &[1.0, 2.0, 3.0]);
+        assert_eq!(decode(&vector).unwrap(), (1, 3, vec![1.0, 2.0, 3.0]));
+        let columns = file("{'descr': '<f8', 'fortran_order': True, 'shape': (2, 3), }\n", &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+        assert_eq!(decode(&columns).unwrap(), (2, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = encode(2, 2, &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(decode(&bytes[..bytes.len() - 1]), Err("expected 4 elements, found 31 bytes of data".to_string()));
+        assert_eq!(decode(&bytes[..20]), Err("truncated header".to_string()));
+        assert_eq!(decode(&bytes[..8]), Err("unsupported .npy version".to_string()));
+        assert_eq!(decode(b"PK\x03\x04"), Err("not an .npy file".to_string()));
+    }
+
+    #[test]
+    fn rejects_other_arrays() {
+        let ints = file("{'descr': '<i8', 'fortran_order': False, 'shape': (1,), }\n", &[1.0]);
+        assert_eq!(decode(&ints), Err("element type '<i8' is not a little-endian f64".to_string()));
+        let cube = file("{'descr': '<f8', 'fortran_order': False, 'shape': (1, 1, 1), }\n", &[1.0]);
+        assert!(decode(&cube).unwrap_err().starts_with("only 1-D and 2-D arrays"));
+        let empty = file("{'descr': '<f8', 'fortran_order': False, 'shape': (0, 3), }\n", &[]);
+        assert!(decode(&empty).unwrap_err().starts_with("empty arrays"));
+    }
+}
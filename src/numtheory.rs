@@ -0,0 +1,132 @@
+//! Integer algorithms behind the number-theory operators.
+
+pub fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Computed in `u128`, so it cannot overflow for `u64` inputs.
+pub fn lcm(a: u64, b: u64) -> u128 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    (a / gcd(a, b)) as u128 * b as u128
+}
+
+pub fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+pub fn pow_mod(mut base: u64, mut exponent: u64, m: u64) -> u64 {
+    let mut result = 1 % m;
+    base %= m;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Miller–Rabin with the first twelve primes as witnesses, which is
+/// deterministic for every `u64`.
+pub fn is_prime(n: u64) -> bool {
+    const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    for p in WITNESSES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    let mut d = n - 1;
+    let mut s = 0;
+    while d.is_multiple_of(2) {
+        d /= 2;
+        s += 1;
+    }
+    'witness: for a in WITNESSES {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// Prime factors in ascending order, repeated by multiplicity. The small
+/// ones come out by trial division, and what is left is split with
+/// Pollard's rho until every part is prime.
+pub fn factorize(mut n: u64) -> Vec<u64> {
+    let mut factors = Vec::new();
+    for p in [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37] {
+        while n.is_multiple_of(p) {
+            factors.push(p);
+            n /= p;
+        }
+    }
+    split(n, &mut factors);
+    factors.sort_unstable();
+    factors
+}
+
+fn split(n: u64, factors: &mut Vec<u64>) {
+    if n == 1 {
+        return;
+    }
+    if is_prime(n) {
+        factors.push(n);
+        return;
+    }
+    let divisor = rho(n);
+    split(divisor, factors);
+    split(n / divisor, factors);
+}
+
+/// A divisor of the composite `n` other than 1 and `n`, which has no factor
+/// below 41. Floyd's cycle finding on `x^2 + c` modulo `n` meets a cycle
+/// modulo some prime factor first; when it meets one modulo `n` itself
+/// instead, the next `c` is tried.
+fn rho(n: u64) -> u64 {
+    let mut c = 1;
+    loop {
+        let step = |x: u64| ((mul_mod(x, x, n) as u128 + c as u128) % n as u128) as u64;
+        let (mut x, mut y, mut divisor) = (2, 2, 1);
+        while divisor == 1 {
+            x = step(x);
+            y = step(step(y));
+            divisor = gcd(x.abs_diff(y), n);
+        }
+        if divisor != n {
+            return divisor;
+        }
+        c += 1;
+    }
+}
+
+/// The inverse of `a` modulo `m`, if `a` and `m` are coprime.
+pub fn inverse_mod(a: u64, m: u64) -> Option<u64> {
+    let (mut old_r, mut r) = (a as i128, m as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+    }
+    if old_r != 1 {
+        return None;
+    }
+    Some(old_s.rem_euclid(m as i128) as u64)
+}
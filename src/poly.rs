@@ -0,0 +1,210 @@
+//! Polynomials in one variable with `f64` coefficients.
+
+use crate::{linalg, CalcError};
+
+/// Coefficients from the constant term up, without zeros at the top, so the
+/// zero polynomial has none and the degree is one less than the length.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Poly {
+    coeffs: Vec<f64>,
+}
+
+impl Poly {
+    /// From the coefficients highest degree first, as they are written.
+    pub fn from_highest(coeffs: &[f64]) -> Poly {
+        Poly::from_lowest(coeffs.iter().rev().copied().collect())
+    }
+
+    fn from_lowest(mut coeffs: Vec<f64>) -> Poly {
+        while coeffs.last() == Some(&0.0) {
+            coeffs.pop();
+        }
+        Poly { coeffs }
+    }
+
+    pub fn constant(value: f64) -> Poly {
+        Poly::from_lowest(vec![value])
+    }
+
+    /// The coefficients highest degree first, with a single zero for the
+    /// zero polynomial.
+    pub fn highest_first(&self) -> Vec<f64> {
+        match self.coeffs.is_empty() {
+            true => vec![0.0],
+            false => self.coeffs.iter().rev().copied().collect(),
+        }
+    }
+
+    /// `None` for the zero polynomial.
+    pub fn degree(&self) -> Option<usize> {
+        self.coeffs.len().checked_sub(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coeffs.is_empty()
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.coeffs.iter().all(|coeff| coeff.is_finite())
+    }
+
+    pub fn add(&self, rhs: &Poly) -> Poly {
+        let len = self.coeffs.len().max(rhs.coeffs.len());
+        let coeff = |poly: &Poly, i: usize| poly.coeffs.get(i).copied().unwrap_or(0.0);
+        Poly::from_lowest((0..len).map(|i| coeff(self, i) + coeff(rhs, i)).collect())
+    }
+
+    pub fn sub(&self, rhs: &Poly) -> Poly {
+        self.add(&rhs.neg())
+    }
+
+    pub fn mul(&self, rhs: &Poly) -> Poly {
+        if self.is_zero() || rhs.is_zero() {
+            return Poly::from_lowest(Vec::new());
+        }
+        let mut coeffs = vec![0.0; self.coeffs.len() + rhs.coeffs.len() - 1];
+        for (i, lhs) in self.coeffs.iter().enumerate() {
+            for (j, rhs) in rhs.coeffs.iter().enumerate() {
+                coeffs[i + j] += lhs * rhs;
+            }
+        }
+        Poly::from_lowest(coeffs)
+    }
+
+    pub fn neg(&self) -> Poly {
+        // Subtracting from zero keeps zero coefficients from turning into -0
+        Poly { coeffs: self.coeffs.iter().map(|coeff| 0.0 - coeff).collect() }
+    }
+
+    /// Horner's rule.
+    pub fn eval(&self, x: f64) -> f64 {
+        self.coeffs.iter().rev().fold(0.0, |acc, coeff| acc * x + coeff)
+    }
+
+    pub fn deriv(&self) -> Poly {
+        Poly::from_lowest(self.coeffs.iter().enumerate().skip(1).map(|(i, coeff)| i as f64 * coeff).collect())
+    }
+
+    /// The antiderivative that is zero at zero.
+    pub fn integral(&self) -> Poly {
+        let terms = self.coeffs.iter().enumerate().map(|(i, coeff)| coeff / (i + 1) as f64);
+        Poly::from_lowest(std::iter::once(0.0).chain(terms).collect())
+    }
+
+    /// The roots as real and imaginary parts, ordered by real part and then
+    /// by imaginary part, from the eigenvalues of the companion matrix. That
+    /// is already upper Hessenberg, with the negated coefficients of the
+    /// monic polynomial in its first row. Fails for the zero polynomial,
+    /// which every number is a root of, or if the iteration does not settle.
+    pub fn roots(&self) -> Result<Vec<(f64, f64)>, CalcError> {
+        let Some((&lead, rest)) = self.coeffs.split_last() else {
+            return Err(CalcError::InvalidArgument("Every number is a root of the zero polynomial".to_string()));
+        };
+        let n = rest.len();
+        let mut companion = vec![0.0; n * n];
+        for (j, coeff) in rest.iter().rev().enumerate() {
+            companion[j] = -coeff / lead;
+        }
+        for i in 1..n {
+            companion[i*n + i - 1] = 1.0;
+        }
+        let mut roots = linalg::hessenberg_eigenvalues(n, &companion)?;
+        roots.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        Ok(roots)
+    }
+
+    /// Writes out the terms with `number` for the magnitudes of the
+    /// coefficients and `power` for the powers of `x` above the first. A
+    /// coefficient of 1 is left out except on the constant term.
+    pub fn format_with(&self, number: impl Fn(f64) -> String, power: impl Fn(usize) -> String) -> String {
+        let mut text = String::new();
+        for (degree, &coeff) in self.coeffs.iter().enumerate().rev().filter(|(_, coeff)| **coeff != 0.0) {
+            text.push_str(match (text.is_empty(), coeff < 0.0) {
+                (true, true) => "-",
+                (true, false) => "",
+                (false, true) => " - ",
+                (false, false) => " + ",
+            });
+            if coeff.abs() != 1.0 || degree == 0 {
+                text.push_str(&number(coeff.abs()));
+            }
+            match degree {
+                0 => {},
+                1 => text.push('x'),
+                _ => text.push_str(&power(degree)),
+            }
+        }
+        if text.is_empty() { "0".to_string() } else { text }
+    }
+}
+
+/// Highest degree first, in `x`, like `x^3 - 2x + 3`.
+impl std::fmt::Display for Poly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = self.format_with(|coeff| crate::format_number(coeff, f.precision()), |degree| format!("x^{degree}"));
+        write!(f, "{text}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(actual: &[(f64, f64)], expected: &[(f64, f64)]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?}");
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a.0 - e.0).abs() < 1e-9 && (a.1 - e.1).abs() < 1e-9, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn drops_leading_zeros() {
+        let poly = Poly::from_highest(&[0.0, 0.0, 2.0, 1.0]);
+        assert_eq!(poly.degree(), Some(1));
+        assert_eq!(poly.highest_first(), [2.0, 1.0]);
+        assert!(Poly::from_highest(&[0.0, 0.0]).is_zero());
+        assert_eq!(Poly::constant(0.0).highest_first(), [0.0]);
+        assert_eq!(Poly::constant(0.0).degree(), None);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Poly::from_highest(&[1.0, 1.0]);
+        let b = Poly::from_highest(&[1.0, -1.0]);
+        assert_eq!(a.mul(&b).highest_first(), [1.0, 0.0, -1.0]);
+        assert_eq!(a.add(&b).highest_first(), [2.0, 0.0]);
+        assert!(a.sub(&a).is_zero());
+        assert!(a.mul(&Poly::constant(0.0)).is_zero());
+    }
+
+    #[test]
+    fn evaluates_and_differentiates() {
+        let poly = Poly::from_highest(&[1.0, 0.0, -2.0, 3.0]);
+        assert_eq!(poly.eval(2.0), 7.0);
+        assert_eq!(poly.deriv().highest_first(), [3.0, 0.0, -2.0]);
+        assert_eq!(poly.integral().highest_first(), [0.25, 0.0, -1.0, 3.0, 0.0]);
+        assert!(Poly::constant(5.0).deriv().is_zero());
+    }
+
+    #[test]
+    fn displays_like_it_is_written() {
+        assert_eq!(Poly::from_highest(&[1.0, 0.0, -2.0, 3.0]).to_string(), "x^3 - 2x + 3");
+        assert_eq!(Poly::from_highest(&[-1.0, 1.0]).to_string(), "-x + 1");
+        assert_eq!(Poly::from_highest(&[1.0, 0.0]).to_string(), "x");
+        assert_eq!(Poly::constant(0.0).to_string(), "0");
+    }
+
+    #[test]
+    fn real_and_complex_roots() {
+        let cubic = Poly::from_highest(&[1.0, -6.0, 11.0, -6.0]);
+        assert_roots(&cubic.roots().unwrap(), &[(1.0, 0.0), (2.0, 0.0), (3.0, 0.0)]);
+        let quadratic = Poly::from_highest(&[1.0, 0.0, 1.0]);
+        assert_roots(&quadratic.roots().unwrap(), &[(0.0, -1.0), (0.0, 1.0)]);
+        assert!(Poly::constant(3.0).roots().unwrap().is_empty());
+    }
+
+    #[test]
+    fn zero_polynomial_has_no_roots_to_list() {
+        assert!(matches!(Poly::constant(0.0).roots(), Err(CalcError::InvalidArgument(_))));
+    }
+}
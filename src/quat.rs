@@ -0,0 +1,130 @@
+//! Quaternions `w + xi + yj + zk`, mostly for rotations: the unit quaternion
+//! `cos(θ/2) + sin(θ/2) (ux i + uy j + uz k)` turns by `θ` about the unit axis
+//! `u`, and multiplying them composes the rotations.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quat {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Quat {
+        Quat { w, x, y, z }
+    }
+
+    pub fn scalar(w: f64) -> Quat {
+        Quat::new(w, 0.0, 0.0, 0.0)
+    }
+
+    /// The rotation by `angle` radians about `axis`, which need not be a unit
+    /// vector. `None` for the zero axis.
+    pub fn from_axis_angle(axis: [f64; 3], angle: f64) -> Option<Quat> {
+        let length = axis.iter().map(|c| c * c).sum::<f64>().sqrt();
+        if length == 0.0 {
+            return None;
+        }
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        let [x, y, z] = axis.map(|c| c / length * sin);
+        Some(Quat::new(cos, x, y, z))
+    }
+
+    /// The quaternion of a row-major rotation matrix, with `w >= 0`. It is
+    /// read off the largest of the four squared components, which keeps the
+    /// divisions away from zero (Shepperd's method).
+    pub fn from_rotation(r: &[f64]) -> Quat {
+        let trace = r[0] + r[4] + r[8];
+        let candidates = [trace, r[0], r[4], r[8]];
+        let largest = (0..4).max_by(|&i, &j| candidates[i].total_cmp(&candidates[j])).unwrap();
+        let quat = match largest {
+            0 => {
+                let s = 2.0 * (1.0 + trace).sqrt();
+                Quat::new(s / 4.0, (r[7] - r[5]) / s, (r[2] - r[6]) / s, (r[3] - r[1]) / s)
+            },
+            1 => {
+                let s = 2.0 * (1.0 + r[0] - r[4] - r[8]).sqrt();
+                Quat::new((r[7] - r[5]) / s, s / 4.0, (r[1] + r[3]) / s, (r[2] + r[6]) / s)
+            },
+            2 => {
+                let s = 2.0 * (1.0 - r[0] + r[4] - r[8]).sqrt();
+                Quat::new((r[2] - r[6]) / s, (r[1] + r[3]) / s, s / 4.0, (r[5] + r[7]) / s)
+            },
+            _ => {
+                let s = 2.0 * (1.0 - r[0] - r[4] + r[8]).sqrt();
+                Quat::new((r[3] - r[1]) / s, (r[2] + r[6]) / s, (r[5] + r[7]) / s, s / 4.0)
+            },
+        };
+        if quat.w < 0.0 { quat.neg() } else { quat }
+    }
+
+    /// The row-major 3x3 matrix rotating column vectors like `self` does.
+    /// Any nonzero quaternion rotates like its normalization, so the terms
+    /// are divided by the squared norm. `None` for zero.
+    pub fn to_rotation(self) -> Option<[f64; 9]> {
+        if self.is_zero() {
+            return None;
+        }
+        let s = 2.0 / self.norm_sqr();
+        let Quat { w, x, y, z } = self;
+        Some([
+            1.0 - s * (y * y + z * z), s * (x * y - w * z), s * (x * z + w * y),
+            s * (x * y + w * z), 1.0 - s * (x * x + z * z), s * (y * z - w * x),
+            s * (x * z - w * y), s * (y * z + w * x), 1.0 - s * (x * x + y * y),
+        ])
+    }
+
+    pub fn add(self, rhs: Quat) -> Quat {
+        Quat::new(self.w + rhs.w, self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+
+    pub fn sub(self, rhs: Quat) -> Quat {
+        self.add(rhs.neg())
+    }
+
+    /// The Hamilton product, which does not commute: `p q` rotates by `q`
+    /// first and then by `p`.
+    pub fn mul(self, rhs: Quat) -> Quat {
+        let (a, b) = (self, rhs);
+        Quat::new(
+            a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        )
+    }
+
+    /// `self` times the inverse of `rhs`.
+    pub fn div(self, rhs: Quat) -> Quat {
+        self.mul(rhs.conj().scale(1.0 / rhs.norm_sqr()))
+    }
+
+    pub fn neg(self) -> Quat {
+        self.scale(-1.0)
+    }
+
+    pub fn scale(self, factor: f64) -> Quat {
+        Quat::new(self.w * factor, self.x * factor, self.y * factor, self.z * factor)
+    }
+
+    pub fn conj(self) -> Quat {
+        Quat::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z
+    }
+
+    pub fn norm(self) -> f64 {
+        self.norm_sqr().sqrt()
+    }
+
+    pub fn is_zero(self) -> bool {
+        [self.w, self.x, self.y, self.z].iter().all(|c| *c == 0.0)
+    }
+
+    pub fn is_finite(self) -> bool {
+        [self.w, self.x, self.y, self.z].iter().all(|c| c.is_finite())
+    }
+}
@@ -0,0 +1,400 @@
+//! Physical units, and quantities measured in them. A unit is a product of
+//! named units raised to integer powers, like `kg*m/s^2`. A quantity keeps
+//! its value in the unit it was entered in, so `1 mile` stays in miles until
+//! it is converted with `to`.
+
+use std::cmp::Ordering;
+
+/// Powers of the SI base units m, kg, s, A, K, mol and cd, in that order,
+/// and of the byte for amounts of data.
+pub type Dims = [i32; 8];
+
+#[derive(Clone, Copy)]
+struct Definition {
+    /// The size of the unit in SI base units
+    factor: f64,
+    dims: Dims,
+    /// Where the zero of a temperature scale is, in degrees Celsius.
+    /// Conversions between scales go through Celsius rather than kelvin, so
+    /// `25 degC degF to` is exactly 77: 273.15 has no exact `f64`.
+    zero: f64,
+    /// Whether SI prefixes like `k` and `m` can be put in front
+    prefixed: bool,
+    /// Whether binary prefixes like `Ki` and `Mi` can be put in front
+    binary: bool,
+}
+
+const fn si(factor: f64, dims: Dims) -> Definition {
+    Definition { factor, dims, zero: 0.0, prefixed: true, binary: false }
+}
+
+const fn plain(factor: f64, dims: Dims) -> Definition {
+    Definition { factor, dims, zero: 0.0, prefixed: false, binary: false }
+}
+
+const fn scale(factor: f64, zero: f64, prefixed: bool) -> Definition {
+    Definition { factor, dims: TEMPERATURE, zero, prefixed, binary: false }
+}
+
+const fn data(factor: f64) -> Definition {
+    Definition { factor, dims: INFORMATION, zero: 0.0, prefixed: true, binary: true }
+}
+
+const LENGTH: Dims = [1, 0, 0, 0, 0, 0, 0, 0];
+const AREA: Dims = [2, 0, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dims = [3, 0, 0, 0, 0, 0, 0, 0];
+const MASS: Dims = [0, 1, 0, 0, 0, 0, 0, 0];
+const TIME: Dims = [0, 0, 1, 0, 0, 0, 0, 0];
+const SPEED: Dims = [1, 0, -1, 0, 0, 0, 0, 0];
+const TEMPERATURE: Dims = [0, 0, 0, 0, 1, 0, 0, 0];
+const PRESSURE: Dims = [-1, 1, -2, 0, 0, 0, 0, 0];
+const ENERGY: Dims = [2, 1, -2, 0, 0, 0, 0, 0];
+const POWER: Dims = [2, 1, -3, 0, 0, 0, 0, 0];
+pub const INFORMATION: Dims = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// The size of a degree Fahrenheit or Rankine in kelvin.
+const RANKINE: f64 = 5.0 / 9.0;
+const ABSOLUTE_ZERO: f64 = -273.15;
+
+/// Every unit by its symbol. The mass unit that takes prefixes is the gram,
+/// so `kg` is read as a prefixed `g` like any other.
+const UNITS: phf::Map<&'static str, Definition> = phf::phf_map!{
+    "m" => si(1.0, LENGTH),
+    "g" => si(1e-3, MASS),
+    "s" => si(1.0, TIME),
+    "A" => si(1.0, [0, 0, 0, 1, 0, 0, 0, 0]),
+    "K" => scale(1.0, ABSOLUTE_ZERO, true),
+    "mol" => si(1.0, [0, 0, 0, 0, 0, 1, 0, 0]),
+    "cd" => si(1.0, [0, 0, 0, 0, 0, 0, 1, 0]),
+    "Hz" => si(1.0, [0, 0, -1, 0, 0, 0, 0, 0]),
+    "N" => si(1.0, [1, 1, -2, 0, 0, 0, 0, 0]),
+    "Pa" => si(1.0, PRESSURE),
+    "J" => si(1.0, ENERGY),
+    "W" => si(1.0, POWER),
+    "C" => si(1.0, [0, 0, 1, 1, 0, 0, 0, 0]),
+    "V" => si(1.0, [2, 1, -3, -1, 0, 0, 0, 0]),
+    "ohm" => si(1.0, [2, 1, -3, -2, 0, 0, 0, 0]),
+    "F" => si(1.0, [-2, -1, 4, 2, 0, 0, 0, 0]),
+    "Wb" => si(1.0, [2, 1, -2, -1, 0, 0, 0, 0]),
+    "T" => si(1.0, [0, 1, -2, -1, 0, 0, 0, 0]),
+    "H" => si(1.0, [2, 1, -2, -2, 0, 0, 0, 0]),
+    "L" => si(1e-3, VOLUME),
+    "l" => si(1e-3, VOLUME),
+    "eV" => si(1.602_176_634e-19, ENERGY),
+    "Wh" => si(3600.0, ENERGY),
+    "cal" => si(4.184, ENERGY),
+    "bar" => si(1e5, PRESSURE),
+    "in" => plain(0.0254, LENGTH),
+    "ft" => plain(0.3048, LENGTH),
+    "yd" => plain(0.9144, LENGTH),
+    "mi" => plain(1609.344, LENGTH),
+    "mile" => plain(1609.344, LENGTH),
+    "nmi" => plain(1852.0, LENGTH),
+    "au" => plain(149_597_870_700.0, LENGTH),
+    "ly" => plain(9_460_730_472_580_800.0, LENGTH),
+    "pc" => plain(3.085_677_581_491_367e16, LENGTH),
+    "ha" => plain(1e4, AREA),
+    "acre" => plain(4_046.856_422_4, AREA),
+    "gal" => plain(3.785_411_784e-3, VOLUME),
+    "t" => plain(1e3, MASS),
+    "lb" => plain(0.453_592_37, MASS),
+    "oz" => plain(0.028_349_523_125, MASS),
+    "minute" => plain(60.0, TIME),
+    "h" => plain(3600.0, TIME),
+    "day" => plain(86_400.0, TIME),
+    "week" => plain(604_800.0, TIME),
+    "yr" => plain(31_557_600.0, TIME),
+    "mph" => plain(0.447_04, SPEED),
+    "kn" => plain(1852.0 / 3600.0, SPEED),
+    "atm" => plain(101_325.0, PRESSURE),
+    "psi" => plain(6_894.757_293_168_361, PRESSURE),
+    "mmHg" => plain(133.322_387_415, PRESSURE),
+    "hp" => plain(745.699_871_582_270_2, POWER),
+    "degC" => scale(1.0, 0.0, false),
+    "degF" => scale(RANKINE, -32.0 * RANKINE, false),
+    "degR" => scale(RANKINE, ABSOLUTE_ZERO, false),
+    "B" => data(1.0),
+    "bit" => data(0.125),
+};
+
+const PREFIXES: [(&str, f64); 20] = [
+    ("Q", 1e30), ("R", 1e27), ("Y", 1e24), ("Z", 1e21), ("E", 1e18), ("P", 1e15),
+    ("T", 1e12), ("G", 1e9), ("M", 1e6), ("k", 1e3), ("h", 1e2), ("da", 1e1),
+    ("d", 1e-1), ("c", 1e-2), ("m", 1e-3), ("u", 1e-6), ("n", 1e-9), ("p", 1e-12),
+    ("f", 1e-15), ("a", 1e-18),
+];
+
+/// The IEC prefixes for powers of 1024, as in `KiB` and `GiB`.
+const BINARY_PREFIXES: [(&str, f64); 8] = [
+    ("Ki", 1024.0), ("Mi", 1_048_576.0), ("Gi", 1_073_741_824.0), ("Ti", 1_099_511_627_776.0),
+    ("Pi", 1_125_899_906_842_624.0), ("Ei", 1_152_921_504_606_846_976.0),
+    ("Zi", 1_180_591_620_717_411_303_424.0), ("Yi", 1_208_925_819_614_629_174_706_176.0),
+];
+
+/// Looks up a unit symbol, with an SI or binary prefix if the unit takes
+/// one. A symbol of its own wins over a prefixed reading, so `cd` is the
+/// candela.
+fn definition(symbol: &str) -> Option<Definition> {
+    if let Some(definition) = UNITS.get(symbol) {
+        return Some(*definition);
+    }
+    let si = PREFIXES.iter().map(|prefix| (prefix, false));
+    let binary = BINARY_PREFIXES.iter().map(|prefix| (prefix, true));
+    si.chain(binary).find_map(|((prefix, size), binary)| {
+        let unit = UNITS.get(symbol.strip_prefix(prefix)?)
+            .filter(|unit| if binary { unit.binary } else { unit.prefixed })?;
+        Some(Definition { factor: unit.factor * size, ..*unit })
+    })
+}
+
+/// Bytes with the largest prefix that leaves `bytes` at 1 or more, from
+/// `kB` up in steps of 1000, or from `KiB` up in steps of 1024 if `binary`.
+/// Plain bytes when no prefix does.
+pub fn byte_unit(bytes: f64, binary: bool) -> Unit {
+    let prefixes = match binary {
+        true => BINARY_PREFIXES.as_slice(),
+        false => &PREFIXES[..10],
+    };
+    let prefix = prefixes.iter().filter(|&&(_, size)| bytes.abs() >= size)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or("", |(prefix, _)| prefix);
+    Unit::parse(&format!("{prefix}B")).unwrap()
+}
+
+/// A product of named units to integer powers, none of them zero.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Unit {
+    /// In the order the symbols were first written
+    terms: Vec<(String, i32)>,
+}
+
+impl Unit {
+    /// Parses symbols with optional integer powers, joined by `*` and `/`,
+    /// as in `kg*m/s^2`. Each `/` divides by the one symbol after it.
+    pub fn parse(text: &str) -> Option<Unit> {
+        let mut unit = Unit { terms: Vec::new() };
+        let mut sign = 1;
+        let mut rest = text;
+        loop {
+            let end = rest.find(['*', '/']).unwrap_or(rest.len());
+            let (symbol, power) = match rest[..end].split_once('^') {
+                Some((symbol, power)) => (symbol, power.parse::<i32>().ok()?),
+                None => (&rest[..end], 1),
+            };
+            definition(symbol)?;
+            unit.push(symbol, sign * power);
+            let Some(separator) = rest[end..].chars().next() else {
+                break;
+            };
+            sign = if separator == '/' { -1 } else { 1 };
+            rest = &rest[end + 1..];
+        }
+        (!unit.terms.is_empty()).then_some(unit)
+    }
+
+    /// The unit of plain numbers.
+    pub fn one() -> Unit {
+        Unit { terms: Vec::new() }
+    }
+
+    fn push(&mut self, symbol: &str, power: i32) {
+        match self.terms.iter().position(|(name, _)| name == symbol) {
+            Some(index) => self.terms[index].1 += power,
+            None => self.terms.push((symbol.to_string(), power)),
+        }
+        self.terms.retain(|&(_, power)| power != 0);
+    }
+
+    fn definitions(&self) -> impl Iterator<Item = (Definition, i32)> + '_ {
+        self.terms.iter().map(|(symbol, power)| (definition(symbol).unwrap(), *power))
+    }
+
+    /// The size of the unit in SI base units.
+    pub fn factor(&self) -> f64 {
+        self.definitions().map(|(unit, power)| unit.factor.powi(power)).product()
+    }
+
+    pub fn dims(&self) -> Dims {
+        let mut dims = [0; 8];
+        for (unit, power) in self.definitions() {
+            for (dim, unit_dim) in dims.iter_mut().zip(unit.dims) {
+                *dim += unit_dim * power;
+            }
+        }
+        dims
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dims() == [0; 8]
+    }
+
+    /// The zero of a temperature scale, which only counts when the scale
+    /// stands alone: `degC/s` is a rate like `K/s`.
+    fn zero(&self) -> f64 {
+        match self.terms.as_slice() {
+            [(symbol, 1)] => definition(symbol).unwrap().zero,
+            _ => 0.0,
+        }
+    }
+
+    pub fn mul(&self, rhs: &Unit) -> Unit {
+        let mut unit = self.clone();
+        for (symbol, power) in &rhs.terms {
+            unit.push(symbol, *power);
+        }
+        unit
+    }
+
+    pub fn powi(&self, n: i32) -> Unit {
+        let terms = self.terms.iter().map(|(symbol, power)| (symbol.clone(), power * n));
+        Unit { terms: terms.filter(|&(_, power)| power != 0).collect() }
+    }
+
+    /// The `n`th root, when every power divides by `n`.
+    pub fn root(&self, n: i32) -> Option<Unit> {
+        if self.terms.iter().any(|(_, power)| power % n != 0) {
+            return None;
+        }
+        Some(Unit { terms: self.terms.iter().map(|(symbol, power)| (symbol.clone(), power / n)).collect() })
+    }
+}
+
+/// Symbols with positive powers first, then `/` and each one with a negative
+/// power. With no positive powers there is nothing to divide, so all of them
+/// are written with their powers, as in `s^-1`. `Unit::parse` reads either
+/// form back.
+impl std::fmt::Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let term = |symbol: &str, power: i32| match power {
+            1 => symbol.to_string(),
+            _ => format!("{symbol}^{power}"),
+        };
+        if self.terms.iter().all(|&(_, power)| power < 0) {
+            let terms = self.terms.iter().map(|(symbol, power)| term(symbol, *power)).collect::<Vec<_>>();
+            return write!(f, "{}", terms.join("*"));
+        }
+        let above = self.terms.iter().filter(|&&(_, power)| power > 0).map(|(symbol, power)| term(symbol, *power));
+        write!(f, "{}", above.collect::<Vec<_>>().join("*"))?;
+        for (symbol, power) in self.terms.iter().filter(|&&(_, power)| power < 0) {
+            write!(f, "/{}", term(symbol, -power))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: Unit) -> Quantity {
+        Quantity { value, unit }
+    }
+
+    /// The value as a number of `unit`s, or `None` if the dimensions differ.
+    /// The scales are compared as sizes, so `1 degC` is one kelvin.
+    pub fn value_in(&self, unit: &Unit) -> Option<f64> {
+        (self.unit.dims() == unit.dims()).then(|| self.value * self.unit.factor() / unit.factor())
+    }
+
+    /// Like `value_in`, but a temperature is read on the scale of `unit`,
+    /// so `25 degC` is 77 in `degF`.
+    pub fn convert(&self, unit: &Unit) -> Option<Quantity> {
+        if self.unit.dims() != unit.dims() {
+            return None;
+        }
+        let shifted = self.value * self.unit.factor() + self.unit.zero();
+        Some(Quantity::new((shifted - unit.zero()) / unit.factor(), unit.clone()))
+    }
+
+    /// `None` if the dimensions differ. The sum is in the unit of `self`.
+    pub fn add(&self, rhs: &Quantity) -> Option<Quantity> {
+        Some(Quantity::new(self.value + rhs.value_in(&self.unit)?, self.unit.clone()))
+    }
+
+    pub fn sub(&self, rhs: &Quantity) -> Option<Quantity> {
+        Some(Quantity::new(self.value - rhs.value_in(&self.unit)?, self.unit.clone()))
+    }
+
+    pub fn mul(&self, rhs: &Quantity) -> Quantity {
+        Quantity::new(self.value * rhs.value, self.unit.mul(&rhs.unit))
+    }
+
+    pub fn div(&self, rhs: &Quantity) -> Quantity {
+        Quantity::new(self.value / rhs.value, self.unit.mul(&rhs.unit.powi(-1)))
+    }
+
+    /// `None` if the dimensions differ, and `Some(None)` if either value is NaN.
+    pub fn compare(&self, rhs: &Quantity) -> Option<Option<Ordering>> {
+        Some(self.value.partial_cmp(&rhs.value_in(&self.unit)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantity(value: f64, unit: &str) -> Quantity {
+        Quantity::new(value, Unit::parse(unit).unwrap())
+    }
+
+    #[test]
+    fn parses_prefixes_powers_and_division() {
+        let unit = Unit::parse("kg*m/s^2").unwrap();
+        assert_eq!(unit.dims(), [1, 1, -2, 0, 0, 0, 0, 0]);
+        assert_eq!(unit.factor(), 1.0);
+        assert_eq!(unit.to_string(), "kg*m/s^2");
+        assert_eq!(Unit::parse("km").unwrap().factor(), 1e3);
+        assert_eq!(Unit::parse("GiB").unwrap().factor(), 1_073_741_824.0);
+        assert_eq!(Unit::parse("s^-1").unwrap().to_string(), "s^-1");
+    }
+
+    #[test]
+    fn rejects_unknown_symbols() {
+        assert!(Unit::parse("").is_none());
+        assert!(Unit::parse("kmx").is_none());
+        assert!(Unit::parse("m/").is_none());
+        assert!(Unit::parse("m^x").is_none());
+        // Binary prefixes are only for bytes and bits, and `mi` has no prefix
+        assert!(Unit::parse("Kim").is_none());
+        assert!(Unit::parse("kmi").is_none());
+    }
+
+    #[test]
+    fn converts_between_units() {
+        assert_eq!(quantity(1.0, "mile").value_in(&Unit::parse("m").unwrap()), Some(1609.344));
+        assert_eq!(quantity(2.0, "KiB").value_in(&Unit::parse("B").unwrap()), Some(2048.0));
+        assert_eq!(quantity(1.0, "m").value_in(&Unit::parse("s").unwrap()), None);
+    }
+
+    #[test]
+    fn converts_temperature_scales() {
+        let fahrenheit = quantity(25.0, "degC").convert(&Unit::parse("degF").unwrap()).unwrap();
+        assert_eq!(fahrenheit.value, 77.0);
+        let kelvin = quantity(0.0, "degC").convert(&Unit::parse("K").unwrap()).unwrap();
+        assert_eq!(kelvin.value, 273.15);
+        // A difference of one degree is a kelvin, without the offset
+        assert_eq!(quantity(1.0, "degC").value_in(&Unit::parse("K").unwrap()), Some(1.0));
+    }
+
+    #[test]
+    fn arithmetic_keeps_the_lower_unit() {
+        let sum = quantity(1.0, "km").add(&quantity(300.0, "m")).unwrap();
+        assert_eq!((sum.value, sum.unit.to_string()), (1.3, "km".to_string()));
+        assert!(quantity(1.0, "km").add(&quantity(1.0, "s")).is_none());
+        assert_eq!(quantity(1.0, "km").compare(&quantity(999.0, "m")), Some(Some(Ordering::Greater)));
+        assert_eq!(quantity(1.0, "m").compare(&quantity(1.0, "kg")), None);
+        let speed = quantity(6.0, "m").div(&quantity(3.0, "s"));
+        assert_eq!((speed.value, speed.unit.to_string()), (2.0, "m/s".to_string()));
+    }
+
+    #[test]
+    fn picks_byte_prefixes() {
+        assert_eq!(byte_unit(1536.0, true).to_string(), "KiB");
+        assert_eq!(byte_unit(1536.0, false).to_string(), "kB");
+        assert_eq!(byte_unit(500.0, false).to_string(), "B");
+        assert_eq!(byte_unit(3e12, false).to_string(), "TB");
+    }
+}